@@ -0,0 +1,32 @@
+#![no_main]
+
+use chipeite::{
+    cpu::{error::Error, Cpu},
+    memory::{Memory, FOUR_K, PROGRAM_START},
+};
+use libfuzzer_sys::fuzz_target;
+
+const MAX_ROM: usize = FOUR_K - 0x200;
+const MAX_STEPS: usize = 10_000;
+
+// Arbitrary bytes as a ROM, executed until the interpreter reports an error.
+fuzz_target!(|rom: &[u8]| {
+    let rom = &rom[..rom.len().min(MAX_ROM)];
+    let mut memory = Memory::new();
+    memory.load(PROGRAM_START, rom).unwrap();
+    let mut cpu = Cpu::seeded(0);
+    for _ in 0..MAX_STEPS {
+        let pc = cpu.program_counter();
+        match cpu.execute(&mut memory) {
+            Ok(()) => {}
+            Err(e) => {
+                assert_eq!(e.pc(), pc, "error does not point at the instruction");
+                assert!(!e.to_string().is_empty());
+                if let Error::OutOfBounds { addr, .. } = e {
+                    assert!(addr >= FOUR_K, "{e} but the address is inside ram");
+                }
+                break;
+            }
+        }
+    }
+});
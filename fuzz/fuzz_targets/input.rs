@@ -0,0 +1,48 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use chipeite::{memory::FOUR_K, Chip8, Config, Key};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+struct Event {
+    frames: u8,
+    key: u8,
+    pressed: bool,
+}
+
+#[derive(Debug, Arbitrary)]
+struct Run {
+    rom: Vec<u8>,
+    instructions_per_frame: u8,
+    events: Vec<Event>,
+}
+
+// A ROM driven frame by frame with key presses interleaved.
+fuzz_target!(|run: Run| {
+    let rom = &run.rom[..run.rom.len().min(FOUR_K - 0x200)];
+    let mut chip8 = Chip8::new(
+        rom,
+        Config {
+            instructions_per_frame: run.instructions_per_frame as usize,
+            rng_seed: Some(0),
+            ..Config::default()
+        },
+    )
+    .unwrap();
+    for event in run.events {
+        let key = Key::from(event.key & 0xF);
+        if event.pressed {
+            chip8.press_key(key);
+        } else {
+            chip8.release_key(key);
+        }
+        for _ in 0..event.frames {
+            if chip8.run_frame().is_err() {
+                return;
+            }
+        }
+        let snapshot = chip8.snapshot();
+        assert_eq!(snapshot.ram.len(), FOUR_K);
+    }
+});
@@ -0,0 +1,562 @@
+pub mod snapshot;
+
+use crate::{
+    cpu::{error::Error, quirks::Quirks, variant::Variant, Cpu},
+    memory::{
+        access::Access,
+        bus::Mapping,
+        color_map::ColorMap,
+        error::LoadError,
+        framebuffer::FrameBuffer,
+        key_state::{Key, KeyState},
+        registers::Registers,
+        Memory, FOUR_K, PROGRAM_START,
+    },
+    trace::{Record, Tracer},
+};
+
+use self::snapshot::Snapshot;
+
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub instructions_per_frame: usize,
+    // Fixed seed for Cxkk, so headless runs are reproducible.
+    pub rng_seed: Option<u64>,
+    pub quirks: Quirks,
+    pub variant: Variant,
+    // Where the ROM goes, 0x600 for ETI-660 programs.
+    pub load_address: u16,
+    // Defaults to the load address.
+    pub entry_point: Option<u16>,
+    // Extra data loaded after the ROM, later segments overwrite earlier ones.
+    pub segments: Vec<Segment>,
+    pub mapping: Mapping,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            rng_seed: None,
+            quirks: Quirks::default(),
+            variant: Variant::default(),
+            load_address: PROGRAM_START,
+            entry_point: None,
+            segments: Vec::new(),
+            mapping: Mapping::default(),
+        }
+    }
+}
+
+pub struct Chip8 {
+    cpu: Cpu,
+    memory: Memory,
+    config: Config,
+    // The image `reset` starts over from.
+    rom: Vec<u8>,
+    cycles: u64,
+    frames: u64,
+    // Instructions run so far in the current frame.
+    frame_steps: usize,
+    tracer: Option<Tracer>,
+}
+
+impl Chip8 {
+    pub fn new(rom: &[u8], config: Config) -> Result<Self, LoadError> {
+        let mut memory = Memory::new();
+        memory.mapping = config.mapping;
+        let (width, height) = config.variant.display_size();
+        memory.framebuffer = FrameBuffer::new(width, height);
+        load_images(&mut memory, &config, rom)?;
+        let entry_point = config.entry_point.unwrap_or(config.load_address);
+        if entry_point as usize + 1 >= FOUR_K {
+            return Err(LoadError::EntryPoint { addr: entry_point });
+        }
+
+        let mut cpu = config.rng_seed.map_or_else(Cpu::new, Cpu::seeded);
+        cpu.set_quirks(config.quirks);
+        cpu.set_variant(config.variant);
+        cpu.set_program_counter(entry_point);
+        Ok(Self {
+            cpu,
+            memory,
+            config,
+            rom: rom.to_vec(),
+            cycles: 0,
+            frames: 0,
+            frame_steps: 0,
+            tracer: None,
+        })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    // Back to how `new` left it, from the same ROM image. The tracer, the
+    // observers and the instructions per frame carry over.
+    pub fn reset(&mut self) {
+        let rom = self.rom.clone();
+        self.reload(&rom).expect("the rom loaded before");
+    }
+
+    // Like `reset`, from a different ROM image.
+    pub fn reload(&mut self, rom: &[u8]) -> Result<(), LoadError> {
+        let fresh = Self::new(rom, self.config.clone())?;
+        self.cpu = fresh.cpu;
+        self.memory = Memory {
+            observers: std::mem::take(&mut self.memory.observers),
+            ..fresh.memory
+        };
+        self.rom = fresh.rom;
+        self.cycles = 0;
+        self.frames = 0;
+        self.frame_steps = 0;
+        Ok(())
+    }
+
+    // Takes effect from the next instruction.
+    pub fn set_instructions_per_frame(&mut self, n: usize) {
+        self.config.instructions_per_frame = n;
+    }
+
+    pub fn step(&mut self) -> Result<(), Error> {
+        self.step_observed(None)
+    }
+
+    fn step_observed(&mut self, observe: Option<&mut dyn FnMut(&Record)>) -> Result<(), Error> {
+        let traced = self
+            .tracer
+            .as_ref()
+            .is_some_and(|tracer| tracer.wants(self.cpu.program_counter(), self.frames));
+        if traced || observe.is_some() {
+            if let Some(record) = self.record() {
+                if let Some(tracer) = self.tracer.as_mut().filter(|_| traced) {
+                    tracer.record(&record);
+                }
+                if let Some(observe) = observe {
+                    observe(&record);
+                }
+            }
+        }
+        self.memory.observers.clear_log();
+        self.cycles += 1;
+        self.cpu.execute(&mut self.memory)
+    }
+
+    // The state the next instruction will run in, None if the pc is at the
+    // very end of ram.
+    pub fn record(&self) -> Option<Record> {
+        Record::capture(self.cycles, self.frames, &self.cpu, &self.memory)
+    }
+
+    // Runs what's left of the current 60Hz frame and then ticks the timers.
+    pub fn run_frame(&mut self) -> Result<(), Error> {
+        self.run_frame_observed(None)
+    }
+
+    // Like `run_frame`, handing `observe` a record before every instruction.
+    pub fn run_frame_with(&mut self, observe: &mut dyn FnMut(&Record)) -> Result<(), Error> {
+        self.run_frame_observed(Some(observe))
+    }
+
+    fn run_frame_observed(
+        &mut self,
+        mut observe: Option<&mut dyn FnMut(&Record)>,
+    ) -> Result<(), Error> {
+        while self.frame_steps < self.config.instructions_per_frame {
+            let observe = observe
+                .as_mut()
+                .map(|f| &mut **f as &mut dyn FnMut(&Record));
+            if self.step_in_frame(observe)? {
+                return Ok(());
+            }
+        }
+        self.end_frame();
+        Ok(())
+    }
+
+    // Runs one instruction as part of the current frame, ending the frame
+    // if that was its last one. True when it did, for debuggers stepping
+    // through frames.
+    pub fn step_in_frame(
+        &mut self,
+        observe: Option<&mut dyn FnMut(&Record)>,
+    ) -> Result<bool, Error> {
+        self.step_observed(observe)?;
+        self.frame_steps += 1;
+        let done =
+            self.cpu.take_vblank_wait() || self.frame_steps >= self.config.instructions_per_frame;
+        if done {
+            self.end_frame();
+        }
+        Ok(done)
+    }
+
+    fn end_frame(&mut self) {
+        self.cpu.tick_timers();
+        self.frames += 1;
+        self.frame_steps = 0;
+    }
+
+    // Instructions executed so far.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // Frames completed so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    pub fn press_key(&mut self, key: Key) {
+        self.memory.key_state.set(key);
+    }
+
+    pub fn release_key(&mut self, key: Key) {
+        self.memory.key_state.unset(key);
+    }
+
+    pub fn key_state(&self) -> &KeyState {
+        &self.memory.key_state
+    }
+
+    pub fn framebuffer(&self) -> &FrameBuffer {
+        &self.memory.framebuffer
+    }
+
+    // Only CHIP-8X programs have colours.
+    pub fn colors(&self) -> Option<&ColorMap> {
+        (self.config.variant == Variant::Chip8X).then_some(&self.memory.colors)
+    }
+
+    pub fn press_second_key(&mut self, key: Key) {
+        self.memory.second_key_state.set(key);
+    }
+
+    pub fn release_second_key(&mut self, key: Key) {
+        self.memory.second_key_state.unset(key);
+    }
+
+    // Last value written by FxF8.
+    pub fn port_output(&self) -> u8 {
+        self.memory.port_output
+    }
+
+    // Picked up by the next FxFB.
+    pub fn set_port_input(&mut self, value: u8) {
+        self.memory.port_input = Some(value);
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.memory.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.memory.registers
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        &self.memory.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.memory.ram
+    }
+
+    // Keeps the ram reads and writes of each instruction for `accesses`.
+    pub fn track_accesses(&mut self, on: bool) {
+        self.memory.observers.set_logging(on);
+    }
+
+    // What the last instruction read and wrote, while tracking.
+    pub fn accesses(&self) -> &[Access] {
+        self.memory.observers.log()
+    }
+
+    // Calls `observer` with every byte the interpreter fetches, reads or
+    // writes from here on. The id is for `unsubscribe`.
+    pub fn subscribe(&mut self, observer: impl FnMut(&Access) + 'static) -> u32 {
+        self.memory.observers.subscribe(Box::new(observer))
+    }
+
+    // False if there was no such observer.
+    pub fn unsubscribe(&mut self, id: u32) -> bool {
+        self.memory.observers.unsubscribe(id)
+    }
+
+    // The address and length of the sprite the last Dxyn drew.
+    pub fn last_sprite(&self) -> Option<(u16, usize)> {
+        self.cpu.last_sprite()
+    }
+
+    // Return addresses, innermost last.
+    pub fn stack(&self) -> &[u16] {
+        self.memory.stack.entries()
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.cpu.program_counter()
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.cpu.set_program_counter(pc);
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.cpu.delay_timer()
+    }
+
+    pub fn set_delay_timer(&mut self, counter: u8) {
+        let sound_timer = self.cpu.sound_timer();
+        self.cpu.load_timers(counter, sound_timer);
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.cpu.sound_timer()
+    }
+
+    pub fn set_sound_timer(&mut self, counter: u8) {
+        let delay_timer = self.cpu.delay_timer();
+        self.cpu.load_timers(delay_timer, counter);
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        self.cpu.sound_timer() > 0
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::capture(&self.cpu, &self.memory)
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        let (mut cpu, memory) = snapshot.rebuild();
+        cpu.set_quirks(self.config.quirks);
+        cpu.set_variant(self.config.variant);
+        self.cpu = cpu;
+        self.memory = Memory {
+            mapping: self.config.mapping,
+            observers: std::mem::take(&mut self.memory.observers),
+            ..memory
+        };
+    }
+
+    // Restores `snapshot` with the current ROM image put back over it, so a
+    // state saved with an older build of the program carries on with this
+    // one.
+    pub fn rebase(&mut self, snapshot: &Snapshot) {
+        self.restore(snapshot);
+        load_images(&mut self.memory, &self.config, &self.rom).expect("the rom loaded before");
+    }
+}
+
+// The ROM and the data segments, as the program expects to find them.
+fn load_images(memory: &mut Memory, config: &Config, rom: &[u8]) -> Result<(), LoadError> {
+    memory.load(config.load_address, rom)?;
+    // 0x260 to 0x2BF is machine code setting up the hi-res display, skip it
+    // like the other emulators do and go straight to the program.
+    let start = PROGRAM_START as usize;
+    if config.variant == Variant::HiRes && memory.ram[start..start + 2] == [0x12, 0x60] {
+        memory.ram[start + 1] = 0xC0;
+    }
+    for segment in &config.segments {
+        memory.load(segment.addr, &segment.bytes)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::access::AccessKind;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn snapshot_round_trips() {
+        let rom = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/roms/pong.ch8")).unwrap();
+        let mut chip8 = Chip8::new(&rom, Config::default()).unwrap();
+        for _ in 0..30 {
+            chip8.run_frame().unwrap();
+        }
+        let snapshot = chip8.snapshot();
+        let mut other = Chip8::new(&[], Config::default()).unwrap();
+        other.restore(&snapshot);
+        assert_eq!(other.snapshot(), snapshot);
+        assert!(other.framebuffer() == chip8.framebuffer());
+        assert_eq!(other.pc(), chip8.pc());
+    }
+
+    #[test]
+    fn oversize_roms_are_rejected() {
+        let rom = vec![0; FOUR_K - 0x200 + 1];
+        assert_eq!(
+            Chip8::new(&rom, Config::default()).err(),
+            Some(LoadError::DoesNotFit {
+                addr: 0x200,
+                len: rom.len()
+            })
+        );
+        assert!(Chip8::new(&rom[1..], Config::default()).is_ok());
+    }
+
+    #[test]
+    fn roms_and_segments_load_at_their_address() {
+        let config = Config {
+            load_address: 0x600,
+            segments: vec![Segment {
+                addr: 0x300,
+                bytes: vec![0xAB, 0xCD],
+            }],
+            ..Config::default()
+        };
+        let mut chip8 = Chip8::new(&[0xA3, 0x00], config).unwrap();
+        assert_eq!(chip8.pc(), 0x600);
+        assert_eq!(&chip8.ram()[0x300..0x302], &[0xAB, 0xCD]);
+        chip8.step().unwrap();
+        assert_eq!(chip8.registers().image, 0x300);
+    }
+
+    #[test]
+    fn entry_point_must_be_in_ram() {
+        let config = Config {
+            entry_point: Some(0xFFF),
+            ..Config::default()
+        };
+        assert_eq!(
+            Chip8::new(&[], config).err(),
+            Some(LoadError::EntryPoint { addr: 0xFFF })
+        );
+    }
+
+    #[test]
+    fn hires_programs_skip_the_display_setup() {
+        let config = Config {
+            variant: Variant::HiRes,
+            ..Config::default()
+        };
+        let mut chip8 = Chip8::new(&[0x12, 0x60], config).unwrap();
+        assert_eq!(chip8.framebuffer().height(), 64);
+        chip8.step().unwrap();
+        assert_eq!(chip8.pc(), 0x2C0);
+    }
+
+    #[test]
+    fn observers_see_every_access() {
+        // LD I, #300; LD [I], V1
+        let rom = [0xA3, 0x00, 0xF1, 0x55];
+        let mut chip8 = Chip8::new(&rom, Config::default()).unwrap();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = seen.clone();
+        let id = chip8.subscribe(move |access| log.borrow_mut().push(*access));
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        let kinds: Vec<_> = seen.borrow().iter().map(|a| (a.kind, a.addr)).collect();
+        assert_eq!(
+            kinds,
+            [
+                (AccessKind::Fetch, 0x200),
+                (AccessKind::Fetch, 0x201),
+                (AccessKind::Fetch, 0x202),
+                (AccessKind::Fetch, 0x203),
+                (AccessKind::Write, 0x300),
+                (AccessKind::Write, 0x301),
+            ]
+        );
+        assert!(chip8.unsubscribe(id));
+        assert!(!chip8.unsubscribe(id));
+    }
+
+    #[test]
+    fn reset_starts_the_rom_over() {
+        // LD V1, #42; LD I, #300; LD [I], V1; LD DT, V1; JP #208
+        let rom = [0x61, 0x42, 0xA3, 0x00, 0xF1, 0x55, 0xF1, 0x15, 0x12, 0x08];
+        let mut chip8 = Chip8::new(&rom, Config::default()).unwrap();
+        let writes = Rc::new(RefCell::new(0));
+        let count = writes.clone();
+        chip8.subscribe(move |access| {
+            if access.kind == AccessKind::Write {
+                *count.borrow_mut() += 1;
+            }
+        });
+        chip8.run_frame().unwrap();
+        chip8.press_key(Key::A);
+        chip8.ram_mut()[0x201] = 0x99;
+        assert_eq!(chip8.ram()[0x301], 0x42);
+
+        chip8.reset();
+        assert_eq!(chip8.pc(), 0x200);
+        assert_eq!((chip8.ram()[0x201], chip8.ram()[0x301]), (0x42, 0));
+        assert_eq!(chip8.registers().values()[1], 0);
+        assert_eq!((chip8.delay_timer(), chip8.frames()), (0, 0));
+        assert!(!chip8.key_state().is_set(Key::A));
+
+        chip8.run_frame().unwrap();
+        assert_eq!(*writes.borrow(), 4);
+
+        chip8.reload(&[0x60, 0x07]).unwrap();
+        assert_eq!(chip8.ram()[0x201], 0x07);
+        assert_eq!(chip8.ram()[0x202], 0);
+        assert_eq!(
+            chip8.reload(&[0; FOUR_K]).err(),
+            Some(LoadError::DoesNotFit {
+                addr: 0x200,
+                len: FOUR_K
+            })
+        );
+    }
+
+    #[test]
+    fn rebase_keeps_the_new_rom() {
+        // LD V1, #42; LD I, #300; LD [I], V1; JP #206
+        let old = [0x61, 0x42, 0xA3, 0x00, 0xF1, 0x55, 0x12, 0x06];
+        let mut chip8 = Chip8::new(&old, Config::default()).unwrap();
+        chip8.run_frame().unwrap();
+        let state = chip8.snapshot();
+
+        let new = [0x61, 0x43, 0xA3, 0x00, 0xF1, 0x55, 0x12, 0x06];
+        chip8.reload(&new).unwrap();
+        chip8.rebase(&state);
+        assert_eq!(chip8.pc(), 0x206);
+        assert_eq!(chip8.registers().values()[1], 0x42);
+        assert_eq!((chip8.ram()[0x201], chip8.ram()[0x301]), (0x43, 0x42));
+
+        // Hi-res programs keep skipping the display setup.
+        let config = Config {
+            variant: Variant::HiRes,
+            ..Config::default()
+        };
+        let mut chip8 = Chip8::new(&[0x12, 0x60], config).unwrap();
+        let state = chip8.snapshot();
+        chip8.reload(&[0x12, 0x60, 0x00, 0xE0]).unwrap();
+        chip8.rebase(&state);
+        assert_eq!(&chip8.ram()[0x200..0x204], &[0x12, 0xC0, 0x00, 0xE0]);
+        chip8.step().unwrap();
+        assert_eq!(chip8.pc(), 0x2C0);
+    }
+
+    #[test]
+    fn last_sprite_is_where_dxyn_read() {
+        // LD I, #000; DRW V0, V0, 5
+        let mut chip8 = Chip8::new(&[0xA0, 0x00, 0xD0, 0x05], Config::default()).unwrap();
+        chip8.step().unwrap();
+        assert_eq!(chip8.last_sprite(), None);
+        chip8.step().unwrap();
+        assert_eq!(chip8.last_sprite(), Some((0x000, 5)));
+    }
+}
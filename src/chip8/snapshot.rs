@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cpu::Cpu,
+    memory::{
+        color_map::ColorMap, framebuffer::FrameBuffer, key_state::KeyState, registers::Registers,
+        stack::Stack, Memory,
+    },
+};
+
+// A plain copy of the whole machine state, cheap to clone and serialise.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub pc: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub stack: Vec<u16>,
+    pub keys: u16,
+    pub framebuffer: Vec<u64>,
+    pub ram: Vec<u8>,
+    #[serde(default)]
+    pub colors: ColorMap,
+}
+
+impl Snapshot {
+    pub(crate) fn capture(cpu: &Cpu, memory: &Memory) -> Self {
+        Self {
+            pc: cpu.program_counter(),
+            delay_timer: cpu.delay_timer(),
+            sound_timer: cpu.sound_timer(),
+            v: *memory.registers.values(),
+            i: memory.registers.image,
+            stack: memory.stack.entries().to_vec(),
+            keys: memory.key_state.bits(),
+            framebuffer: memory.framebuffer.rows(),
+            ram: memory.ram.to_vec(),
+            colors: memory.colors.clone(),
+        }
+    }
+
+    pub(crate) fn rebuild(&self) -> (Cpu, Memory) {
+        let cpu = Cpu::with_state(self.pc, self.delay_timer, self.sound_timer);
+        let mut memory = Memory::new();
+        memory
+            .ram
+            .iter_mut()
+            .zip(&self.ram)
+            .for_each(|(dst, src)| *dst = *src);
+        memory.registers = Registers::new(self.v, self.i);
+        memory.stack = Stack::from_entries(&self.stack);
+        memory.key_state = KeyState::from_bits(self.keys);
+        memory.framebuffer = FrameBuffer::from_rows(&self.framebuffer);
+        memory.colors = self.colors.clone();
+        (cpu, memory)
+    }
+}
@@ -0,0 +1,277 @@
+pub mod error;
+
+use std::{collections::BTreeMap, fmt::Write, ops::Range};
+
+use crate::{
+    cpu::disassembler::disassemble,
+    memory::{
+        access::{Access, AccessKind},
+        FOUR_K,
+    },
+    trace::Record,
+    Variant,
+};
+
+use self::error::SymbolError;
+
+// Which bytes of ram were run, counted from trace records like the profiler,
+// and which were read as data or written, from what went over the bus.
+pub struct Coverage {
+    executed: Vec<u64>,
+    read: Vec<bool>,
+    written: Vec<bool>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self {
+            executed: vec![0; FOUR_K],
+            read: vec![false; FOUR_K],
+            written: vec![false; FOUR_K],
+        }
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&mut self, record: &Record) {
+        let pc = record.pc as usize;
+        self.executed[pc] += 1;
+        self.executed[pc + 1] += 1;
+    }
+
+    // For `Chip8::subscribe`. Fetches are already counted by `observe`.
+    pub fn access(&mut self, access: &Access) {
+        let bytes = match access.kind {
+            AccessKind::Fetch => return,
+            AccessKind::Read => &mut self.read,
+            AccessKind::Write => &mut self.written,
+        };
+        if let Some(byte) = bytes.get_mut(access.addr) {
+            *byte = true;
+        }
+    }
+
+    pub fn executed(&self, addr: u16) -> u64 {
+        self.executed[addr as usize]
+    }
+
+    pub fn was_read(&self, addr: u16) -> bool {
+        self.read[addr as usize]
+    }
+
+    pub fn was_written(&self, addr: u16) -> bool {
+        self.written[addr as usize]
+    }
+
+    // `image` disassembled two bytes at a time from `start`, each line with
+    // how often it ran (##### for never) and r/w for data accesses.
+    pub fn listing(&self, image: &[u8], start: u16, variant: Variant) -> String {
+        let mut out = String::new();
+        let mut offset = 0;
+        while offset < image.len() {
+            let addr = start + offset as u16;
+            // instructions can sit on odd addresses too
+            let next = self.executed.get(addr as usize + 1).copied().unwrap_or(0);
+            let len = if self.executed(addr) == 0 && next > 0 {
+                1
+            } else {
+                2.min(image.len() - offset)
+            };
+            let bytes = &image[offset..offset + len];
+            let flags: String = (addr..addr + len as u16)
+                .map(|a| match (self.was_read(a), self.was_written(a)) {
+                    (true, true) => 'x',
+                    (true, false) => 'r',
+                    (false, true) => 'w',
+                    (false, false) => '-',
+                })
+                .collect();
+            let count = match self.executed(addr) {
+                0 => "#####".to_owned(),
+                n => n.to_string(),
+            };
+            let (hex, mnemonic) = match *bytes {
+                [hi, lo] => {
+                    let opcode = u16::from_be_bytes([hi, lo]);
+                    (format!("{opcode:04X}"), disassemble(opcode, variant))
+                }
+                _ => (format!("{:02X}", bytes[0]), format!("DB #{:02X}", bytes[0])),
+            };
+            let _ = writeln!(
+                out,
+                "{count:>10}  {addr:03X}: {hex:<4}  {flags:<2}  {mnemonic}"
+            );
+            offset += len;
+        }
+        out
+    }
+
+    // lcov tracefile for `source`, with addresses as line numbers.
+    pub fn lcov(&self, source: &str, range: Range<u16>) -> String {
+        let lines = range
+            .step_by(2)
+            .map(|addr| (addr as u32, self.executed(addr)))
+            .collect();
+        lcov_record(source, lines)
+    }
+
+    // lcov tracefiles for the assembler source lines in `symbols`. A line
+    // counts as often as the instructions it assembled into ran.
+    pub fn lcov_with_symbols(&self, symbols: &Symbols) -> String {
+        let mut files: BTreeMap<&str, BTreeMap<u32, u64>> = BTreeMap::new();
+        for (&addr, (file, line)) in &symbols.0 {
+            *files.entry(file).or_default().entry(*line).or_default() += self.executed(addr);
+        }
+        files
+            .into_iter()
+            .map(|(file, lines)| lcov_record(file, lines))
+            .collect()
+    }
+}
+
+// Assembler source lines by address, one `0x200 game.8o:12` per line.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Symbols(BTreeMap<u16, (String, u32)>);
+
+impl Symbols {
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        let mut symbols = BTreeMap::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = SymbolError { line: n + 1 };
+            let (addr, location) = line.split_once(char::is_whitespace).ok_or(error.clone())?;
+            let (file, source_line) = location.trim().rsplit_once(':').ok_or(error.clone())?;
+            let addr = match addr.strip_prefix("0x").or_else(|| addr.strip_prefix("0X")) {
+                Some(hex) => u16::from_str_radix(hex, 16),
+                None => addr.parse(),
+            }
+            .map_err(|_| error.clone())?;
+            let source_line = source_line.parse().map_err(|_| error)?;
+            symbols.insert(addr, (file.to_owned(), source_line));
+        }
+        Ok(Self(symbols))
+    }
+
+    // The source line of the instruction at `addr`, or of the closest one
+    // before it.
+    pub fn location(&self, addr: u16) -> Option<(&str, u32)> {
+        let (_, (file, line)) = self.0.range(..=addr).next_back()?;
+        Some((file, *line))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str, u32)> {
+        self.0
+            .iter()
+            .map(|(&addr, (file, line))| (addr, file.as_str(), *line))
+    }
+}
+
+fn lcov_record(source: &str, lines: BTreeMap<u32, u64>) -> String {
+    let mut out = format!("TN:\nSF:{source}\n");
+    for (line, count) in &lines {
+        let _ = writeln!(out, "DA:{line},{count}");
+    }
+    let hit = lines.values().filter(|&&count| count > 0).count();
+    let _ = writeln!(out, "LF:{}\nLH:{hit}\nend_of_record", lines.len());
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{memory::bus::Mapping, Chip8, Config};
+
+    #[rustfmt::skip]
+    const ROM: &[u8] = &[
+        0xA2, 0x0A, // 200 LD I, 20A
+        0xF1, 0x65, // 202 LD V1, [I]
+        0x12, 0x04, // 204 JP 204
+        0x00, 0xE0, // 206 CLS, never reached
+        0xFF,       // 208
+        0x00,
+        0x12, 0x34, // 20A data
+    ];
+
+    fn run(rom: &[u8], config: Config) -> Coverage {
+        let mut chip8 = Chip8::new(rom, config).unwrap();
+        let coverage = Rc::new(RefCell::new(Coverage::new()));
+        let accesses = coverage.clone();
+        chip8.subscribe(move |access| accesses.borrow_mut().access(access));
+        let _ = chip8.run_frame_with(&mut |record| coverage.borrow_mut().observe(record));
+        coverage.take()
+    }
+
+    fn coverage() -> Coverage {
+        run(ROM, Config::default())
+    }
+
+    #[test]
+    fn tracks_code_and_data() {
+        let coverage = coverage();
+        assert_eq!(coverage.executed(0x204), 8);
+        assert_eq!(coverage.executed(0x206), 0);
+        assert!(coverage.was_read(0x20B) && !coverage.was_read(0x20C));
+
+        let listing = coverage.listing(ROM, 0x200, Variant::Chip8);
+        let lines: Vec<_> = listing.lines().collect();
+        assert_eq!(lines[1], "         1  202: F165  --  LD V1, [I]");
+        assert_eq!(lines[3], "     #####  206: 00E0  --  CLS");
+        assert_eq!(lines[5], "     #####  20A: 1234  rr  JP #234");
+
+        let lcov = coverage.lcov("rom.ch8", 0x200..0x208);
+        assert!(lcov.contains("DA:516,8\nDA:518,0\nLF:4\nLH:3\n"));
+    }
+
+    #[test]
+    fn data_accesses_come_from_the_bus() {
+        // LD I, FFE; LD [I], V2 wraps around to 000
+        let mirrored = Config {
+            mapping: Mapping {
+                mirror: true,
+                ..Mapping::default()
+            },
+            ..Config::default()
+        };
+        let coverage = run(&[0xAF, 0xFE, 0xF2, 0x55], mirrored);
+        assert!([0xFFE, 0xFFF, 0x000]
+            .iter()
+            .all(|&a| coverage.was_written(a)));
+        assert!(!coverage.was_written(0x001));
+
+        // LD I, 1FF; LD [I], V1 stops at the first byte
+        let protected = Config {
+            mapping: Mapping {
+                write_protect: true,
+                ..Mapping::default()
+            },
+            ..Config::default()
+        };
+        let coverage = run(&[0xA1, 0xFF, 0xF1, 0x55], protected);
+        assert!(!coverage.was_written(0x1FF) && !coverage.was_written(0x200));
+        assert_eq!(coverage.executed(0x202), 1);
+    }
+
+    #[test]
+    fn lcov_by_source_line() {
+        let symbols =
+            Symbols::parse("0x200 game.8o:3\n0x202 game.8o:3\n0x206 game.8o:9\n").unwrap();
+        let lcov = coverage().lcov_with_symbols(&symbols);
+        assert_eq!(
+            lcov,
+            "TN:\nSF:game.8o\nDA:3,2\nDA:9,0\nLF:2\nLH:1\nend_of_record\n"
+        );
+        assert_eq!(
+            Symbols::parse("0x200 game.8o"),
+            Err(SymbolError { line: 1 })
+        );
+    }
+}
@@ -1,13 +1,29 @@
-mod instruction;
+pub mod disassembler;
+pub mod error;
+pub mod instruction;
+pub mod quirks;
+#[cfg(test)]
+mod test;
 mod timer;
+pub mod variant;
 
-use crate::memory::{key_state, Memory, registers::Register};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use self::{instruction::Instruction, timer::Timers};
+use crate::memory::{bus::Bus, error::BusError, registers::Register, Memory};
+
+use self::{
+    error::Error, instruction::Instruction, quirks::Quirks, timer::Timers, variant::Variant,
+};
 
 pub struct Cpu {
     program_counter: u16,
     timers: Timers,
+    rng: StdRng,
+    quirks: Quirks,
+    variant: Variant,
+    vblank_wait: bool,
+    // Where the last Dxyn read its sprite and how many bytes.
+    last_sprite: Option<(u16, usize)>,
 }
 
 impl Cpu {
@@ -15,89 +31,217 @@ impl Cpu {
         Self {
             program_counter: 0x200,
             timers: Timers::new(),
+            rng: StdRng::from_entropy(),
+            quirks: Quirks::default(),
+            variant: Variant::default(),
+            vblank_wait: false,
+            last_sprite: None,
+        }
+    }
+
+    pub fn seeded(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            ..Self::new()
         }
     }
 
-    pub fn execute(&mut self, memory: &mut Memory) {
-        let inst = memory.fetch(self.program_counter);
-        #[cfg(debug_assertions)]
-        {
-            println!("executing {inst:?} at index {}", self.program_counter);
+    pub fn with_state(program_counter: u16, delay_timer: u8, sound_timer: u8) -> Self {
+        let mut timers = Timers::new();
+        timers.set_delay_timer(delay_timer);
+        timers.set_sound_timer(sound_timer);
+        Self {
+            program_counter,
+            timers,
+            ..Self::new()
         }
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+    }
+
+    // True once after a Dxyn when the vblank quirk asks the frame to end.
+    pub fn take_vblank_wait(&mut self) -> bool {
+        std::mem::take(&mut self.vblank_wait)
+    }
+
+    pub fn last_sprite(&self) -> Option<(u16, usize)> {
+        self.last_sprite
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn set_program_counter(&mut self, program_counter: u16) {
+        self.program_counter = program_counter;
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.timers.delay()
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.timers.sound()
+    }
+
+    // For debuggers, programs use Fx15 and Fx18.
+    pub fn load_timers(&mut self, delay_timer: u8, sound_timer: u8) {
+        self.timers.set_delay_timer(delay_timer);
+        self.timers.set_sound_timer(sound_timer);
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.timers.tick();
+    }
+
+    pub fn execute(&mut self, memory: &mut Memory) -> Result<(), Error> {
+        let inst = self.fetch(memory)?;
         let (id, inst) = inst.one();
         match id {
-            0 => self.zeroth(inst, memory),
+            0 => self.zeroth(inst, memory)?,
             1 => self.jump(inst),
-            2 => self.call(inst, memory),
+            2 => self.call(inst, memory)?,
             3 => self.skip_if_equal(inst, memory),
             4 => self.skip_if_not_equal(inst, memory),
-            5 => self.skip_if_equal_reg(inst, memory),
+            5 => self.fifth(inst, memory),
             6 => self.set_reg(inst, memory),
             7 => self.add_reg(inst, memory),
-            8 => self.eighth(inst, memory),
+            8 => self.eighth(inst, memory)?,
             9 => self.skip_if_not_equal_reg(inst, memory),
             0xA => self.set_i(inst, memory),
+            0xB if self.variant == Variant::Chip8X => self.set_colors(inst, memory),
             0xB => self.jump_reg(inst, memory),
             0xC => self.rand(inst, memory),
-            0xD => self.draw(inst, memory),
-            0xE => self.eth(inst, memory),
-            0xF => self.fth(inst, memory),
+            0xD => self.draw(inst, memory)?,
+            0xE => self.eth(inst, memory)?,
+            0xF => self.fth(inst, memory)?,
             _ => unreachable!(),
         }
-        self.program_counter += 2;
-        self.timers.count_down();
-        if self.timers.sound() == 0 {
-            memory.video.stop_beep();
+        self.program_counter = self.program_counter.wrapping_add(2);
+        Ok(())
+    }
+
+    fn illegal(&self, inst: Instruction<instruction::Three>, id: u8) -> Error {
+        Error::IllegalInstruction {
+            pc: self.program_counter,
+            opcode: (id as u16) << 12 | inst.three().0,
         }
-        memory.video.get_keys(|(k, c)| {
-            if let Ok(key) = k.try_into() {
-                if c {
-                    memory.key_state.set(key);
-                } else {
-                    memory.key_state.unset(key);
-                }
-            }
-        });
     }
 
-    pub fn zeroth(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
+    fn bus_error(&self, e: BusError) -> Error {
+        let pc = self.program_counter;
+        match e {
+            BusError::OutOfBounds { addr } => Error::OutOfBounds { pc, addr },
+            BusError::WriteProtected { addr } => Error::WriteProtected { pc, addr },
+        }
+    }
+
+    fn fetch(&self, memory: &mut Memory) -> Result<Instruction<instruction::Four>, Error> {
+        let pc = self.program_counter as usize;
+        let mut fetch = |addr| memory.fetch(addr).map_err(|e| self.bus_error(e));
+        Ok(Instruction::from_bytes([fetch(pc)?, fetch(pc + 1)?]))
+    }
+
+    // `len` bytes from I on.
+    fn read_image(&self, memory: &mut Memory, len: usize) -> Result<Vec<u8>, Error> {
+        let base = memory.registers.image as usize;
+        (base..base + len)
+            .map(|addr| memory.read(addr).map_err(|e| self.bus_error(e)))
+            .collect()
+    }
+
+    fn write_image(&self, memory: &mut Memory, bytes: &[u8]) -> Result<(), Error> {
+        let base = memory.registers.image as usize;
+        for (addr, &value) in (base..).zip(bytes) {
+            memory.write(addr, value).map_err(|e| self.bus_error(e))?;
+        }
+        Ok(())
+    }
+
+    pub fn zeroth(
+        &mut self,
+        inst: Instruction<instruction::Three>,
+        memory: &mut Memory,
+    ) -> Result<(), Error> {
         let (middle_two, cls_or_ret) = inst.two();
-        match middle_two {
-            0x0E => match cls_or_ret.one().0 {
-                0x0 => {
-                    memory.framebuffer.reset();
-                }
-                0xE => {
-                    self.program_counter = memory.stack.ret();
-                }
-                _ => unreachable!(),
-            },
-            _ => todo!("sys"),
+        match (middle_two, cls_or_ret.one().0) {
+            (0x0E, 0x0) => {
+                memory.framebuffer.reset();
+            }
+            (0x0E, 0xE) => {
+                self.program_counter = memory.stack.ret().ok_or(Error::StackUnderflow {
+                    pc: self.program_counter,
+                })?;
+            }
+            (0x2A, 0x0) if self.variant == Variant::Chip8X => memory.colors.cycle_background(),
+            // the hi-res interpreter's clear screen routine
+            (0x23, 0x0) if self.variant == Variant::HiRes => memory.framebuffer.reset(),
+            (0x00, _) | (0x0E, _) => return Err(self.illegal(inst, 0)),
+            _ => {
+                return Err(Error::MachineCodeRoutine {
+                    pc: self.program_counter,
+                    addr: inst.three().0,
+                })
+            }
         }
+        Ok(())
     }
 
     pub fn jump(&mut self, inst: Instruction<instruction::Three>) {
         self.program_counter = inst.three().0;
-        self.program_counter -= 2;
+        self.program_counter = self.program_counter.wrapping_sub(2);
     }
 
-    pub fn call(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
+    pub fn call(
+        &mut self,
+        inst: Instruction<instruction::Three>,
+        memory: &mut Memory,
+    ) -> Result<(), Error> {
         let addr = inst.three().0;
-        memory.stack.call(self.program_counter);
+        memory
+            .stack
+            .call(self.program_counter)
+            .ok_or(Error::StackOverflow {
+                pc: self.program_counter,
+            })?;
         self.program_counter = addr;
+        self.program_counter = self.program_counter.wrapping_sub(2);
+        Ok(())
     }
 
     pub fn skip_if_equal(&mut self, inst: Instruction<instruction::Three>, memory: &Memory) {
         let (reg, inst_val) = inst.one();
-        if memory.registers[reg.into()] == inst_val.two().0 as _ {
-            self.program_counter += 2;
+        if memory.registers[reg.into()] == inst_val.two().0 {
+            self.program_counter = self.program_counter.wrapping_add(2);
         }
     }
 
     pub fn skip_if_not_equal(&mut self, inst: Instruction<instruction::Three>, memory: &Memory) {
         let (reg, inst_val) = inst.one();
-        if memory.registers[reg.into()] != inst_val.two().0 as _ {
-            self.program_counter += 2;
+        if memory.registers[reg.into()] != inst_val.two().0 {
+            self.program_counter = self.program_counter.wrapping_add(2);
+        }
+    }
+
+    pub fn fifth(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
+        let (_, op_code) = inst.two();
+        match op_code.one().0 {
+            0x1 if self.variant == Variant::Chip8X => self.add_nibbles(inst, memory),
+            _ => self.skip_if_equal_reg(inst, memory),
         }
     }
 
@@ -105,10 +249,18 @@ impl Cpu {
         let (reg1, reg2) = inst.one();
         let (reg2, _) = reg2.one();
         if memory.registers[reg1.into()] == memory.registers[reg2.into()] {
-            self.program_counter += 2;
+            self.program_counter = self.program_counter.wrapping_add(2);
         }
     }
 
+    // 5xy1, both digits added on their own and wrapping at 8
+    pub fn add_nibbles(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
+        let (reg1, reg2) = inst.one();
+        let (reg2, _) = reg2.one();
+        let sum = (memory.registers[reg1.into()] & 0x77) + (memory.registers[reg2.into()] & 0x77);
+        memory.registers[reg1.into()] = sum & 0x77;
+    }
+
     pub fn set_reg(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
         let (reg, val) = inst.one();
         let (val, _) = val.two();
@@ -121,7 +273,11 @@ impl Cpu {
         memory.registers[reg.into()] = memory.registers[reg.into()].wrapping_add(val);
     }
 
-    pub fn eighth(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
+    pub fn eighth(
+        &mut self,
+        inst: Instruction<instruction::Three>,
+        memory: &mut Memory,
+    ) -> Result<(), Error> {
         let (_, op_code) = inst.two();
         let op_code = op_code.one().0;
         match op_code {
@@ -134,8 +290,9 @@ impl Cpu {
             0x6 => self.shr_reg_reg(inst, memory),
             0x7 => self.subn_reg_reg(inst, memory),
             0xE => self.shl_reg_reg(inst, memory),
-            _ => unreachable!(),
+            _ => return Err(self.illegal(inst, 8)),
         }
+        Ok(())
     }
 
     pub fn set_reg_reg(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
@@ -148,18 +305,37 @@ impl Cpu {
         let (reg1, reg2) = inst.one();
         let (reg2, _) = reg2.one();
         memory.registers[reg1.into()] |= memory.registers[reg2.into()];
+        self.reset_vf_on_logic(memory);
     }
 
     pub fn and_reg_reg(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
         let (reg1, reg2) = inst.one();
         let (reg2, _) = reg2.one();
         memory.registers[reg1.into()] &= memory.registers[reg2.into()];
+        self.reset_vf_on_logic(memory);
     }
 
     pub fn xor_reg_reg(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
         let (reg1, reg2) = inst.one();
         let (reg2, _) = reg2.one();
         memory.registers[reg1.into()] ^= memory.registers[reg2.into()];
+        self.reset_vf_on_logic(memory);
+    }
+
+    fn reset_vf_on_logic(&self, memory: &mut Memory) {
+        if self.quirks.logic {
+            memory.registers[Register::VF] = 0;
+        }
+    }
+
+    // The value 8xy6 / 8xyE shift, Vy unless the shift quirk is on.
+    fn shift_source(&self, inst: Instruction<instruction::Three>, memory: &Memory) -> u8 {
+        let (reg1, reg2) = inst.one();
+        if self.quirks.shift {
+            memory.registers[reg1.into()]
+        } else {
+            memory.registers[reg2.one().0.into()]
+        }
     }
 
     pub fn add_reg_reg(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
@@ -182,9 +358,9 @@ impl Cpu {
 
     pub fn shr_reg_reg(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
         let (reg1, _) = inst.one();
-        let val = memory.registers[reg1.into()];
-        memory.registers[crate::memory::registers::Register::VF] = val & 1;
+        let val = self.shift_source(inst, memory);
         memory.registers[reg1.into()] = val.wrapping_shr(1);
+        memory.registers[crate::memory::registers::Register::VF] = val & 1;
     }
 
     pub fn subn_reg_reg(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
@@ -198,9 +374,9 @@ impl Cpu {
 
     pub fn shl_reg_reg(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
         let (reg1, _) = inst.one();
-        let val = memory.registers[reg1.into()];
-        memory.registers[crate::memory::registers::Register::VF] = val >> 7;
+        let val = self.shift_source(inst, memory);
         memory.registers[reg1.into()] = val.wrapping_shl(1);
+        memory.registers[crate::memory::registers::Register::VF] = val >> 7;
     }
 
     // 9
@@ -212,7 +388,7 @@ impl Cpu {
         let (reg1, reg2) = inst.one();
         let (reg2, _) = reg2.one();
         if memory.registers[reg1.into()] != memory.registers[reg2.into()] {
-            self.program_counter += 2;
+            self.program_counter = self.program_counter.wrapping_add(2);
         }
     }
 
@@ -225,49 +401,91 @@ impl Cpu {
     // B
     pub fn jump_reg(&mut self, inst: Instruction<instruction::Three>, memory: &Memory) {
         let (val, _) = inst.three();
-        self.program_counter =
-            memory.registers[crate::memory::registers::Register::V0] as u16 + val;
+        let reg = if self.quirks.jump { inst.one().0 } else { 0 };
+        self.program_counter = memory.registers[reg.into()] as u16 + val;
+        self.program_counter = self.program_counter.wrapping_sub(2);
+    }
+
+    // Bxyn on CHIP-8X colours the zone at Vx, Vy in V(x + 1). With n the zone
+    // is n lines high, Bxy0 takes the zone size from the high digits and
+    // works in blocks of 4 lines.
+    pub fn set_colors(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
+        let (reg1, inst) = inst.one();
+        let (reg2, inst) = inst.one();
+        let (lines, _) = inst.one();
+        let (x, y) = (memory.registers[reg1.into()], memory.registers[reg2.into()]);
+        let color = memory.registers[((reg1 + 1) & 0xF).into()];
+        if lines == 0 {
+            let (column, row) = ((x & 0xF) as usize, (y & 0xF) as usize * 4);
+            let (width, height) = ((x >> 4) as usize + 1, (y >> 4) as usize * 4 + 4);
+            memory
+                .colors
+                .fill(column..column + width, row..row + height, color);
+        } else {
+            let (column, row) = (x as usize % 64 / 8, y as usize % 32);
+            memory
+                .colors
+                .fill(column..column + 1, row..row + lines as usize, color);
+        }
     }
 
     // C
     pub fn rand(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
         let (reg, val) = inst.one();
         let (val, _) = val.two();
-        memory.registers[reg.into()] = rand::random::<u8>() & val;
+        memory.registers[reg.into()] = self.rng.gen::<u8>() & val;
     }
 
     //D
-    pub fn draw(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
+    pub fn draw(
+        &mut self,
+        inst: Instruction<instruction::Three>,
+        memory: &mut Memory,
+    ) -> Result<(), Error> {
         let (x, inst) = inst.one();
         let (y, inst) = inst.one();
         let (len, _) = inst.one();
 
-        let base = memory.registers.image as usize;
-        let colided = memory.framebuffer.write(
+        let sprite = self.read_image(memory, len as usize)?;
+        self.last_sprite = Some((memory.registers.image, sprite.len()));
+        let (x, y) = (
             memory.registers[x.into()].into(),
             memory.registers[y.into()].into(),
-            &memory.ram[base..(base + len as usize)],
         );
+        let colided = if self.quirks.wrap {
+            memory.framebuffer.write_wrapping(x, y, &sprite)
+        } else {
+            memory.framebuffer.write(x, y, &sprite)
+        };
         memory.registers[Register::VF] = colided as u8;
-        memory.video.draw(&memory.framebuffer).unwrap();
+        self.vblank_wait = self.quirks.vblank;
+        Ok(())
     }
 
-    pub fn eth(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
+    pub fn eth(
+        &mut self,
+        inst: Instruction<instruction::Three>,
+        memory: &mut Memory,
+    ) -> Result<(), Error> {
         let (_, op_code) = inst.one();
         let op_code = op_code.two().0;
         match op_code {
             0x9E => self.skip_if_key_pressed(inst, memory),
             0xA1 => self.skip_if_key_not_pressed(inst, memory),
-            _ => unreachable!(),
+            0xF2 | 0xF5 if self.variant == Variant::Chip8X => {
+                self.skip_on_second_keypad(inst, memory, op_code == 0xF2)
+            }
+            _ => return Err(self.illegal(inst, 0xE)),
         }
+        Ok(())
     }
 
     // Ex9E
     pub fn skip_if_key_pressed(&mut self, inst: Instruction<instruction::Three>, memory: &Memory) {
         let (reg, _) = inst.one();
-        let key = memory.registers[reg.into()];
+        let key = memory.registers[reg.into()] & 0xF;
         if memory.key_state.is_set(key.into()) {
-            self.program_counter += 2;
+            self.program_counter = self.program_counter.wrapping_add(2);
         }
     }
 
@@ -278,13 +496,31 @@ impl Cpu {
         memory: &Memory,
     ) {
         let (reg, _) = inst.one();
-        let key = memory.registers[reg.into()];
+        let key = memory.registers[reg.into()] & 0xF;
         if !memory.key_state.is_set(key.into()) {
-            self.program_counter += 2;
+            self.program_counter = self.program_counter.wrapping_add(2);
         }
     }
 
-    pub fn fth(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
+    // ExF2 / ExF5, CHIP-8X's second keypad
+    pub fn skip_on_second_keypad(
+        &mut self,
+        inst: Instruction<instruction::Three>,
+        memory: &Memory,
+        pressed: bool,
+    ) {
+        let (reg, _) = inst.one();
+        let key = memory.registers[reg.into()] & 0xF;
+        if memory.second_key_state.is_set(key.into()) == pressed {
+            self.program_counter = self.program_counter.wrapping_add(2);
+        }
+    }
+
+    pub fn fth(
+        &mut self,
+        inst: Instruction<instruction::Three>,
+        memory: &mut Memory,
+    ) -> Result<(), Error> {
         let (_, op_code) = inst.one();
         let op_code = op_code.two().0;
         match op_code {
@@ -294,11 +530,14 @@ impl Cpu {
             0x18 => self.set_sound_timer(inst, memory),
             0x1E => self.add_to_i(inst, memory),
             0x29 => self.set_i_to_sprite(inst, memory),
-            0x33 => self.store_bcd(inst, memory),
-            0x55 => self.store_registers(inst, memory),
-            0x65 => self.load_registers(inst, memory),
-            _ => unreachable!(),
+            0x33 => self.store_bcd(inst, memory)?,
+            0x55 => self.store_registers(inst, memory)?,
+            0x65 => self.load_registers(inst, memory)?,
+            0xF8 if self.variant == Variant::Chip8X => self.output_to_port(inst, memory),
+            0xFB if self.variant == Variant::Chip8X => self.wait_for_port(inst, memory),
+            _ => return Err(self.illegal(inst, 0xF)),
         }
+        Ok(())
     }
 
     // Fx07
@@ -310,16 +549,26 @@ impl Cpu {
     // Fx0A
     pub fn wait_for_key(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
         let (reg, _) = inst.one();
-        println!("waiting for key");
-        if let Ok(key) = memory
-            .video
-            .wait_for_key()
-            .ok_or(())
-            .and_then(key_state::Key::try_from)
-        {
+        if let Some(key) = memory.key_state.first_pressed() {
             memory.registers[reg.into()] = key as u8;
         } else {
-            panic!("No key pressed");
+            // execute this instruction again until a key shows up
+            self.program_counter = self.program_counter.wrapping_sub(2);
+        }
+    }
+
+    // FxF8
+    pub fn output_to_port(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
+        let (reg, _) = inst.one();
+        memory.port_output = memory.registers[reg.into()];
+    }
+
+    // FxFB
+    pub fn wait_for_port(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
+        let (reg, _) = inst.one();
+        match memory.port_input.take() {
+            Some(value) => memory.registers[reg.into()] = value,
+            None => self.program_counter = self.program_counter.wrapping_sub(2),
         }
     }
 
@@ -333,7 +582,6 @@ impl Cpu {
     pub fn set_sound_timer(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
         let (reg, _) = inst.one();
         self.timers.set_sound_timer(memory.registers[reg.into()]);
-        //memory.video.start_beep();
     }
 
     // Fx1E
@@ -342,49 +590,73 @@ impl Cpu {
         let val = memory
             .registers
             .image
-            .checked_add(memory.registers[reg.into()] as u16)
-            .unwrap();
+            .wrapping_add(memory.registers[reg.into()] as u16);
         memory.registers.image = val;
     }
 
     // Fx29
     pub fn set_i_to_sprite(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
         let (reg, _) = inst.one();
-        let val = memory.registers[reg.into()] as u16;
+        let val = memory.registers[reg.into()] as u16 & 0xF;
         memory.registers.image = val * 5;
     }
 
     // Fx33
-    pub fn store_bcd(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
+    pub fn store_bcd(
+        &mut self,
+        inst: Instruction<instruction::Three>,
+        memory: &mut Memory,
+    ) -> Result<(), Error> {
         let (reg, _) = inst.one();
         let val = memory.registers[reg.into()];
-        memory.ram[memory.registers.image as usize] = val / 100;
-        memory.ram[memory.registers.image as usize + 1] = (val / 10) % 10;
-        memory.ram[memory.registers.image as usize + 2] = val % 10;
+        self.write_image(memory, &[val / 100, (val / 10) % 10, val % 10])
     }
 
     // Fx55
-    pub fn store_registers(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
+    pub fn store_registers(
+        &mut self,
+        inst: Instruction<instruction::Three>,
+        memory: &mut Memory,
+    ) -> Result<(), Error> {
         let (reg, _) = inst.one();
-        for i in 0u8..=reg {
-            memory.ram[(memory.registers.image + i as u16) as usize] = memory.registers[i.into()];
-        }
-        memory.registers.image += reg as u16 + 1;
+        let values = *memory.registers.values();
+        self.write_image(memory, &values[..=reg as usize])?;
+        self.advance_i_after_transfer(reg, memory);
+        Ok(())
     }
 
     // Fx65
-    pub fn load_registers(&mut self, inst: Instruction<instruction::Three>, memory: &mut Memory) {
+    pub fn load_registers(
+        &mut self,
+        inst: Instruction<instruction::Three>,
+        memory: &mut Memory,
+    ) -> Result<(), Error> {
         let (reg, _) = inst.one();
-        for i in 0u8..=reg {
-            memory.registers[i.into()] = memory.ram[(memory.registers.image + i as u16) as usize];
+        let values = self.read_image(memory, reg as usize + 1)?;
+        for (i, value) in (0u8..).zip(values) {
+            memory.registers[i.into()] = value;
         }
-        memory.registers.image += reg as u16 + 1;
+        self.advance_i_after_transfer(reg, memory);
+        Ok(())
+    }
+}
+
+impl Cpu {
+    fn advance_i_after_transfer(&self, reg: u8, memory: &mut Memory) {
+        let step = match (
+            self.quirks.memory_leave_i_unchanged,
+            self.quirks.memory_increment_by_x,
+        ) {
+            (true, _) => 0,
+            (false, true) => reg as u16,
+            (false, false) => reg as u16 + 1,
+        };
+        memory.registers.image = memory.registers.image.wrapping_add(step);
     }
 }
 
-impl Memory {
-    fn fetch(&self, pc: u16) -> Instruction<instruction::Four> {
-        let pc = pc as usize;
-        Instruction::from_bytes([self.ram[pc], self.ram[pc + 1]])
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -0,0 +1,83 @@
+use super::variant::Variant;
+
+// Cowgod style mnemonics, anything the interpreter would reject comes out as
+// a DW of the raw opcode.
+pub fn disassemble(opcode: u16, variant: Variant) -> String {
+    let x = opcode >> 8 & 0xF;
+    let y = opcode >> 4 & 0xF;
+    let n = opcode & 0xF;
+    let kk = opcode & 0xFF;
+    let nnn = opcode & 0xFFF;
+    let chip8x = variant == Variant::Chip8X;
+    match (opcode >> 12, x, y, n) {
+        (0x0, 0x0, 0xE, 0x0) => "CLS".to_owned(),
+        (0x0, 0x0, 0xE, 0xE) => "RET".to_owned(),
+        (0x0, 0x2, 0x3, 0x0) if variant == Variant::HiRes => "CLS".to_owned(),
+        (0x0, 0x2, 0xA, 0x0) if chip8x => "BGCOL".to_owned(),
+        (0x0, 0x0, 0x0 | 0xE, _) => format!("DW #{opcode:04X}"),
+        (0x0, ..) => format!("SYS #{nnn:03X}"),
+        (0x1, ..) => format!("JP #{nnn:03X}"),
+        (0x2, ..) => format!("CALL #{nnn:03X}"),
+        (0x3, ..) => format!("SE V{x:X}, #{kk:02X}"),
+        (0x4, ..) => format!("SNE V{x:X}, #{kk:02X}"),
+        (0x5, _, _, 0x1) if chip8x => format!("ADDN V{x:X}, V{y:X}"),
+        (0x5, ..) => format!("SE V{x:X}, V{y:X}"),
+        (0x6, ..) => format!("LD V{x:X}, #{kk:02X}"),
+        (0x7, ..) => format!("ADD V{x:X}, #{kk:02X}"),
+        (0x8, _, _, 0x0) => format!("LD V{x:X}, V{y:X}"),
+        (0x8, _, _, 0x1) => format!("OR V{x:X}, V{y:X}"),
+        (0x8, _, _, 0x2) => format!("AND V{x:X}, V{y:X}"),
+        (0x8, _, _, 0x3) => format!("XOR V{x:X}, V{y:X}"),
+        (0x8, _, _, 0x4) => format!("ADD V{x:X}, V{y:X}"),
+        (0x8, _, _, 0x5) => format!("SUB V{x:X}, V{y:X}"),
+        (0x8, _, _, 0x6) => format!("SHR V{x:X}, V{y:X}"),
+        (0x8, _, _, 0x7) => format!("SUBN V{x:X}, V{y:X}"),
+        (0x8, _, _, 0xE) => format!("SHL V{x:X}, V{y:X}"),
+        (0x8, ..) => format!("DW #{opcode:04X}"),
+        (0x9, ..) => format!("SNE V{x:X}, V{y:X}"),
+        (0xA, ..) => format!("LD I, #{nnn:03X}"),
+        (0xB, ..) if chip8x => format!("COL V{x:X}, V{y:X}, {n}"),
+        (0xB, ..) => format!("JP V0, #{nnn:03X}"),
+        (0xC, ..) => format!("RND V{x:X}, #{kk:02X}"),
+        (0xD, ..) => format!("DRW V{x:X}, V{y:X}, {n}"),
+        (0xE, ..) => match kk {
+            0x9E => format!("SKP V{x:X}"),
+            0xA1 => format!("SKNP V{x:X}"),
+            0xF2 if chip8x => format!("SKP2 V{x:X}"),
+            0xF5 if chip8x => format!("SKNP2 V{x:X}"),
+            _ => format!("DW #{opcode:04X}"),
+        },
+        _ => match kk {
+            0x07 => format!("LD V{x:X}, DT"),
+            0x0A => format!("LD V{x:X}, K"),
+            0x15 => format!("LD DT, V{x:X}"),
+            0x18 => format!("LD ST, V{x:X}"),
+            0x1E => format!("ADD I, V{x:X}"),
+            0x29 => format!("LD F, V{x:X}"),
+            0x33 => format!("LD B, V{x:X}"),
+            0x55 => format!("LD [I], V{x:X}"),
+            0x65 => format!("LD V{x:X}, [I]"),
+            0xF8 if chip8x => format!("OUT V{x:X}"),
+            0xFB if chip8x => format!("IN V{x:X}"),
+            _ => format!("DW #{opcode:04X}"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mnemonics() {
+        let plain = |opcode| disassemble(opcode, Variant::Chip8);
+        assert_eq!(plain(0x00E0), "CLS");
+        assert_eq!(plain(0x0123), "SYS #123");
+        assert_eq!(plain(0x8AB6), "SHR VA, VB");
+        assert_eq!(plain(0xD125), "DRW V1, V2, 5");
+        assert_eq!(plain(0xF355), "LD [I], V3");
+        assert_eq!(plain(0xE1F2), "DW #E1F2");
+        assert_eq!(plain(0x812F), "DW #812F");
+        assert_eq!(disassemble(0xE1F2, Variant::Chip8X), "SKP2 V1");
+    }
+}
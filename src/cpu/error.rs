@@ -0,0 +1,54 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    IllegalInstruction { pc: u16, opcode: u16 },
+    MachineCodeRoutine { pc: u16, addr: u16 },
+    StackOverflow { pc: u16 },
+    StackUnderflow { pc: u16 },
+    OutOfBounds { pc: u16, addr: usize },
+    WriteProtected { pc: u16, addr: usize },
+}
+
+impl Error {
+    pub fn pc(&self) -> u16 {
+        match *self {
+            Error::IllegalInstruction { pc, .. }
+            | Error::MachineCodeRoutine { pc, .. }
+            | Error::StackOverflow { pc }
+            | Error::StackUnderflow { pc }
+            | Error::OutOfBounds { pc, .. }
+            | Error::WriteProtected { pc, .. } => pc,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::IllegalInstruction { pc, opcode } => {
+                write!(f, "illegal instruction {opcode:04X} at {pc:#05X}")
+            }
+            Error::MachineCodeRoutine { pc, addr } => write!(
+                f,
+                "machine code routine at {addr:#05X} called from {pc:#05X} is not supported"
+            ),
+            Error::StackOverflow { pc } => write!(f, "stack overflow on call at {pc:#05X}"),
+            Error::StackUnderflow { pc } => {
+                write!(f, "tried to return at {pc:#05X} but the stack is empty")
+            }
+            Error::OutOfBounds { pc, addr } => {
+                write!(
+                    f,
+                    "instruction at {pc:#05X} accessed {addr:#X}, outside of ram"
+                )
+            }
+            Error::WriteProtected { pc, addr } => write!(
+                f,
+                "instruction at {pc:#05X} wrote to {addr:#05X}, below the program"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -1,4 +1,4 @@
-use std::{marker::PhantomData, fmt::Debug};
+use std::{fmt::Debug, marker::PhantomData};
 
 pub trait Len {
     type Pred;
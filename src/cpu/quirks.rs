@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+// Behaviour differences between CHIP-8 interpreters, named and interpreted
+// like the quirks of the community chip-8-database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Quirks {
+    // 8xy6 / 8xyE shift Vx in place instead of loading Vy first
+    pub shift: bool,
+    // Fx55 / Fx65 leave I at I + x instead of I + x + 1
+    pub memory_increment_by_x: bool,
+    // Fx55 / Fx65 don't touch I at all
+    pub memory_leave_i_unchanged: bool,
+    // sprites wrap around the screen edges instead of being clipped
+    pub wrap: bool,
+    // Bxnn jumps to xnn + Vx instead of xnn + V0
+    pub jump: bool,
+    // Dxyn waits for the next frame
+    pub vblank: bool,
+    // 8xy1 / 8xy2 / 8xy3 reset VF
+    pub logic: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift: true,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: false,
+            wrap: false,
+            jump: false,
+            vblank: false,
+            logic: false,
+        }
+    }
+}
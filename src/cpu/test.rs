@@ -0,0 +1,717 @@
+use crate::memory::{
+    bus::Mapping,
+    framebuffer::FrameBuffer,
+    key_state::Key,
+    registers::{Register, Register::*, Registers},
+    stack::Stack,
+    Memory,
+};
+
+use super::{error::Error, quirks::Quirks, variant::Variant, Cpu};
+
+// Describes the machine before a single instruction runs.
+struct Machine {
+    v: [u8; 16],
+    i: u16,
+    pc: u16,
+    stack: Vec<u16>,
+    ram: Vec<(u16, Vec<u8>)>,
+    pixels: Vec<(usize, usize)>,
+    delay: u8,
+    sound: u8,
+    keys: Vec<Key>,
+    second_keys: Vec<Key>,
+    quirks: Quirks,
+    variant: Variant,
+    mapping: Mapping,
+}
+
+// The machine after the instruction ran.
+struct State {
+    cpu: Cpu,
+    memory: Memory,
+}
+
+impl Machine {
+    fn new() -> Self {
+        Self {
+            v: [0; 16],
+            i: 0,
+            pc: 0x200,
+            stack: Vec::new(),
+            ram: Vec::new(),
+            pixels: Vec::new(),
+            delay: 0,
+            sound: 0,
+            keys: Vec::new(),
+            second_keys: Vec::new(),
+            quirks: Quirks::default(),
+            variant: Variant::Chip8,
+            mapping: Mapping::default(),
+        }
+    }
+
+    fn v(mut self, reg: Register, val: u8) -> Self {
+        self.v[reg as usize] = val;
+        self
+    }
+
+    fn i(mut self, val: u16) -> Self {
+        self.i = val;
+        self
+    }
+
+    fn pc(mut self, val: u16) -> Self {
+        self.pc = val;
+        self
+    }
+
+    fn stack(mut self, entries: &[u16]) -> Self {
+        self.stack = entries.to_vec();
+        self
+    }
+
+    fn ram(mut self, addr: u16, bytes: &[u8]) -> Self {
+        self.ram.push((addr, bytes.to_vec()));
+        self
+    }
+
+    fn pixel(mut self, x: usize, y: usize) -> Self {
+        self.pixels.push((x, y));
+        self
+    }
+
+    fn delay(mut self, val: u8) -> Self {
+        self.delay = val;
+        self
+    }
+
+    fn key(mut self, key: Key) -> Self {
+        self.keys.push(key);
+        self
+    }
+
+    fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    fn chip8x(mut self) -> Self {
+        self.variant = Variant::Chip8X;
+        self
+    }
+
+    fn hires(mut self) -> Self {
+        self.variant = Variant::HiRes;
+        self
+    }
+
+    fn mapping(mut self, mapping: Mapping) -> Self {
+        self.mapping = mapping;
+        self
+    }
+
+    fn second_key(mut self, key: Key) -> Self {
+        self.second_keys.push(key);
+        self
+    }
+
+    // Places `opcode` at the program counter and executes it.
+    fn run(self, opcode: u16) -> State {
+        self.try_run(opcode).unwrap()
+    }
+
+    fn fail(self, opcode: u16) -> Error {
+        self.try_run(opcode).err().unwrap()
+    }
+
+    fn try_run(self, opcode: u16) -> Result<State, Error> {
+        let mut memory = Memory::new();
+        memory.mapping = self.mapping;
+        let (width, height) = self.variant.display_size();
+        memory.framebuffer = FrameBuffer::new(width, height);
+        memory.registers = Registers::new(self.v, self.i);
+        memory.stack = Stack::from_entries(&self.stack);
+        for (addr, bytes) in self.ram {
+            let addr = addr as usize;
+            memory.ram[addr..addr + bytes.len()].copy_from_slice(&bytes);
+        }
+        for (x, y) in self.pixels {
+            memory.framebuffer.write(x, y, &[0x80]);
+        }
+        for key in self.keys {
+            memory.key_state.set(key);
+        }
+        for key in self.second_keys {
+            memory.second_key_state.set(key);
+        }
+        let pc = self.pc as usize;
+        memory.ram[pc..pc + 2].copy_from_slice(&opcode.to_be_bytes());
+
+        let mut cpu = Cpu::with_state(self.pc, self.delay, self.sound);
+        cpu.set_quirks(self.quirks);
+        cpu.set_variant(self.variant);
+        cpu.execute(&mut memory)?;
+        Ok(State { cpu, memory })
+    }
+}
+
+impl State {
+    fn v(&self, reg: Register) -> u8 {
+        self.memory.registers[reg]
+    }
+
+    fn i(&self) -> u16 {
+        self.memory.registers.image
+    }
+
+    fn pc(&self) -> u16 {
+        self.cpu.program_counter()
+    }
+
+    fn ram(&self, addr: u16, len: usize) -> &[u8] {
+        &self.memory.ram[addr as usize..addr as usize + len]
+    }
+
+    fn lit_pixels(&self) -> Vec<(usize, usize)> {
+        self.memory
+            .framebuffer
+            .iter()
+            .filter(|p| p.state)
+            .map(|p| (p.x, p.y))
+            .collect()
+    }
+}
+
+#[test]
+fn cls_clears_the_screen() {
+    let s = Machine::new().pixel(0, 0).pixel(63, 31).run(0x00E0);
+    assert!(s.lit_pixels().is_empty());
+    assert_eq!(s.pc(), 0x202);
+}
+
+#[test]
+fn ret_returns_after_the_call() {
+    let s = Machine::new().pc(0x300).stack(&[0x204]).run(0x00EE);
+    assert_eq!(s.pc(), 0x206);
+    assert!(s.memory.stack.entries().is_empty());
+}
+
+#[test]
+fn jump_goes_to_the_address() {
+    let s = Machine::new().run(0x1345);
+    assert_eq!(s.pc(), 0x345);
+}
+
+#[test]
+fn call_pushes_and_jumps() {
+    let s = Machine::new().pc(0x210).run(0x2345);
+    assert_eq!(s.pc(), 0x345);
+    assert_eq!(s.memory.stack.entries(), &[0x210]);
+}
+
+#[test]
+fn skip_if_equal_imm() {
+    assert_eq!(Machine::new().v(V3, 0x42).run(0x3342).pc(), 0x204);
+    assert_eq!(Machine::new().v(V3, 0x41).run(0x3342).pc(), 0x202);
+}
+
+#[test]
+fn skip_if_not_equal_imm() {
+    assert_eq!(Machine::new().v(V3, 0x42).run(0x4342).pc(), 0x202);
+    assert_eq!(Machine::new().v(V3, 0x41).run(0x4342).pc(), 0x204);
+}
+
+#[test]
+fn skip_if_equal_reg() {
+    assert_eq!(Machine::new().v(V1, 7).v(V2, 7).run(0x5120).pc(), 0x204);
+    assert_eq!(Machine::new().v(V1, 7).v(V2, 8).run(0x5120).pc(), 0x202);
+}
+
+#[test]
+fn skip_if_not_equal_reg() {
+    assert_eq!(Machine::new().v(V1, 7).v(V2, 7).run(0x9120).pc(), 0x202);
+    assert_eq!(Machine::new().v(V1, 7).v(V2, 8).run(0x9120).pc(), 0x204);
+}
+
+#[test]
+fn set_reg_imm() {
+    assert_eq!(Machine::new().run(0x6AFE).v(VA), 0xFE);
+}
+
+#[test]
+fn add_reg_imm_wraps_without_touching_vf() {
+    let s = Machine::new().v(V2, 0xFF).v(VF, 0x55).run(0x7203);
+    assert_eq!(s.v(V2), 0x02);
+    assert_eq!(s.v(VF), 0x55);
+}
+
+#[test]
+fn logic_ops() {
+    let m = || Machine::new().v(V1, 0b1100).v(V2, 0b1010);
+    assert_eq!(m().run(0x8120).v(V1), 0b1010);
+    assert_eq!(m().run(0x8121).v(V1), 0b1110);
+    assert_eq!(m().run(0x8122).v(V1), 0b1000);
+    assert_eq!(m().run(0x8123).v(V1), 0b0110);
+}
+
+#[test]
+fn add_reg_reg_sets_carry() {
+    let s = Machine::new().v(V1, 0xF0).v(V2, 0x20).run(0x8124);
+    assert_eq!((s.v(V1), s.v(VF)), (0x10, 1));
+    let s = Machine::new().v(V1, 0x10).v(V2, 0x20).run(0x8124);
+    assert_eq!((s.v(V1), s.v(VF)), (0x30, 0));
+}
+
+#[test]
+fn add_reg_reg_with_vf_as_operand() {
+    // VF as destination: the flag wins over the sum
+    let s = Machine::new().v(VF, 0xF0).v(V2, 0x20).run(0x8F24);
+    assert_eq!(s.v(VF), 1);
+    // VF as source: its value is read before the flag is written
+    let s = Machine::new().v(V1, 0x01).v(VF, 0xFF).run(0x81F4);
+    assert_eq!((s.v(V1), s.v(VF)), (0x00, 1));
+}
+
+#[test]
+fn sub_reg_reg_sets_not_borrow() {
+    let s = Machine::new().v(V1, 0x30).v(V2, 0x10).run(0x8125);
+    assert_eq!((s.v(V1), s.v(VF)), (0x20, 1));
+    let s = Machine::new().v(V1, 0x10).v(V2, 0x30).run(0x8125);
+    assert_eq!((s.v(V1), s.v(VF)), (0xE0, 0));
+    let s = Machine::new().v(V1, 0x10).v(V2, 0x10).run(0x8125);
+    assert_eq!((s.v(V1), s.v(VF)), (0x00, 1));
+}
+
+#[test]
+fn sub_reg_reg_with_vf_as_operand() {
+    let s = Machine::new().v(VF, 0x10).v(V2, 0x30).run(0x8F25);
+    assert_eq!(s.v(VF), 0);
+    let s = Machine::new().v(V1, 0x30).v(VF, 0x10).run(0x81F5);
+    assert_eq!((s.v(V1), s.v(VF)), (0x20, 1));
+}
+
+#[test]
+fn subn_reg_reg_sets_not_borrow() {
+    let s = Machine::new().v(V1, 0x10).v(V2, 0x30).run(0x8127);
+    assert_eq!((s.v(V1), s.v(VF)), (0x20, 1));
+    let s = Machine::new().v(V1, 0x30).v(V2, 0x10).run(0x8127);
+    assert_eq!((s.v(V1), s.v(VF)), (0xE0, 0));
+}
+
+#[test]
+fn subn_reg_reg_with_vf_as_operand() {
+    let s = Machine::new().v(VF, 0x30).v(V2, 0x10).run(0x8F27);
+    assert_eq!(s.v(VF), 0);
+    let s = Machine::new().v(V1, 0x10).v(VF, 0x30).run(0x81F7);
+    assert_eq!((s.v(V1), s.v(VF)), (0x20, 1));
+}
+
+#[test]
+fn shifts_move_the_lost_bit_into_vf() {
+    let s = Machine::new().v(V1, 0b1000_0011).run(0x8106);
+    assert_eq!((s.v(V1), s.v(VF)), (0b0100_0001, 1));
+    let s = Machine::new().v(V1, 0b1000_0011).run(0x810E);
+    assert_eq!((s.v(V1), s.v(VF)), (0b0000_0110, 1));
+    let s = Machine::new().v(V1, 0b0000_0010).run(0x8106);
+    assert_eq!((s.v(V1), s.v(VF)), (0b0000_0001, 0));
+}
+
+#[test]
+fn shifts_with_vf_as_operand_keep_the_flag() {
+    assert_eq!(Machine::new().v(VF, 0b0000_0011).run(0x8F06).v(VF), 1);
+    assert_eq!(Machine::new().v(VF, 0b0100_0000).run(0x8F0E).v(VF), 0);
+}
+
+#[test]
+fn set_i() {
+    assert_eq!(Machine::new().run(0xA123).i(), 0x123);
+}
+
+#[test]
+fn jump_plus_v0() {
+    let s = Machine::new().v(V0, 0x10).run(0xB300);
+    assert_eq!(s.pc(), 0x310);
+}
+
+#[test]
+fn rand_is_masked() {
+    for _ in 0..32 {
+        assert_eq!(Machine::new().run(0xC30F).v(V3) & 0xF0, 0);
+    }
+    assert_eq!(Machine::new().v(V3, 0xFF).run(0xC300).v(V3), 0);
+}
+
+#[test]
+fn draw_xors_sprite_rows() {
+    let s = Machine::new()
+        .v(V1, 2)
+        .v(V2, 3)
+        .i(0x300)
+        .ram(0x300, &[0b1100_0000, 0b0100_0000])
+        .run(0xD122);
+    assert_eq!(s.lit_pixels(), vec![(2, 3), (3, 3), (3, 4)]);
+    assert_eq!(s.v(VF), 0);
+}
+
+#[test]
+fn draw_reports_collisions() {
+    let s = Machine::new()
+        .pixel(0, 0)
+        .i(0x300)
+        .ram(0x300, &[0b1000_0000])
+        .run(0xD121);
+    assert!(s.lit_pixels().is_empty());
+    assert_eq!(s.v(VF), 1);
+}
+
+#[test]
+fn draw_wraps_the_origin_and_clips_the_sprite() {
+    let s = Machine::new()
+        .v(V1, 64 + 62)
+        .v(V2, 32 + 31)
+        .i(0x300)
+        .ram(0x300, &[0xFF, 0xFF])
+        .run(0xD122);
+    assert_eq!(s.lit_pixels(), vec![(62, 31), (63, 31)]);
+}
+
+#[test]
+fn skip_if_key() {
+    let m = || Machine::new().v(V4, 0xA);
+    assert_eq!(m().key(Key::A).run(0xE49E).pc(), 0x204);
+    assert_eq!(m().run(0xE49E).pc(), 0x202);
+    assert_eq!(m().key(Key::A).run(0xE4A1).pc(), 0x202);
+    assert_eq!(m().run(0xE4A1).pc(), 0x204);
+}
+
+#[test]
+fn timers() {
+    assert_eq!(Machine::new().delay(9).run(0xF507).v(V5), 9);
+    let s = Machine::new().v(V5, 7).run(0xF515);
+    assert_eq!(s.cpu.delay_timer(), 7);
+    let s = Machine::new().v(V5, 3).run(0xF518);
+    assert_eq!(s.cpu.sound_timer(), 3);
+}
+
+#[test]
+fn wait_for_key_blocks_until_pressed() {
+    let s = Machine::new().run(0xF30A);
+    assert_eq!(s.pc(), 0x200);
+    let s = Machine::new().key(Key::B).run(0xF30A);
+    assert_eq!((s.pc(), s.v(V3)), (0x202, 0xB));
+}
+
+#[test]
+fn add_to_i() {
+    let s = Machine::new().i(0x100).v(V2, 0x20).v(VF, 0x33).run(0xF21E);
+    assert_eq!((s.i(), s.v(VF)), (0x120, 0x33));
+}
+
+#[test]
+fn font_sprite_address() {
+    assert_eq!(Machine::new().v(V2, 0xA).run(0xF229).i(), 50);
+    assert_eq!(Machine::new().v(V2, 0x1A).run(0xF229).i(), 50);
+    let s = Machine::new().v(V2, 0x1).run(0xF229);
+    assert_eq!(s.ram(s.i(), 5), &[0x20, 0x60, 0x20, 0x20, 0x70]);
+}
+
+#[test]
+fn bcd_edge_values() {
+    for (val, digits) in [
+        (0, [0, 0, 0]),
+        (9, [0, 0, 9]),
+        (10, [0, 1, 0]),
+        (99, [0, 9, 9]),
+        (100, [1, 0, 0]),
+        (101, [1, 0, 1]),
+        (255, [2, 5, 5]),
+    ] {
+        let s = Machine::new().v(V7, val).i(0x300).run(0xF733);
+        assert_eq!(s.ram(0x300, 3), &digits, "bcd of {val}");
+        assert_eq!(s.i(), 0x300);
+    }
+}
+
+#[test]
+fn store_registers() {
+    let s = Machine::new()
+        .v(V0, 1)
+        .v(V1, 2)
+        .v(V2, 3)
+        .v(V3, 4)
+        .i(0x300)
+        .run(0xF255);
+    assert_eq!(s.ram(0x300, 4), &[1, 2, 3, 0]);
+    assert_eq!(s.i(), 0x303);
+}
+
+#[test]
+fn load_registers() {
+    let s = Machine::new()
+        .i(0x300)
+        .ram(0x300, &[9, 8, 7, 6])
+        .run(0xF265);
+    assert_eq!([s.v(V0), s.v(V1), s.v(V2), s.v(V3)], [9, 8, 7, 0]);
+    assert_eq!(s.i(), 0x303);
+}
+
+#[test]
+fn ret_on_empty_stack_is_an_error() {
+    assert_eq!(
+        Machine::new().fail(0x00EE),
+        Error::StackUnderflow { pc: 0x200 }
+    );
+}
+
+#[test]
+fn call_on_full_stack_is_an_error() {
+    let s = Machine::new().stack(&[0x200; 32]);
+    assert_eq!(s.fail(0x2300), Error::StackOverflow { pc: 0x200 });
+}
+
+#[test]
+fn unknown_opcodes_are_errors() {
+    for opcode in [0x0000, 0x00E1, 0x8128, 0xE1FF, 0xF1FF] {
+        assert_eq!(
+            Machine::new().fail(opcode),
+            Error::IllegalInstruction { pc: 0x200, opcode }
+        );
+    }
+    assert_eq!(
+        Machine::new().fail(0x0123),
+        Error::MachineCodeRoutine {
+            pc: 0x200,
+            addr: 0x123
+        }
+    );
+}
+
+#[test]
+fn memory_accesses_past_ram_are_errors() {
+    let m = || Machine::new().i(0xFFE);
+    let oob = Error::OutOfBounds {
+        pc: 0x200,
+        addr: 0x1000,
+    };
+    assert_eq!(m().fail(0xD013), oob);
+    assert_eq!(m().fail(0xF033), oob);
+    assert_eq!(m().fail(0xF255), oob);
+    assert_eq!(m().fail(0xF265), oob);
+    assert_eq!(m().run(0xF155).i(), 0x1000);
+}
+
+#[test]
+fn mirrored_ram_wraps_around() {
+    let mirror = Mapping {
+        mirror: true,
+        ..Mapping::default()
+    };
+    let s = Machine::new()
+        .i(0xFFF)
+        .v(V0, 1)
+        .v(V1, 2)
+        .mapping(mirror)
+        .run(0xF155);
+    assert_eq!((s.ram(0xFFF, 1), s.ram(0x000, 1)), (&[1][..], &[2][..]));
+
+    let mut memory = Memory::new();
+    memory.mapping = mirror;
+    memory.ram[0x000] = 0x00;
+    memory.ram[0xFFF] = 0x60;
+    let mut cpu = Cpu::with_state(0xFFF, 0, 0);
+    cpu.execute(&mut memory).unwrap();
+    assert_eq!(memory.registers[V0], 0);
+}
+
+#[test]
+fn write_protection_covers_the_interpreter_area() {
+    let protect = Mapping {
+        write_protect: true,
+        ..Mapping::default()
+    };
+    let m = || Machine::new().mapping(protect);
+    assert_eq!(
+        m().i(0x1FF).fail(0xF155),
+        Error::WriteProtected {
+            pc: 0x200,
+            addr: 0x1FF
+        }
+    );
+    assert_eq!(m().i(0x200).run(0xF033).ram(0x200, 3), &[0, 0, 0]);
+    assert_eq!(m().i(0x000).run(0xF065).v(V0), 0xF0);
+}
+
+#[test]
+fn fetching_past_ram_is_an_error() {
+    let mut memory = Memory::new();
+    let mut cpu = Cpu::with_state(0xFFF, 0, 0);
+    assert_eq!(
+        cpu.execute(&mut memory),
+        Err(Error::OutOfBounds {
+            pc: 0xFFF,
+            addr: 0x1000
+        })
+    );
+}
+
+#[test]
+fn shift_quirk_off_shifts_vy() {
+    let quirks = Quirks {
+        shift: false,
+        ..Quirks::default()
+    };
+    let s = Machine::new()
+        .quirks(quirks)
+        .v(V1, 0xFF)
+        .v(V2, 0b0000_0110)
+        .run(0x8126);
+    assert_eq!((s.v(V1), s.v(VF)), (0b0000_0011, 0));
+}
+
+#[test]
+fn memory_quirks_move_i() {
+    let m = |quirks| Machine::new().quirks(quirks).i(0x300).run(0xF255).i();
+    assert_eq!(m(Quirks::default()), 0x303);
+    let by_x = Quirks {
+        memory_increment_by_x: true,
+        ..Quirks::default()
+    };
+    assert_eq!(m(by_x), 0x302);
+    let unchanged = Quirks {
+        memory_leave_i_unchanged: true,
+        ..Quirks::default()
+    };
+    assert_eq!(m(unchanged), 0x300);
+}
+
+#[test]
+fn wrap_quirk_wraps_sprites() {
+    let quirks = Quirks {
+        wrap: true,
+        ..Quirks::default()
+    };
+    let s = Machine::new()
+        .quirks(quirks)
+        .v(V1, 63)
+        .v(V2, 31)
+        .i(0x300)
+        .ram(0x300, &[0xC0, 0x80])
+        .run(0xD122);
+    assert_eq!(s.lit_pixels(), vec![(63, 0), (0, 31), (63, 31)]);
+}
+
+#[test]
+fn jump_quirk_uses_vx() {
+    let quirks = Quirks {
+        jump: true,
+        ..Quirks::default()
+    };
+    let s = Machine::new()
+        .quirks(quirks)
+        .v(V0, 0x01)
+        .v(V3, 0x10)
+        .run(0xB300);
+    assert_eq!(s.pc(), 0x310);
+}
+
+#[test]
+fn logic_quirk_resets_vf() {
+    let quirks = Quirks {
+        logic: true,
+        ..Quirks::default()
+    };
+    for opcode in [0x8121, 0x8122, 0x8123] {
+        assert_eq!(Machine::new().quirks(quirks).v(VF, 1).run(opcode).v(VF), 0);
+    }
+    assert_eq!(Machine::new().v(VF, 1).run(0x8121).v(VF), 1);
+}
+
+#[test]
+fn vblank_quirk_ends_the_frame_after_drawing() {
+    let quirks = Quirks {
+        vblank: true,
+        ..Quirks::default()
+    };
+    let mut s = Machine::new().quirks(quirks).run(0xD120);
+    assert!(s.cpu.take_vblank_wait());
+    assert!(!s.cpu.take_vblank_wait());
+}
+
+#[test]
+fn chip8x_cycles_the_background() {
+    let s = Machine::new().chip8x().run(0x02A0);
+    assert_eq!(s.memory.colors.background(), [0, 0, 0]);
+    assert!(matches!(
+        Machine::new().fail(0x02A0),
+        Error::MachineCodeRoutine { .. }
+    ));
+}
+
+#[test]
+fn chip8x_adds_digits_separately() {
+    let s = Machine::new().chip8x().v(V1, 0x35).v(V2, 0x46).run(0x5121);
+    assert_eq!(s.v(V1), 0x73);
+    assert_eq!(s.pc(), 0x202);
+}
+
+#[test]
+fn chip8x_colours_zones() {
+    // one strip, lines 2 to 4, in V3
+    let s = Machine::new()
+        .chip8x()
+        .v(V2, 8)
+        .v(V3, 4)
+        .v(V4, 2)
+        .run(0xB243);
+    let colors = &s.memory.colors;
+    assert_eq!(colors.foreground(8, 2), [0x00, 0xFF, 0x00]);
+    assert_eq!(colors.foreground(15, 4), [0x00, 0xFF, 0x00]);
+    assert_eq!(colors.foreground(16, 2), [0xFF, 0x00, 0x00]);
+    assert_eq!(colors.foreground(8, 5), [0xFF, 0x00, 0x00]);
+
+    // two zones across, one 4 line block down
+    let s = Machine::new()
+        .chip8x()
+        .v(V2, 0x11)
+        .v(V3, 7)
+        .v(V4, 0x01)
+        .run(0xB240);
+    let colors = &s.memory.colors;
+    assert_eq!(colors.foreground(8, 4), [0xFF, 0xFF, 0xFF]);
+    assert_eq!(colors.foreground(23, 7), [0xFF, 0xFF, 0xFF]);
+    assert_eq!(colors.foreground(24, 4), [0xFF, 0x00, 0x00]);
+    assert_eq!(colors.foreground(8, 8), [0xFF, 0x00, 0x00]);
+    // Bnnn is still a jump on plain CHIP-8
+    assert_eq!(Machine::new().run(0xB240).pc(), 0x240);
+}
+
+#[test]
+fn chip8x_second_keypad() {
+    let m = || Machine::new().chip8x().v(V1, 0xA);
+    assert_eq!(m().second_key(Key::A).run(0xE1F2).pc(), 0x204);
+    assert_eq!(m().key(Key::A).run(0xE1F2).pc(), 0x202);
+    assert_eq!(m().run(0xE1F5).pc(), 0x204);
+}
+
+#[test]
+fn chip8x_port_io() {
+    let s = Machine::new().chip8x().v(V1, 0x42).run(0xF1F8);
+    assert_eq!(s.memory.port_output, 0x42);
+    // nothing on the port yet, so FxFB runs again
+    assert_eq!(Machine::new().chip8x().run(0xF1FB).pc(), 0x200);
+}
+
+#[test]
+fn hires_draws_on_64_lines() {
+    let s = Machine::new()
+        .hires()
+        .v(V1, 3)
+        .v(V2, 40)
+        .i(0x300)
+        .ram(0x300, &[0x80])
+        .run(0xD121);
+    assert_eq!(s.lit_pixels(), [(3, 40)]);
+    let s = Machine::new().hires().pixel(3, 40).run(0x0230);
+    assert_eq!(s.lit_pixels(), []);
+}
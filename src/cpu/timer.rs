@@ -1,18 +1,12 @@
-use std::time::{Duration, Instant};
-
+#[derive(Default)]
 pub struct Timers {
     delay_timer: u8,
     sound_timer: u8,
-    last_instant: Instant,
 }
 
 impl Timers {
     pub fn new() -> Self {
-        Self {
-            delay_timer: 0,
-            sound_timer: 0,
-            last_instant: Instant::now(),
-        }
+        Self::default()
     }
 
     pub fn set_delay_timer(&mut self, counter: u8) {
@@ -23,19 +17,10 @@ impl Timers {
         self.sound_timer = counter;
     }
 
-    pub fn count_down(&mut self) {
-        let frame_length = Duration::from_secs_f64(1.0 / 60.0);
-
-        let duration = self.last_instant.elapsed();
-
-        // TODO: remove this panic
-        let frames_skipped = (duration.as_nanos() / frame_length.as_nanos())
-            .try_into()
-            .unwrap_or(u8::MAX);
-
-        self.delay_timer = self.delay_timer.saturating_sub(frames_skipped);
-        self.sound_timer = self.sound_timer.saturating_sub(frames_skipped);
-        self.last_instant += frame_length * frames_skipped as u32;
+    // Called once per 60Hz frame by whoever drives the emulator.
+    pub fn tick(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
     }
 
     pub fn delay(&self) -> u8 {
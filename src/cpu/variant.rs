@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+use crate::memory::PROGRAM_START;
+
+// Interpreters whose instruction sets differ from CHIP-8, not just in quirks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Variant {
+    #[default]
+    Chip8,
+    // RCA's CHIP-8X for the VP-590 colour board and VP-595 sound board.
+    Chip8X,
+    // The two page hi-res interpreter for the VIP, 64x64 pixels.
+    HiRes,
+}
+
+impl Variant {
+    // By chip-8-database platform id.
+    pub fn from_platform(id: &str) -> Self {
+        match id {
+            "chip8x" => Variant::Chip8X,
+            _ => Variant::Chip8,
+        }
+    }
+
+    // Hi-res programs start with a jump to 0x260.
+    pub fn detect(rom: &[u8]) -> Self {
+        if rom.starts_with(&[0x12, 0x60]) {
+            Variant::HiRes
+        } else {
+            Variant::Chip8
+        }
+    }
+
+    pub fn load_address(self) -> u16 {
+        match self {
+            Variant::Chip8 | Variant::HiRes => PROGRAM_START,
+            // the CHIP-8X interpreter is a page longer
+            Variant::Chip8X => 0x300,
+        }
+    }
+
+    // Width and height of the display.
+    pub fn display_size(self) -> (usize, usize) {
+        match self {
+            Variant::HiRes => (64, 64),
+            Variant::Chip8 | Variant::Chip8X => (64, 32),
+        }
+    }
+}
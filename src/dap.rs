@@ -0,0 +1,864 @@
+// The Debug Adapter Protocol on top of a `Chip8` and a `Debugger`, without
+// the transport, like `gdb::Session`. Source breakpoints and the source
+// lines of stack frames go through assembler symbols, see
+// `coverage::Symbols`. Frames are the pc and the call sites on the stack.
+// Conditions, hover and the watch pane use `debugger::expression`, data
+// breakpoints watch registers or ram, and function breakpoints are
+// expressions that stop when they become true.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    coverage::Symbols,
+    cpu::error::Error,
+    debugger::{
+        error::ExpressionError,
+        expression::{Expression, LogMessage},
+        Debugger, Options, Stop, Watch,
+    },
+    disassemble,
+    memory::{registers::Register, FOUR_K},
+    trace::Record,
+    Chip8,
+};
+
+const THREAD_ID: u64 = 1;
+const REGISTERS: u64 = 1;
+const TIMERS: u64 = 2;
+
+pub struct Session {
+    buffer: Vec<u8>,
+    seq: u64,
+    symbols: Symbols,
+    // What the file names in the symbols are relative to.
+    source_dir: PathBuf,
+    // By source path, replaced on every setBreakpoints.
+    source_breakpoints: HashMap<String, Vec<(u16, Options)>>,
+    instruction_breakpoints: Vec<(u16, Options)>,
+    // Debugger watchpoint ids.
+    data_breakpoints: Vec<u32>,
+    function_breakpoints: Vec<u32>,
+    stop_on_entry: bool,
+    // Continued, so a stopped event is due.
+    running: bool,
+    closed: bool,
+}
+
+impl Session {
+    pub fn new(symbols: Symbols, source_dir: PathBuf) -> Self {
+        Self {
+            buffer: Vec::new(),
+            seq: 0,
+            symbols,
+            source_dir,
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: Vec::new(),
+            data_breakpoints: Vec::new(),
+            function_breakpoints: Vec::new(),
+            stop_on_entry: false,
+            running: false,
+            closed: false,
+        }
+    }
+
+    // After a disconnect, the connection can go.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn receive(
+        &mut self,
+        bytes: &[u8],
+        chip8: &mut Chip8,
+        debugger: &mut Debugger,
+        mut observe: Option<&mut dyn FnMut(&Record)>,
+    ) -> Result<Vec<u8>, Error> {
+        self.buffer.extend_from_slice(bytes);
+        let mut out = Vec::new();
+        while let Some(header_end) = find(&self.buffer, b"\r\n\r\n") {
+            let header = String::from_utf8_lossy(&self.buffer[..header_end]);
+            let length = header.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("content-length")
+                    .then(|| value.trim().parse::<usize>().ok())?
+            });
+            let body_start = header_end + 4;
+            let Some(length) = length else {
+                self.buffer.drain(..body_start);
+                continue;
+            };
+            if self.buffer.len() < body_start.saturating_add(length) {
+                break;
+            }
+            let body: Vec<u8> = self.buffer.drain(..body_start + length).collect();
+            if let Ok(request) = serde_json::from_slice::<Value>(&body[body_start..]) {
+                let observe = observe
+                    .as_mut()
+                    .map(|f| &mut **f as &mut dyn FnMut(&Record));
+                self.handle(&request, chip8, debugger, observe, &mut out)?;
+            }
+        }
+        Ok(out)
+    }
+
+    // The stopped event for a client waiting since it last continued.
+    pub fn stopped(&mut self, stop: Stop) -> Vec<u8> {
+        let mut out = Vec::new();
+        if std::mem::take(&mut self.running) {
+            let reason = self.reason(stop);
+            self.stopped_event(&mut out, reason);
+        }
+        out
+    }
+
+    fn reason(&self, stop: Stop) -> &'static str {
+        match stop {
+            Stop::Breakpoint(_) => "breakpoint",
+            Stop::Watchpoint { id, .. } if self.function_breakpoints.contains(&id) => {
+                "function breakpoint"
+            }
+            Stop::Watchpoint { .. } => "data breakpoint",
+            Stop::Step => "step",
+        }
+    }
+
+    // Logpoint messages and the like, for the debug console.
+    pub fn output(&mut self, text: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        let body = json!({ "category": "console", "output": format!("{text}\n") });
+        self.event(&mut out, "output", body);
+        out
+    }
+
+    // For when the emulator quits.
+    pub fn terminated(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.event(&mut out, "terminated", json!({}));
+        out
+    }
+
+    fn handle(
+        &mut self,
+        request: &Value,
+        chip8: &mut Chip8,
+        debugger: &mut Debugger,
+        observe: Option<&mut dyn FnMut(&Record)>,
+        out: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let args = &request["arguments"];
+        let body = match command {
+            "initialize" => {
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsSetVariable": true,
+                    "supportsReadMemoryRequest": true,
+                    "supportsDisassembleRequest": true,
+                    "supportsInstructionBreakpoints": true,
+                    "supportsConditionalBreakpoints": true,
+                    "supportsHitConditionalBreakpoints": true,
+                    "supportsLogPoints": true,
+                    "supportsFunctionBreakpoints": true,
+                    "supportsDataBreakpoints": true,
+                    "supportsDataBreakpointBytes": true,
+                    "supportsEvaluateForHovers": true,
+                });
+                self.respond(out, request, capabilities);
+                self.event(out, "initialized", json!({}));
+                return Ok(());
+            }
+            "launch" | "attach" => {
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                json!({})
+            }
+            "setBreakpoints" => {
+                let path = args["source"]["path"].as_str().unwrap_or_default();
+                let mut addresses = Vec::new();
+                let breakpoints: Vec<Value> = args["breakpoints"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|bp| {
+                        let line = bp["line"].as_u64().unwrap_or_default() as u32;
+                        match (self.resolve(path, line), options(bp)) {
+                            (Some((line, addr)), Ok(options)) => {
+                                addresses.push((addr, options));
+                                json!({ "verified": true, "line": line })
+                            }
+                            (None, _) => json!({
+                                "verified": false,
+                                "line": line,
+                                "message": "no code on or after this line in the symbols",
+                            }),
+                            (Some(_), Err(e)) => unverified(e),
+                        }
+                    })
+                    .collect();
+                self.source_breakpoints.insert(path.to_owned(), addresses);
+                self.sync_breakpoints(debugger);
+                json!({ "breakpoints": breakpoints })
+            }
+            "setInstructionBreakpoints" => {
+                let mut breakpoints = Vec::new();
+                self.instruction_breakpoints.clear();
+                for bp in args["breakpoints"].as_array().into_iter().flatten() {
+                    let addr = bp["instructionReference"]
+                        .as_str()
+                        .and_then(parse_reference)
+                        .map(|addr| addr + bp["offset"].as_i64().unwrap_or_default())
+                        .filter(|addr| (0..FOUR_K as i64).contains(addr));
+                    breakpoints.push(match (addr, options(bp)) {
+                        (Some(addr), Ok(options)) => {
+                            self.instruction_breakpoints.push((addr as u16, options));
+                            json!({ "verified": true })
+                        }
+                        (None, _) => json!({ "verified": false }),
+                        (Some(_), Err(e)) => unverified(e),
+                    });
+                }
+                self.sync_breakpoints(debugger);
+                json!({ "breakpoints": breakpoints })
+            }
+            "setFunctionBreakpoints" => {
+                for id in self.function_breakpoints.drain(..) {
+                    debugger.remove_watchpoint(id);
+                }
+                let mut breakpoints = Vec::new();
+                for bp in args["breakpoints"].as_array().into_iter().flatten() {
+                    let name = bp["name"].as_str().unwrap_or_default();
+                    let watch = Expression::parse(name).map(Watch::Becomes);
+                    breakpoints.push(match watch.and_then(|w| Ok((w, options(bp)?))) {
+                        Ok((watch, options)) => {
+                            let id = debugger.add_watchpoint(watch, options);
+                            self.function_breakpoints.push(id);
+                            json!({ "verified": true })
+                        }
+                        Err(e) => unverified(e),
+                    });
+                }
+                json!({ "breakpoints": breakpoints })
+            }
+            "dataBreakpointInfo" => {
+                let name = args["name"].as_str().unwrap_or_default();
+                let info = if args["asAddress"].as_bool() == Some(true) {
+                    let addr =
+                        parse_reference(name).filter(|addr| (0..FOUR_K as i64).contains(addr));
+                    let bytes = args["bytes"].as_u64().unwrap_or(1);
+                    addr.map(|addr| {
+                        let id = format!("0x{addr:03X},{bytes}");
+                        (id, json!(["read", "write", "readWrite"]))
+                    })
+                } else if args["variablesReference"].as_u64() == Some(REGISTERS)
+                    && watch_register(name).is_some()
+                {
+                    Some((name.to_owned(), json!(["write"])))
+                } else {
+                    None
+                };
+                match info {
+                    Some((id, access_types)) => json!({
+                        "dataId": id,
+                        "description": format!("{name} changing"),
+                        "accessTypes": access_types,
+                    }),
+                    None => json!({
+                        "dataId": null,
+                        "description": format!("{name} can't be watched"),
+                    }),
+                }
+            }
+            "setDataBreakpoints" => {
+                for id in self.data_breakpoints.drain(..) {
+                    debugger.remove_watchpoint(id);
+                }
+                let mut breakpoints = Vec::new();
+                for bp in args["breakpoints"].as_array().into_iter().flatten() {
+                    let id = bp["dataId"].as_str().unwrap_or_default();
+                    let access = bp["accessType"].as_str().unwrap_or("write");
+                    match (data_watch(id, access), options(bp)) {
+                        (Some(watch), Ok(options)) => {
+                            let id = debugger.add_watchpoint(watch, options);
+                            self.data_breakpoints.push(id);
+                            breakpoints.push(json!({ "verified": true }));
+                        }
+                        (None, _) => breakpoints.push(json!({ "verified": false })),
+                        (Some(_), Err(e)) => breakpoints.push(unverified(e)),
+                    }
+                }
+                json!({ "breakpoints": breakpoints })
+            }
+            "evaluate" => {
+                let expression = args["expression"].as_str().unwrap_or_default();
+                match Expression::parse(expression) {
+                    Ok(expression) => json!({
+                        "result": format!("{:#X}", expression.eval(chip8)),
+                        "variablesReference": 0,
+                    }),
+                    Err(e) => {
+                        self.fail(out, request, &e.to_string());
+                        return Ok(());
+                    }
+                }
+            }
+            "configurationDone" => {
+                self.respond(out, request, json!({}));
+                if self.stop_on_entry {
+                    self.stopped_event(out, "entry");
+                } else {
+                    debugger.resume();
+                    self.running = true;
+                }
+                return Ok(());
+            }
+            "threads" => json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] }),
+            "stackTrace" => self.stack_trace(chip8),
+            "scopes" => json!({
+                "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                    { "name": "Timers", "variablesReference": TIMERS, "expensive": false },
+                ]
+            }),
+            "variables" => {
+                let variables = match args["variablesReference"].as_u64() {
+                    Some(REGISTERS) => registers(chip8),
+                    Some(TIMERS) => vec![
+                        variable("DT", chip8.delay_timer() as u16, 2),
+                        variable("ST", chip8.sound_timer() as u16, 2),
+                    ],
+                    _ => Vec::new(),
+                };
+                json!({ "variables": variables })
+            }
+            "setVariable" => {
+                let name = args["name"].as_str().unwrap_or_default();
+                let value = args["value"]
+                    .as_str()
+                    .and_then(parse_reference)
+                    .and_then(|value| u16::try_from(value).ok());
+                match value.and_then(|value| set_variable(chip8, name, value)) {
+                    Some(value) => json!({ "value": value }),
+                    None => {
+                        self.fail(out, request, &format!("can't set {name} to that"));
+                        return Ok(());
+                    }
+                }
+            }
+            "readMemory" => {
+                let start = args["memoryReference"]
+                    .as_str()
+                    .and_then(parse_reference)
+                    .and_then(|addr| addr.checked_add(args["offset"].as_i64().unwrap_or_default()));
+                let count = args["count"].as_i64().unwrap_or_default();
+                let end = start.and_then(|start| start.checked_add(count));
+                let (Some(start), Some(end)) = (start, end) else {
+                    self.fail(out, request, "bad memory reference");
+                    return Ok(());
+                };
+                let from = start.clamp(0, FOUR_K as i64) as usize;
+                let to = end.clamp(0, FOUR_K as i64) as usize;
+                json!({
+                    "address": format!("0x{start:03X}"),
+                    "data": base64(&chip8.ram()[from..to.max(from)]),
+                    "unreadableBytes": count - (to.max(from) - from) as i64,
+                })
+            }
+            "disassemble" => {
+                let offset = args["offset"].as_i64().unwrap_or_default();
+                let instruction_offset = args["instructionOffset"].as_i64().unwrap_or_default();
+                let count = args["instructionCount"].as_i64().unwrap_or_default();
+                let start = args["memoryReference"]
+                    .as_str()
+                    .and_then(parse_reference)
+                    .and_then(|addr| {
+                        addr.checked_add(offset)?
+                            .checked_add(instruction_offset.checked_mul(2)?)
+                    })
+                    .filter(|start| {
+                        count
+                            .checked_mul(2)
+                            .and_then(|n| start.checked_add(n))
+                            .is_some()
+                    });
+                let Some(start) = start else {
+                    self.fail(out, request, "bad memory reference");
+                    return Ok(());
+                };
+                let instructions: Vec<Value> = (0..count)
+                    .map(|n| self.instruction(chip8, start + 2 * n))
+                    .collect();
+                json!({ "instructions": instructions })
+            }
+            "continue" => {
+                debugger.resume();
+                self.running = true;
+                json!({ "allThreadsContinued": true })
+            }
+            "next" | "stepOut" => {
+                if command == "next" {
+                    debugger.step_over(chip8);
+                } else {
+                    debugger.step_out(chip8);
+                }
+                self.running = true;
+                json!({})
+            }
+            "stepIn" => {
+                self.respond(out, request, json!({}));
+                let stop = debugger.step(chip8, observe)?;
+                let reason = self.reason(stop);
+                self.stopped_event(out, reason);
+                return Ok(());
+            }
+            "pause" => {
+                self.respond(out, request, json!({}));
+                debugger.pause();
+                self.running = false;
+                self.stopped_event(out, "pause");
+                return Ok(());
+            }
+            "disconnect" | "terminate" => {
+                self.source_breakpoints.clear();
+                self.instruction_breakpoints.clear();
+                let watchpoints = self.data_breakpoints.drain(..);
+                for id in watchpoints.chain(self.function_breakpoints.drain(..)) {
+                    debugger.remove_watchpoint(id);
+                }
+                debugger.clear_breakpoints();
+                debugger.resume();
+                self.closed = true;
+                json!({})
+            }
+            _ => {
+                self.fail(out, request, &format!("{command} isn't supported"));
+                return Ok(());
+            }
+        };
+        self.respond(out, request, body);
+        Ok(())
+    }
+
+    // The first instruction on `line` of the source at `path`, or on the next
+    // line with code, like other debuggers do.
+    fn resolve(&self, path: &str, line: u32) -> Option<(u32, u16)> {
+        self.symbols
+            .iter()
+            .filter(|&(_, file, at)| at >= line && Path::new(path).ends_with(file))
+            .map(|(addr, _, at)| (at, addr))
+            .min()
+    }
+
+    // Only touches what changed, so hit counts carry on.
+    fn sync_breakpoints(&self, debugger: &mut Debugger) {
+        let sources = self.source_breakpoints.values().flatten();
+        let wanted: BTreeMap<u16, &Options> = sources
+            .chain(&self.instruction_breakpoints)
+            .map(|(addr, options)| (*addr, options))
+            .collect();
+        let gone: Vec<u16> = debugger
+            .breakpoints()
+            .filter(|addr| !wanted.contains_key(addr))
+            .collect();
+        for addr in gone {
+            debugger.remove_breakpoint(addr);
+        }
+        for (addr, options) in wanted {
+            debugger.set_breakpoint(addr, options.clone());
+        }
+    }
+
+    fn stack_trace(&self, chip8: &Chip8) -> Value {
+        let stack = chip8.stack();
+        let pcs = std::iter::once(chip8.pc()).chain(stack.iter().rev().copied());
+        let frames: Vec<Value> = pcs
+            .enumerate()
+            .map(|(depth, pc)| {
+                // Each frame is in the subroutine called from one level out.
+                let name = match stack.len().checked_sub(depth + 1) {
+                    Some(caller) => format!("sub_{:03X}", opcode(chip8, stack[caller]) & 0xFFF),
+                    None => "main".to_owned(),
+                };
+                let mut frame = json!({
+                    "id": depth,
+                    "name": name,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("0x{pc:03X}"),
+                });
+                if let Some((source, line)) = self.source(pc) {
+                    frame["source"] = source;
+                    frame["line"] = json!(line);
+                    frame["column"] = json!(1);
+                }
+                frame
+            })
+            .collect();
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn instruction(&self, chip8: &Chip8, addr: i64) -> Value {
+        if !(0..FOUR_K as i64 - 1).contains(&addr) {
+            return json!({ "address": format!("0x{addr:03X}"), "instruction": "??" });
+        }
+        let addr = addr as u16;
+        let opcode = opcode(chip8, addr);
+        let mut instruction = json!({
+            "address": format!("0x{addr:03X}"),
+            "instructionBytes": format!("{opcode:04X}"),
+            "instruction": disassemble(opcode, chip8.config().variant),
+        });
+        if let Some((source, line)) = self.source(addr) {
+            instruction["location"] = source;
+            instruction["line"] = json!(line);
+        }
+        instruction
+    }
+
+    fn source(&self, addr: u16) -> Option<(Value, u32)> {
+        let (file, line) = self.symbols.location(addr)?;
+        let path = self.source_dir.join(file);
+        let name = path.file_name().map(|name| name.to_string_lossy());
+        Some((json!({ "name": name, "path": path }), line))
+    }
+
+    fn stopped_event(&mut self, out: &mut Vec<u8>, reason: &str) {
+        let body = json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true });
+        self.event(out, "stopped", body);
+    }
+
+    fn respond(&mut self, out: &mut Vec<u8>, request: &Value, body: Value) {
+        let response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        });
+        self.send(out, response);
+    }
+
+    fn fail(&mut self, out: &mut Vec<u8>, request: &Value, message: &str) {
+        let response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        });
+        self.send(out, response);
+    }
+
+    fn event(&mut self, out: &mut Vec<u8>, event: &str, body: Value) {
+        self.send(
+            out,
+            json!({ "type": "event", "event": event, "body": body }),
+        );
+    }
+
+    fn send(&mut self, out: &mut Vec<u8>, mut message: Value) {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        out.extend(format!("Content-Length: {}\r\n\r\n{body}", body.len()).bytes());
+    }
+}
+
+fn registers(chip8: &Chip8) -> Vec<Value> {
+    let mut variables: Vec<Value> = chip8
+        .registers()
+        .values()
+        .iter()
+        .enumerate()
+        .map(|(n, &value)| variable(&format!("V{n:X}"), value as u16, 2))
+        .collect();
+    let mut i = variable("I", chip8.registers().image, 3);
+    i["memoryReference"] = json!(format!("0x{:03X}", chip8.registers().image));
+    let mut pc = variable("PC", chip8.pc(), 3);
+    pc["memoryReference"] = json!(format!("0x{:03X}", chip8.pc()));
+    variables.extend([i, pc, variable("SP", chip8.stack().len() as u16, 2)]);
+    variables
+}
+
+// The condition, hit condition and log message of a breakpoint.
+fn options(bp: &Value) -> Result<Options, ExpressionError> {
+    let text = |key: &str| bp[key].as_str().filter(|s| !s.trim().is_empty());
+    Ok(Options {
+        condition: text("condition").map(Expression::parse).transpose()?,
+        hit_condition: text("hitCondition").map(str::parse).transpose()?,
+        log_message: text("logMessage").map(LogMessage::parse).transpose()?,
+    })
+}
+
+fn unverified(e: ExpressionError) -> Value {
+    json!({ "verified": false, "message": e.to_string() })
+}
+
+// From the `dataId`s `dataBreakpointInfo` hands out.
+fn data_watch(id: &str, access: &str) -> Option<Watch> {
+    if let Some((addr, bytes)) = id.split_once(',') {
+        let addr = parse_reference(addr)? as usize;
+        let bytes: usize = bytes.parse().ok()?;
+        return Some(Watch::Ram {
+            range: addr..addr + bytes,
+            read: access != "write",
+            write: access != "read",
+        });
+    }
+    watch_register(id)
+}
+
+fn watch_register(name: &str) -> Option<Watch> {
+    if name == "I" {
+        return Some(Watch::I);
+    }
+    let n = name
+        .strip_prefix('V')
+        .and_then(|n| u8::from_str_radix(n, 16).ok())
+        .filter(|&n| n < 16)?;
+    Some(Watch::Register(Register::from(n)))
+}
+
+fn variable(name: &str, value: u16, digits: usize) -> Value {
+    json!({ "name": name, "value": format!("0x{value:0digits$X}"), "variablesReference": 0 })
+}
+
+// The new value as shown, None for read only or unknown names.
+fn set_variable(chip8: &mut Chip8, name: &str, value: u16) -> Option<String> {
+    let byte = u8::try_from(value).ok();
+    match name {
+        "I" => chip8.registers_mut().image = value,
+        "PC" => chip8.set_pc(value),
+        "DT" => chip8.set_delay_timer(byte?),
+        "ST" => chip8.set_sound_timer(byte?),
+        _ => {
+            let n = name
+                .strip_prefix('V')
+                .and_then(|n| u8::from_str_radix(n, 16).ok())
+                .filter(|&n| n < 16)?;
+            chip8.registers_mut()[Register::from(n)] = byte?;
+        }
+    }
+    let digits = if byte.is_some() && !matches!(name, "I" | "PC") {
+        2
+    } else {
+        3
+    };
+    Some(format!("0x{value:0digits$X}"))
+}
+
+fn opcode(chip8: &Chip8, addr: u16) -> u16 {
+    let ram = chip8.ram();
+    let addr = addr as usize;
+    u16::from_be_bytes([ram[addr % FOUR_K], ram[(addr + 1) % FOUR_K]])
+}
+
+// Hex with 0x, or decimal.
+fn parse_reference(s: &str) -> Option<i64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Config;
+
+    #[rustfmt::skip]
+    const ROM: &[u8] = &[
+        0x22, 0x06, // 200 CALL 206     game.8o:1
+        0x60, 0x07, // 202 LD V0, 7     game.8o:2
+        0x12, 0x04, // 204 JP 204       game.8o:3
+        0x61, 0x01, // 206 LD V1, 1     game.8o:6
+        0x62, 0x02, // 208 LD V2, 2     game.8o:7
+        0x00, 0xEE, // 20A RET          game.8o:8
+    ];
+
+    struct Client {
+        session: Session,
+        chip8: Chip8,
+        debugger: Debugger,
+        seq: u64,
+    }
+
+    impl Client {
+        fn new() -> Self {
+            let symbols = Symbols::parse(
+                "0x200 game.8o:1\n0x202 game.8o:2\n0x204 game.8o:3\n\
+                 0x206 game.8o:6\n0x208 game.8o:7\n0x20A game.8o:8\n",
+            )
+            .unwrap();
+            let mut debugger = Debugger::new();
+            debugger.pause();
+            Self {
+                session: Session::new(symbols, PathBuf::from("/src")),
+                chip8: Chip8::new(ROM, Config::default()).unwrap(),
+                debugger,
+                seq: 0,
+            }
+        }
+
+        // Every message sent back.
+        fn request(&mut self, command: &str, arguments: Value) -> Vec<Value> {
+            self.seq += 1;
+            let body = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            })
+            .to_string();
+            let bytes = format!("Content-Length: {}\r\n\r\n{body}", body.len());
+            let out = self
+                .session
+                .receive(bytes.as_bytes(), &mut self.chip8, &mut self.debugger, None)
+                .unwrap();
+            messages(&out)
+        }
+
+        fn run_frame(&mut self) -> Vec<Value> {
+            match self.debugger.run_frame(&mut self.chip8, None).unwrap() {
+                Some(stop) => messages(&self.session.stopped(stop)),
+                None => Vec::new(),
+            }
+        }
+    }
+
+    fn messages(mut out: &[u8]) -> Vec<Value> {
+        let mut messages = Vec::new();
+        while let Some(end) = find(out, b"\r\n\r\n") {
+            let header = std::str::from_utf8(&out[..end]).unwrap();
+            let length: usize = header["Content-Length: ".len()..].parse().unwrap();
+            let body = &out[end + 4..end + 4 + length];
+            messages.push(serde_json::from_slice(body).unwrap());
+            out = &out[end + 4 + length..];
+        }
+        messages
+    }
+
+    #[test]
+    fn source_breakpoints_and_stack_frames() {
+        let mut client = Client::new();
+        let out = client.request("initialize", json!({}));
+        assert_eq!(out[1]["event"], "initialized");
+        client.request("launch", json!({}));
+        let out = client.request(
+            "setBreakpoints",
+            json!({ "source": { "path": "/src/game.8o" }, "breakpoints": [{ "line": 5 }, { "line": 9 }] }),
+        );
+        assert_eq!(
+            out[0]["body"]["breakpoints"],
+            json!([{ "verified": true, "line": 6 }, {
+                "verified": false,
+                "line": 9,
+                "message": "no code on or after this line in the symbols"
+            }])
+        );
+        client.request("configurationDone", json!({}));
+        let out = client.run_frame();
+        assert_eq!(out[0]["body"]["reason"], "breakpoint");
+
+        let out = client.request("stackTrace", json!({ "threadId": 1 }));
+        let frames = &out[0]["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "sub_206");
+        assert_eq!(frames[0]["line"], 6);
+        assert_eq!(frames[0]["source"]["path"], "/src/game.8o");
+        assert_eq!(
+            (&frames[1]["name"], &frames[1]["line"]),
+            (&json!("main"), &json!(1))
+        );
+
+        client.request("stepOut", json!({ "threadId": 1 }));
+        let out = client.run_frame();
+        assert_eq!(out[0]["body"]["reason"], "step");
+        assert_eq!(client.chip8.pc(), 0x202);
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let mut client = Client::new();
+        let out = client.request(
+            "setVariable",
+            json!({ "variablesReference": REGISTERS, "name": "VA", "value": "0x2a" }),
+        );
+        assert_eq!(out[0]["body"]["value"], "0x2A");
+        let out = client.request("variables", json!({ "variablesReference": REGISTERS }));
+        let variables = out[0]["body"]["variables"].as_array().unwrap();
+        assert_eq!(variables[10]["value"], "0x2A");
+        assert_eq!(variables[17]["memoryReference"], "0x200");
+
+        let out = client.request(
+            "readMemory",
+            json!({ "memoryReference": "0x200", "offset": 2, "count": 4 }),
+        );
+        assert_eq!(out[0]["body"]["data"], "YAcSBA==");
+        assert_eq!(out[0]["body"]["unreadableBytes"], 0);
+        assert_eq!(base64(b"ab"), "YWI=");
+
+        let out = client.request(
+            "readMemory",
+            json!({ "memoryReference": "0x200", "count": i64::MAX }),
+        );
+        assert_eq!(out[0]["success"], false);
+    }
+
+    #[test]
+    fn conditions_data_breakpoints_and_evaluate() {
+        let mut client = Client::new();
+        let out = client.request(
+            "setBreakpoints",
+            json!({ "source": { "path": "/src/game.8o" }, "breakpoints": [{ "line": 7, "condition": "V1 =" }] }),
+        );
+        assert_eq!(
+            out[0]["body"]["breakpoints"][0]["message"],
+            "unexpected `=` at column 4"
+        );
+        let out = client.request(
+            "dataBreakpointInfo",
+            json!({ "variablesReference": REGISTERS, "name": "V2" }),
+        );
+        assert_eq!(out[0]["body"]["dataId"], "V2");
+        let out = client.request(
+            "setDataBreakpoints",
+            json!({ "breakpoints": [{ "dataId": "V2" }, { "dataId": "0x300,2", "accessType": "read" }] }),
+        );
+        assert_eq!(out[0]["body"]["breakpoints"][1]["verified"], true);
+        client.request("configurationDone", json!({}));
+        let out = client.run_frame();
+        assert_eq!(out[0]["body"]["reason"], "data breakpoint");
+        assert_eq!(client.chip8.pc(), 0x20A);
+
+        let out = client.request("evaluate", json!({ "expression": "V2 + [0x201]" }));
+        assert_eq!(out[0]["body"]["result"], "0x8");
+    }
+}
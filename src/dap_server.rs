@@ -0,0 +1,115 @@
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, TcpListener},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use anyhow::Context;
+use chipeite::{
+    coverage::Symbols,
+    dap::Session,
+    debugger::{Debugger, Stop},
+    trace::Record,
+    Chip8,
+};
+
+use crate::{machine::DebugServer, structopt::DapTransport};
+
+// `--dap`, for editors. Reads happen on their own thread so stdin works too.
+pub struct DapServer {
+    incoming: Receiver<Vec<u8>>,
+    output: Box<dyn Write>,
+    session: Session,
+    connected: bool,
+}
+
+impl DapServer {
+    // Over TCP this blocks until the editor connects.
+    pub fn new(
+        transport: DapTransport,
+        symbols: Symbols,
+        source_dir: PathBuf,
+    ) -> anyhow::Result<Self> {
+        let (input, output): (Box<dyn Read + Send>, Box<dyn Write>) = match transport {
+            DapTransport::Stdio => (Box::new(io::stdin()), Box::new(io::stdout())),
+            DapTransport::Tcp(port) => {
+                let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+                    .with_context(|| format!("couldn't listen on port {port}"))?;
+                eprintln!("waiting for a DAP client on {}", listener.local_addr()?);
+                let (stream, _) = listener.accept()?;
+                (Box::new(stream.try_clone()?), Box::new(stream))
+            }
+        };
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            let mut input = input;
+            let mut buffer = [0; 4096];
+            while let Ok(n @ 1..) = input.read(&mut buffer) {
+                if sender.send(buffer[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            incoming,
+            output,
+            session: Session::new(symbols, source_dir),
+            connected: true,
+        })
+    }
+
+    fn send(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        if !bytes.is_empty() && self.connected {
+            self.output.write_all(bytes)?;
+            self.output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl DebugServer for DapServer {
+    fn poll(
+        &mut self,
+        chip8: &mut Chip8,
+        debugger: &mut Debugger,
+        observe: Option<&mut dyn FnMut(&Record)>,
+    ) -> anyhow::Result<()> {
+        if !self.connected {
+            return Ok(());
+        }
+        let mut received = Vec::new();
+        let gone = loop {
+            match self.incoming.try_recv() {
+                Ok(bytes) => received.extend(bytes),
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true,
+            }
+        };
+        let reply = self.session.receive(&received, chip8, debugger, observe)?;
+        self.send(&reply)?;
+        if gone || self.session.is_closed() {
+            debugger.clear_breakpoints();
+            debugger.clear_watchpoints();
+            debugger.resume();
+            self.connected = false;
+        }
+        Ok(())
+    }
+
+    fn stopped(&mut self, stop: Stop) -> anyhow::Result<()> {
+        let event = self.session.stopped(stop);
+        self.send(&event)
+    }
+
+    fn log(&mut self, text: &str) -> anyhow::Result<()> {
+        let event = self.session.output(text);
+        self.send(&event)
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        let event = self.session.terminated();
+        self.send(&event)
+    }
+}
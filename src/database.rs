@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+
+use crate::{cpu::quirks::Quirks, memory::key_state::Key};
+
+const PROGRAMS: &str = include_str!("../database/programs.json");
+const HASHES: &str = include_str!("../database/sha1-hashes.json");
+const PLATFORMS: &str = include_str!("../database/platforms.json");
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    tickrate: Option<usize>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, serde_json::Map<String, Value>>,
+    colors: Option<Colors>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Platform {
+    id: String,
+    default_tickrate: Option<usize>,
+    #[serde(default)]
+    quirks: Quirks,
+}
+
+// Everything the database knows about one ROM image.
+#[derive(Debug, Clone)]
+pub struct RomInfo {
+    pub title: String,
+    pub platform: Option<String>,
+    pub tickrate: Option<usize>,
+    pub quirks: Option<Quirks>,
+    pub palette: Vec<[u8; 3]>,
+    // e.g. "up" or "player2A" to the CHIP-8 key the program expects
+    pub keys: HashMap<String, Key>,
+}
+
+pub struct Database {
+    hashes: HashMap<String, usize>,
+    programs: Vec<Program>,
+    platforms: Vec<Platform>,
+}
+
+impl Database {
+    pub fn embedded() -> Self {
+        Self::from_json(PROGRAMS, HASHES, PLATFORMS).expect("embedded rom database is valid")
+    }
+
+    pub fn from_json(programs: &str, hashes: &str, platforms: &str) -> serde_json::Result<Self> {
+        Ok(Self {
+            hashes: serde_json::from_str(hashes)?,
+            programs: serde_json::from_str(programs)?,
+            platforms: serde_json::from_str(platforms)?,
+        })
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<RomInfo> {
+        let hash = sha1_hex(rom);
+        let program = self.programs.get(*self.hashes.get(&hash)?)?;
+        let rom = program.roms.get(&hash)?;
+        let platform = rom.platforms.first();
+        let quirks = platform.and_then(|id| {
+            let quirks = self.platform_quirks(id)?;
+            match rom.quirky_platforms.get(id) {
+                Some(overrides) => with_overrides(quirks, overrides).ok(),
+                None => Some(quirks),
+            }
+        });
+        Some(RomInfo {
+            title: program.title.clone(),
+            platform: platform.cloned(),
+            tickrate: rom
+                .tickrate
+                .or_else(|| platform.and_then(|id| self.platform_tickrate(id))),
+            quirks,
+            palette: rom
+                .colors
+                .iter()
+                .flat_map(|c| c.pixels.iter())
+                .filter_map(|c| parse_color(c))
+                .collect(),
+            keys: rom
+                .keys
+                .iter()
+                .filter(|(_, &key)| key < 16)
+                .map(|(action, &key)| (action.clone(), Key::from(key)))
+                .collect(),
+        })
+    }
+
+    pub fn platform_quirks(&self, id: &str) -> Option<Quirks> {
+        self.platform(id).map(|p| p.quirks)
+    }
+
+    pub fn platform_tickrate(&self, id: &str) -> Option<usize> {
+        self.platform(id)?.default_tickrate
+    }
+
+    fn platform(&self, id: &str) -> Option<&Platform> {
+        self.platforms.iter().find(|p| p.id == id)
+    }
+}
+
+// Applies quirk settings named like in the database on top of `quirks`.
+pub fn with_overrides(
+    quirks: Quirks,
+    overrides: &serde_json::Map<String, Value>,
+) -> Result<Quirks, String> {
+    let Ok(Value::Object(mut merged)) = serde_json::to_value(quirks) else {
+        unreachable!("quirks serialise to an object")
+    };
+    for (name, value) in overrides {
+        if !merged.contains_key(name) {
+            return Err(format!("unknown quirk {name}"));
+        }
+        merged.insert(name.clone(), value.clone());
+    }
+    serde_json::from_value(Value::Object(merged)).map_err(|e| e.to_string())
+}
+
+pub fn sha1_hex(rom: &[u8]) -> String {
+    Sha1::digest(rom)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+pub(crate) fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // pong_1p.ch8 is the same file under another name.
+    #[test]
+    fn bundled_pong_is_recognised() {
+        for file in ["pong.ch8", "pong_1p.ch8"] {
+            let path = format!("{}/roms/{file}", env!("CARGO_MANIFEST_DIR"));
+            let info = Database::embedded()
+                .lookup(&std::fs::read(path).unwrap())
+                .unwrap();
+            assert_eq!(info.title, "Pong");
+            assert_eq!(info.platform.as_deref(), Some("originalChip8"));
+            assert_eq!(info.tickrate, Some(15));
+            assert!(info.quirks.unwrap().vblank);
+            assert_eq!(info.keys["player2Up"], Key::C);
+        }
+    }
+
+    #[test]
+    fn unknown_roms_are_not_found() {
+        assert!(Database::embedded().lookup(&[0x12, 0x00]).is_none());
+    }
+
+    #[test]
+    fn rom_specific_quirks_and_colors_win() {
+        let programs = r##"[{"title": "T", "roms": {"HASH": {
+            "platforms": ["modernChip8"],
+            "tickrate": 40,
+            "quirkyPlatforms": {"modernChip8": {"wrap": true}},
+            "colors": {"pixels": ["#102030", "#ffffff"]}
+        }}}]"##;
+        let rom = [0xAB];
+        let programs = programs.replace("HASH", &sha1_hex(&rom));
+        let hashes = format!(r#"{{"{}": 0}}"#, sha1_hex(&rom));
+        let db = Database::from_json(&programs, &hashes, PLATFORMS).unwrap();
+        let info = db.lookup(&rom).unwrap();
+        assert_eq!(info.tickrate, Some(40));
+        let quirks = info.quirks.unwrap();
+        assert!(quirks.wrap && !quirks.vblank);
+        assert_eq!(info.palette, vec![[0x10, 0x20, 0x30], [0xFF, 0xFF, 0xFF]]);
+    }
+
+    #[test]
+    fn unknown_quirk_overrides_are_rejected() {
+        let overrides = serde_json::from_str(r#"{"bogus": true}"#).unwrap();
+        assert!(with_overrides(Quirks::default(), &overrides).is_err());
+    }
+}
@@ -0,0 +1,524 @@
+pub mod error;
+pub mod expression;
+
+use std::{collections::BTreeMap, ops::Range, str::FromStr};
+
+use crate::{
+    cpu::error::Error,
+    memory::{access::AccessKind, registers::Register, registers::Registers},
+    trace::Record,
+    Chip8,
+};
+
+use self::{
+    error::ExpressionError,
+    expression::{Expression, LogMessage},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    // About to run the instruction at this address.
+    Breakpoint(u16),
+    // Set off by the instruction at `pc`, which has run.
+    Watchpoint { id: u32, pc: u16 },
+    Step,
+}
+
+// Which hits of a breakpoint stop, counting only those where its condition
+// held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitCondition {
+    Equal(u64),
+    AtLeast(u64),
+    Multiple(u64),
+}
+
+impl HitCondition {
+    fn matches(self, hits: u64) -> bool {
+        match self {
+            HitCondition::Equal(n) => hits == n,
+            HitCondition::AtLeast(n) => hits >= n,
+            HitCondition::Multiple(n) => hits.is_multiple_of(n),
+        }
+    }
+}
+
+// `5` or `== 5`, `> 5`, `>= 5` and `% 5`.
+impl FromStr for HitCondition {
+    type Err = ExpressionError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let number = |n: &str| n.trim().parse::<u64>().ok();
+        let condition = if let Some(n) = s.strip_prefix(">=") {
+            number(n).map(HitCondition::AtLeast)
+        } else if let Some(n) = s.strip_prefix('>') {
+            number(n).map(|n| HitCondition::AtLeast(n + 1))
+        } else if let Some(n) = s.strip_prefix('%') {
+            number(n).filter(|&n| n > 0).map(HitCondition::Multiple)
+        } else {
+            number(s.strip_prefix("==").unwrap_or(s)).map(HitCondition::Equal)
+        };
+        condition.ok_or_else(|| ExpressionError::BadHitCondition {
+            condition: s.to_owned(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Options {
+    pub condition: Option<Expression>,
+    pub hit_condition: Option<HitCondition>,
+    // Logs this instead of stopping.
+    pub log_message: Option<LogMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Watch {
+    // Instructions reading or writing any of these bytes of ram.
+    Ram {
+        range: Range<usize>,
+        read: bool,
+        write: bool,
+    },
+    // An instruction changing the register's value.
+    Register(Register),
+    I,
+    // The expression going from false to true.
+    Becomes(Expression),
+}
+
+#[derive(Debug)]
+struct Breakpoint {
+    options: Options,
+    hits: u64,
+}
+
+#[derive(Debug)]
+struct Watchpoint {
+    watch: Watch,
+    options: Options,
+    hits: u64,
+    // For `Watch::Becomes`, the value before the last instruction.
+    was_true: Option<bool>,
+}
+
+// Breakpoints, watchpoints and single-stepping on top of
+// `Chip8::step_in_frame`. While paused, frames don't run at all, so the
+// timers stop too.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: BTreeMap<u16, Breakpoint>,
+    watchpoints: BTreeMap<u32, Watchpoint>,
+    next_watchpoint: u32,
+    // Logpoint messages not yet shown.
+    logs: Vec<String>,
+    paused: bool,
+    // Set when resuming so the breakpoint we're sitting on doesn't hit again.
+    skip_breakpoint: bool,
+    // Stepping over or out of calls, stops once the stack is this deep.
+    step_depth: Option<usize>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // False if there already was one.
+    pub fn add_breakpoint(&mut self, addr: u16) -> bool {
+        if self.breakpoints.contains_key(&addr) {
+            return false;
+        }
+        self.set_breakpoint(addr, Options::default());
+        true
+    }
+
+    // Replaces any breakpoint at `addr`, keeping its hit count if the options
+    // are the same.
+    pub fn set_breakpoint(&mut self, addr: u16, options: Options) {
+        if self
+            .breakpoints
+            .get(&addr)
+            .is_none_or(|bp| bp.options != options)
+        {
+            self.breakpoints
+                .insert(addr, Breakpoint { options, hits: 0 });
+        }
+    }
+
+    // False if there was none.
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr).is_some()
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.keys().copied()
+    }
+
+    // The id `Stop::Watchpoint` reports.
+    pub fn add_watchpoint(&mut self, watch: Watch, options: Options) -> u32 {
+        let id = self.next_watchpoint;
+        self.next_watchpoint += 1;
+        let watchpoint = Watchpoint {
+            watch,
+            options,
+            hits: 0,
+            was_true: None,
+        };
+        self.watchpoints.insert(id, watchpoint);
+        id
+    }
+
+    // False if there was none.
+    pub fn remove_watchpoint(&mut self, id: u32) -> bool {
+        self.watchpoints.remove(&id).is_some()
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn watchpoint(&self, id: u32) -> Option<&Watch> {
+        self.watchpoints.get(&id).map(|wp| &wp.watch)
+    }
+
+    // Logpoint messages since the last call.
+    pub fn take_logs(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.logs)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.step_depth = None;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.skip_breakpoint = true;
+    }
+
+    // Runs exactly one instruction, breakpoints or not, and stays paused.
+    // Watchpoints it sets off are still reported.
+    pub fn step(
+        &mut self,
+        chip8: &mut Chip8,
+        observe: Option<&mut dyn FnMut(&Record)>,
+    ) -> Result<Stop, Error> {
+        self.paused = true;
+        self.skip_breakpoint = false;
+        let pc = chip8.pc();
+        let before = self
+            .arm_watchpoints(chip8)
+            .then(|| chip8.registers().clone());
+        chip8.step_in_frame(observe)?;
+        Ok(match self.watchpoint_hit(before.as_ref(), chip8) {
+            Some(id) => Stop::Watchpoint { id, pc },
+            None => Stop::Step,
+        })
+    }
+
+    // Runs the next instruction, or the whole call if it's a 2nnn, breakpoints
+    // in it aside. Stops with `Stop::Step` from `run_frame`.
+    pub fn step_over(&mut self, chip8: &Chip8) {
+        self.resume();
+        self.step_depth = Some(chip8.stack().len());
+    }
+
+    // Runs until the current subroutine returns, or like `resume` outside of
+    // one.
+    pub fn step_out(&mut self, chip8: &Chip8) {
+        self.resume();
+        self.step_depth = chip8.stack().len().checked_sub(1);
+    }
+
+    // The rest of the current frame, unless a breakpoint or watchpoint comes
+    // first.
+    pub fn run_frame(
+        &mut self,
+        chip8: &mut Chip8,
+        mut observe: Option<&mut dyn FnMut(&Record)>,
+    ) -> Result<Option<Stop>, Error> {
+        if self.paused {
+            return Ok(None);
+        }
+        let watches_registers = self.arm_watchpoints(chip8);
+        loop {
+            let pc = chip8.pc();
+            if !std::mem::take(&mut self.skip_breakpoint) && self.breakpoint_hit(pc, chip8) {
+                self.pause();
+                return Ok(Some(Stop::Breakpoint(pc)));
+            }
+            let before = watches_registers.then(|| chip8.registers().clone());
+            let observe = observe
+                .as_mut()
+                .map(|f| &mut **f as &mut dyn FnMut(&Record));
+            let frame_done = chip8.step_in_frame(observe)?;
+            if let Some(id) = self.watchpoint_hit(before.as_ref(), chip8) {
+                self.pause();
+                return Ok(Some(Stop::Watchpoint { id, pc }));
+            }
+            if self
+                .step_depth
+                .is_some_and(|depth| chip8.stack().len() <= depth)
+            {
+                self.pause();
+                return Ok(Some(Stop::Step));
+            }
+            if frame_done {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn breakpoint_hit(&mut self, pc: u16, chip8: &Chip8) -> bool {
+        match self.breakpoints.get_mut(&pc) {
+            Some(bp) => trigger(&bp.options, &mut bp.hits, chip8, &mut self.logs),
+            None => false,
+        }
+    }
+
+    // The first watchpoint the last instruction set off.
+    fn watchpoint_hit(&mut self, before: Option<&Registers>, chip8: &Chip8) -> Option<u32> {
+        let mut hit = None;
+        for (&id, wp) in &mut self.watchpoints {
+            let fired = match &wp.watch {
+                Watch::Ram { range, read, write } => chip8.accesses().iter().any(|access| {
+                    range.contains(&access.addr)
+                        && match access.kind {
+                            AccessKind::Read => *read,
+                            AccessKind::Write => *write,
+                            AccessKind::Fetch => false,
+                        }
+                }),
+                Watch::Register(reg) => before.is_some_and(|b| b[*reg] != chip8.registers()[*reg]),
+                Watch::I => before.is_some_and(|b| b.image != chip8.registers().image),
+                Watch::Becomes(expression) => {
+                    let now = expression.is_true(chip8);
+                    wp.was_true.replace(now) == Some(false) && now
+                }
+            };
+            // Every watchpoint is checked so they all count hits and log.
+            if fired && trigger(&wp.options, &mut wp.hits, chip8, &mut self.logs) {
+                hit.get_or_insert(id);
+            }
+        }
+        hit
+    }
+
+    // Gets the watchpoints ready to be checked after each instruction, and
+    // whether they need the registers from before it.
+    fn arm_watchpoints(&mut self, chip8: &mut Chip8) -> bool {
+        let watches = self.watchpoints.values().map(|wp| &wp.watch);
+        chip8.track_accesses(watches.clone().any(|w| matches!(w, Watch::Ram { .. })));
+        let watches_registers = watches
+            .clone()
+            .any(|w| matches!(w, Watch::Register(_) | Watch::I));
+        for wp in self.watchpoints.values_mut() {
+            if let Watch::Becomes(expression) = &wp.watch {
+                wp.was_true.get_or_insert_with(|| expression.is_true(chip8));
+            }
+        }
+        watches_registers
+    }
+}
+
+// Whether a breakpoint or watchpoint that went off should stop, after its
+// condition, hit count and logpoint.
+fn trigger(options: &Options, hits: &mut u64, chip8: &Chip8, logs: &mut Vec<String>) -> bool {
+    if !options
+        .condition
+        .as_ref()
+        .is_none_or(|condition| condition.is_true(chip8))
+    {
+        return false;
+    }
+    *hits += 1;
+    if !options
+        .hit_condition
+        .is_none_or(|condition| condition.matches(*hits))
+    {
+        return false;
+    }
+    match &options.log_message {
+        Some(message) => {
+            logs.push(message.format(chip8));
+            false
+        }
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Config;
+
+    #[rustfmt::skip]
+    const ROM: &[u8] = &[
+        0x60, 0x01, // 200 LD V0, 1
+        0x70, 0x01, // 202 ADD V0, 1
+        0x12, 0x02, // 204 JP 202
+    ];
+
+    #[test]
+    fn stops_at_breakpoints_and_steps() {
+        let mut chip8 = Chip8::new(ROM, Config::default()).unwrap();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x204);
+        assert_eq!(
+            debugger.run_frame(&mut chip8, None),
+            Ok(Some(Stop::Breakpoint(0x204)))
+        );
+        assert_eq!(chip8.registers().values()[0], 2);
+        assert_eq!(debugger.run_frame(&mut chip8, None), Ok(None));
+        assert_eq!(chip8.pc(), 0x204);
+
+        assert_eq!(debugger.step(&mut chip8, None), Ok(Stop::Step));
+        assert_eq!(chip8.pc(), 0x202);
+
+        // Resuming on a breakpoint runs past it, then it hits again.
+        debugger.step(&mut chip8, None).unwrap();
+        debugger.resume();
+        assert_eq!(
+            debugger.run_frame(&mut chip8, None),
+            Ok(Some(Stop::Breakpoint(0x204)))
+        );
+        debugger.resume();
+        assert_eq!(
+            debugger.run_frame(&mut chip8, None),
+            Ok(Some(Stop::Breakpoint(0x204)))
+        );
+        assert_eq!(chip8.registers().values()[0], 5);
+        assert_eq!(chip8.frames(), 0);
+    }
+
+    #[test]
+    fn steps_over_and_out_of_calls() {
+        #[rustfmt::skip]
+        let rom = [
+            0x22, 0x06, // 200 CALL 206
+            0x60, 0x07, // 202 LD V0, 7
+            0x12, 0x04, // 204 JP 204
+            0x61, 0x01, // 206 LD V1, 1
+            0x62, 0x02, // 208 LD V2, 2
+            0x00, 0xEE, // 20A RET
+        ];
+        let mut chip8 = Chip8::new(&rom, Config::default()).unwrap();
+        let mut debugger = Debugger::new();
+        debugger.pause();
+        debugger.step_over(&chip8);
+        assert_eq!(debugger.run_frame(&mut chip8, None), Ok(Some(Stop::Step)));
+        assert_eq!((chip8.pc(), chip8.registers().values()[2]), (0x202, 2));
+
+        chip8.set_pc(0x200);
+        debugger.step(&mut chip8, None).unwrap();
+        debugger.step(&mut chip8, None).unwrap();
+        debugger.step_out(&chip8);
+        assert_eq!(debugger.run_frame(&mut chip8, None), Ok(Some(Stop::Step)));
+        assert_eq!(chip8.pc(), 0x202);
+    }
+
+    fn run_until_stop(debugger: &mut Debugger, chip8: &mut Chip8) -> Stop {
+        (0..10)
+            .find_map(|_| debugger.run_frame(chip8, None).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn conditions_hit_counts_and_logpoints() {
+        let mut chip8 = Chip8::new(ROM, Config::default()).unwrap();
+        let mut debugger = Debugger::new();
+        let options = Options {
+            condition: Some(Expression::parse("V0 >= 4").unwrap()),
+            hit_condition: Some("% 2".parse().unwrap()),
+            log_message: None,
+        };
+        debugger.set_breakpoint(0x204, options);
+        assert_eq!(
+            run_until_stop(&mut debugger, &mut chip8),
+            Stop::Breakpoint(0x204)
+        );
+        assert_eq!(chip8.registers().values()[0], 5);
+
+        let mut chip8 = Chip8::new(ROM, Config::default()).unwrap();
+        let mut debugger = Debugger::new();
+        let log = Options {
+            log_message: Some(LogMessage::parse("v0={V0}").unwrap()),
+            ..Options::default()
+        };
+        debugger.set_breakpoint(0x202, log);
+        let third = Options {
+            hit_condition: Some(HitCondition::Equal(3)),
+            ..Options::default()
+        };
+        debugger.set_breakpoint(0x204, third);
+        run_until_stop(&mut debugger, &mut chip8);
+        assert_eq!(chip8.registers().values()[0], 4);
+        assert_eq!(debugger.take_logs(), ["v0=0x1", "v0=0x2", "v0=0x3"]);
+
+        assert_eq!(">2".parse(), Ok(HitCondition::AtLeast(3)));
+        assert!("% 0".parse::<HitCondition>().is_err());
+    }
+
+    #[test]
+    fn watchpoints() {
+        let mut chip8 = Chip8::new(ROM, Config::default()).unwrap();
+        let mut debugger = Debugger::new();
+        let three = Options {
+            condition: Some(Expression::parse("V0 == 3").unwrap()),
+            ..Options::default()
+        };
+        let v0 = debugger.add_watchpoint(Watch::Register(Register::V0), three);
+        assert_eq!(
+            run_until_stop(&mut debugger, &mut chip8),
+            Stop::Watchpoint { id: v0, pc: 0x202 }
+        );
+        assert_eq!(chip8.pc(), 0x204);
+
+        debugger.remove_watchpoint(v0);
+        let becomes = Watch::Becomes(Expression::parse("V0 > 5").unwrap());
+        let id = debugger.add_watchpoint(becomes, Options::default());
+        debugger.resume();
+        assert_eq!(
+            run_until_stop(&mut debugger, &mut chip8),
+            Stop::Watchpoint { id, pc: 0x202 }
+        );
+        assert_eq!(chip8.registers().values()[0], 6);
+        debugger.clear_watchpoints();
+
+        #[rustfmt::skip]
+        let rom = [
+            0xA3, 0x00, // 200 LD I, 300
+            0xF0, 0x55, // 202 LD [I], V0
+            0xF0, 0x65, // 204 LD V0, [I]
+            0x12, 0x04, // 206 JP 204
+        ];
+        let mut chip8 = Chip8::new(&rom, Config::default()).unwrap();
+        let mut debugger = Debugger::new();
+        // LD [I], V0 moves I on to 301.
+        let written = Watch::Ram {
+            range: 0x301..0x302,
+            read: false,
+            write: true,
+        };
+        debugger.add_watchpoint(written, Options::default());
+        let read = Watch::Ram {
+            range: 0x300..0x302,
+            read: true,
+            write: false,
+        };
+        let id = debugger.add_watchpoint(read, Options::default());
+        assert_eq!(
+            run_until_stop(&mut debugger, &mut chip8),
+            Stop::Watchpoint { id, pc: 0x204 }
+        );
+    }
+}
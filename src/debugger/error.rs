@@ -0,0 +1,30 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpressionError {
+    UnexpectedEnd,
+    // `at` is a byte offset into the expression.
+    Unexpected { at: usize, found: String },
+    UnknownName { at: usize, name: String },
+    BadHitCondition { condition: String },
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpressionError::UnexpectedEnd => write!(f, "the expression ends too early"),
+            ExpressionError::Unexpected { at, found } => {
+                write!(f, "unexpected `{found}` at column {}", at + 1)
+            }
+            ExpressionError::UnknownName { at, name } => {
+                write!(f, "unknown name `{name}` at column {}", at + 1)
+            }
+            ExpressionError::BadHitCondition { condition } => write!(
+                f,
+                "hit condition `{condition}` should be like `5`, `>= 5` or `% 5`"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExpressionError {}
@@ -0,0 +1,331 @@
+// Conditions for breakpoints and watchpoints, e.g. `V3 == 0x1F && I > 0x300`.
+// Names are V0-VF, I, PC, SP (the stack depth), DT and ST, `[addr]` is the
+// byte of ram there, numbers are decimal or hex with 0x or #, and the
+// operators are C's. Everything is an i64, comparisons give 0 or 1 and
+// dividing by zero gives 0.
+
+use crate::Chip8;
+
+use super::error::ExpressionError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expression(Node);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Number(i64),
+    Name(Name),
+    Ram(Box<Node>),
+    Unary(&'static str, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Name {
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+// Longest first so `<=` isn't read as `<`.
+const OPERATORS: [&str; 24] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "<", ">", "|", "^", "&", "+", "-", "*", "/",
+    "%", "!", "~", "(", ")", "[", "]",
+];
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Self, ExpressionError> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            pos: 0,
+        };
+        let node = parser.binary(0)?;
+        match parser.tokens.get(parser.pos) {
+            Some((at, token)) => Err(unexpected(*at, token)),
+            None => Ok(Self(node)),
+        }
+    }
+
+    pub fn eval(&self, chip8: &Chip8) -> i64 {
+        eval(&self.0, chip8)
+    }
+
+    pub fn is_true(&self, chip8: &Chip8) -> bool {
+        self.eval(chip8) != 0
+    }
+}
+
+// A logpoint message, text with `{expression}`s in it, shown in hex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogMessage(Vec<(String, Option<Expression>)>);
+
+impl LogMessage {
+    pub fn parse(text: &str) -> Result<Self, ExpressionError> {
+        let mut parts = Vec::new();
+        let mut rest = text;
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..]
+                .find('}')
+                .ok_or(ExpressionError::UnexpectedEnd)?;
+            let expression = Expression::parse(&rest[open + 1..open + close])?;
+            parts.push((rest[..open].to_owned(), Some(expression)));
+            rest = &rest[open + close + 1..];
+        }
+        parts.push((rest.to_owned(), None));
+        Ok(Self(parts))
+    }
+
+    pub fn format(&self, chip8: &Chip8) -> String {
+        let mut out = String::new();
+        for (text, expression) in &self.0 {
+            out.push_str(text);
+            if let Some(expression) = expression {
+                out.push_str(&format!("{:#X}", expression.eval(chip8)));
+            }
+        }
+        out
+    }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    // Precedence climbing, `min` is the loosest operator taken.
+    fn binary(&mut self, min: u8) -> Result<Node, ExpressionError> {
+        let mut lhs = self.unary()?;
+        while let Some((_, Token::Op(op))) = self.tokens.get(self.pos) {
+            let Some(precedence) = precedence(op).filter(|&p| p >= min) else {
+                break;
+            };
+            let op = *op;
+            self.pos += 1;
+            let rhs = self.binary(precedence + 1)?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Node, ExpressionError> {
+        let (at, token) = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or(ExpressionError::UnexpectedEnd)?;
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(Node::Number(n)),
+            Token::Name(name) => Ok(Node::Name(lookup(at, &name)?)),
+            Token::Op(op @ ("!" | "-" | "~")) => Ok(Node::Unary(op, Box::new(self.unary()?))),
+            Token::Op(open @ ("(" | "[")) => {
+                let inner = self.binary(0)?;
+                let close = if open == "(" { ")" } else { "]" };
+                match self.tokens.get(self.pos) {
+                    Some((_, Token::Op(op))) if *op == close => self.pos += 1,
+                    Some((at, token)) => return Err(unexpected(*at, token)),
+                    None => return Err(ExpressionError::UnexpectedEnd),
+                }
+                Ok(if open == "(" {
+                    inner
+                } else {
+                    Node::Ram(Box::new(inner))
+                })
+            }
+            token => Err(unexpected(at, &token)),
+        }
+    }
+}
+
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "==" | "!=" => 3,
+        "<" | "<=" | ">" | ">=" => 4,
+        "|" => 5,
+        "^" => 6,
+        "&" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        _ => return None,
+    })
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let mut tokens = Vec::new();
+    let mut at = 0;
+    while at < text.len() {
+        let rest = &text[at..];
+        let c = rest.chars().next().unwrap_or_default();
+        if c.is_whitespace() {
+            at += c.len_utf8();
+            continue;
+        }
+        if c.is_ascii_alphanumeric() || c == '#' || c == '_' {
+            let len = rest[1..]
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .map_or(rest.len(), |n| n + 1);
+            let word = &rest[..len];
+            let token = if c.is_ascii_digit() || c == '#' {
+                let number = match word
+                    .strip_prefix("0x")
+                    .or_else(|| word.strip_prefix("0X"))
+                    .or_else(|| word.strip_prefix('#'))
+                {
+                    Some(hex) => i64::from_str_radix(hex, 16),
+                    None => word.parse(),
+                };
+                Token::Number(number.map_err(|_| ExpressionError::Unexpected {
+                    at,
+                    found: word.to_owned(),
+                })?)
+            } else {
+                Token::Name(word.to_owned())
+            };
+            tokens.push((at, token));
+            at += len;
+            continue;
+        }
+        let op = OPERATORS
+            .iter()
+            .find(|op| rest.starts_with(**op))
+            .ok_or_else(|| ExpressionError::Unexpected {
+                at,
+                found: c.to_string(),
+            })?;
+        tokens.push((at, Token::Op(op)));
+        at += op.len();
+    }
+    Ok(tokens)
+}
+
+fn lookup(at: usize, name: &str) -> Result<Name, ExpressionError> {
+    Ok(match name.to_ascii_uppercase().as_str() {
+        "I" => Name::I,
+        "PC" => Name::Pc,
+        "SP" => Name::Sp,
+        "DT" => Name::Dt,
+        "ST" => Name::St,
+        reg => match reg.strip_prefix('V').map(|n| u8::from_str_radix(n, 16)) {
+            Some(Ok(n)) if n < 16 => Name::V(n),
+            _ => {
+                return Err(ExpressionError::UnknownName {
+                    at,
+                    name: name.to_owned(),
+                })
+            }
+        },
+    })
+}
+
+fn unexpected(at: usize, token: &Token) -> ExpressionError {
+    let found = match token {
+        Token::Number(n) => n.to_string(),
+        Token::Name(name) => name.clone(),
+        Token::Op(op) => op.to_string(),
+    };
+    ExpressionError::Unexpected { at, found }
+}
+
+fn eval(node: &Node, chip8: &Chip8) -> i64 {
+    match node {
+        Node::Number(n) => *n,
+        Node::Name(name) => match *name {
+            Name::V(n) => chip8.registers().values()[n as usize] as i64,
+            Name::I => chip8.registers().image as i64,
+            Name::Pc => chip8.pc() as i64,
+            Name::Sp => chip8.stack().len() as i64,
+            Name::Dt => chip8.delay_timer() as i64,
+            Name::St => chip8.sound_timer() as i64,
+        },
+        Node::Ram(addr) => usize::try_from(eval(addr, chip8))
+            .ok()
+            .and_then(|addr| chip8.ram().get(addr))
+            .map_or(0, |&byte| byte as i64),
+        Node::Unary(op, x) => {
+            let x = eval(x, chip8);
+            match *op {
+                "!" => (x == 0) as i64,
+                "-" => x.wrapping_neg(),
+                _ => !x,
+            }
+        }
+        Node::Binary("&&", a, b) => (eval(a, chip8) != 0 && eval(b, chip8) != 0) as i64,
+        Node::Binary("||", a, b) => (eval(a, chip8) != 0 || eval(b, chip8) != 0) as i64,
+        Node::Binary(op, a, b) => {
+            let (a, b) = (eval(a, chip8), eval(b, chip8));
+            match *op {
+                "==" => (a == b) as i64,
+                "!=" => (a != b) as i64,
+                "<" => (a < b) as i64,
+                "<=" => (a <= b) as i64,
+                ">" => (a > b) as i64,
+                ">=" => (a >= b) as i64,
+                "|" => a | b,
+                "^" => a ^ b,
+                "&" => a & b,
+                "<<" => a.wrapping_shl(b as u32),
+                ">>" => a.wrapping_shr(b as u32),
+                "+" => a.wrapping_add(b),
+                "-" => a.wrapping_sub(b),
+                "*" => a.wrapping_mul(b),
+                "/" => a.checked_div(b).unwrap_or(0),
+                _ => a.checked_rem(b).unwrap_or(0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn evaluates_against_the_machine() {
+        // LD V3, #1F; LD I, #310
+        let mut chip8 = Chip8::new(&[0x63, 0x1F, 0xA3, 0x10], Config::default()).unwrap();
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        let eval = |text: &str| Expression::parse(text).unwrap().eval(&chip8);
+        assert_eq!(eval("V3 == 0x1F && I > 0x300"), 1);
+        assert_eq!(eval("v3 + 1 * 2 == #21"), 1);
+        assert_eq!(eval("[0x200] << 8 | [PC - 3]"), 0x631F);
+        assert_eq!(eval("-(1 + 2) / 0 || !SP"), 1);
+
+        assert_eq!(
+            Expression::parse("V3 = 1"),
+            Err(ExpressionError::Unexpected {
+                at: 3,
+                found: "=".to_owned()
+            })
+        );
+        assert_eq!(
+            Expression::parse("VG > 1"),
+            Err(ExpressionError::UnknownName {
+                at: 0,
+                name: "VG".to_owned()
+            })
+        );
+        assert_eq!(
+            Expression::parse("(V0 + 1"),
+            Err(ExpressionError::UnexpectedEnd)
+        );
+
+        let message = LogMessage::parse("score {V3} at {[I]}").unwrap();
+        assert_eq!(message.format(&chip8), "score 0x1F at 0x0");
+    }
+}
@@ -0,0 +1,166 @@
+use std::{collections::VecDeque, fs};
+
+use anyhow::{anyhow, bail, Context};
+use chipeite::{
+    trace::{
+        reference::{self, Mismatch},
+        Record,
+    },
+    Chip8, Key,
+};
+
+use crate::structopt::DiffTraceOpt;
+
+// How long to keep running for the instructions after a divergence, in case
+// the ROM is stuck waiting for a key.
+const AFTER_FRAMES: u64 = 60;
+
+struct KeyEvent {
+    frame: u64,
+    key: Key,
+    pressed: bool,
+}
+
+struct Divergence {
+    index: usize,
+    record: Record,
+    mismatches: Vec<Mismatch>,
+}
+
+// Runs the ROM until its trace leaves the reference and reports where.
+pub fn run(opts: &DiffTraceOpt) -> anyhow::Result<()> {
+    let (rom, settings) = crate::load(&opts.run)?;
+    let reference = fs::read_to_string(&opts.reference)
+        .with_context(|| format!("couldn't read {}", opts.reference.display()))?;
+    let reference = reference::parse(&reference)
+        .with_context(|| format!("couldn't parse {}", opts.reference.display()))?;
+    let script = match &opts.input {
+        Some(path) => {
+            let script = fs::read_to_string(path)
+                .with_context(|| format!("couldn't read {}", path.display()))?;
+            parse_script(&script).with_context(|| format!("couldn't parse {}", path.display()))?
+        }
+        None => Vec::new(),
+    };
+    let mut chip8 = Chip8::new(&rom.bytes, settings.config)?;
+
+    let mut history = VecDeque::with_capacity(opts.context);
+    let mut divergence = None;
+    let mut after = Vec::with_capacity(opts.context);
+    let mut frames_after = 0;
+    let mut index = 0;
+    loop {
+        match divergence {
+            None if index >= reference.len() => break,
+            Some(_) if after.len() >= opts.context || frames_after == AFTER_FRAMES => break,
+            Some(_) => frames_after += 1,
+            None => {}
+        }
+        let frame = chip8.frames();
+        for event in script.iter().filter(|e| e.frame == frame) {
+            if event.pressed {
+                chip8.press_key(event.key);
+            } else {
+                chip8.release_key(event.key);
+            }
+        }
+        let result = chip8.run_frame_with(&mut |record| {
+            if divergence.is_some() {
+                if after.len() < opts.context {
+                    after.push(record.clone());
+                }
+                return;
+            }
+            let Some(expected) = reference.get(index) else {
+                return;
+            };
+            let mismatches = expected.diff(record);
+            if !mismatches.is_empty() {
+                divergence = Some(Divergence {
+                    index,
+                    record: record.clone(),
+                    mismatches,
+                });
+                return;
+            }
+            if opts.context > 0 {
+                while history.len() >= opts.context {
+                    history.pop_front();
+                }
+                history.push_back(record.clone());
+            }
+            index += 1;
+        });
+        if let Err(e) = result {
+            if divergence.is_none() {
+                bail!("the ROM stopped after {index} matching instructions: {e}");
+            }
+            break;
+        }
+    }
+
+    let Some(divergence) = divergence else {
+        println!("all {} instructions match", reference.len());
+        return Ok(());
+    };
+    println!(
+        "diverged at instruction {} (frame {}):",
+        divergence.index, divergence.record.frame
+    );
+    for record in &history {
+        println!("  {record}");
+    }
+    println!("> {}", divergence.record);
+    for Mismatch {
+        field,
+        expected,
+        actual,
+    } in &divergence.mismatches
+    {
+        println!("  {field:<6} expected {expected}, got {actual}");
+    }
+    if opts.context > 0 {
+        println!("then this ROM ran:");
+        for record in &after {
+            println!("  {record}");
+        }
+        println!("and the reference:");
+        for expected in reference
+            .iter()
+            .skip(divergence.index + 1)
+            .take(opts.context)
+        {
+            println!("  {expected}");
+        }
+    }
+    bail!("the traces diverge")
+}
+
+// `frame key down|up` per line, keys in hex, # starts a comment.
+fn parse_script(text: &str) -> anyhow::Result<Vec<KeyEvent>> {
+    let mut events = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bad_line = || anyhow!("line {} should be `frame key down|up`", n + 1);
+        let [frame, key, action] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+            return Err(bad_line());
+        };
+        let key = u8::from_str_radix(key, 16)
+            .ok()
+            .filter(|&key| key < 16)
+            .ok_or_else(bad_line)?;
+        events.push(KeyEvent {
+            frame: frame.parse().map_err(|_| bad_line())?,
+            key: Key::from(key),
+            pressed: match action {
+                "down" => true,
+                "up" => false,
+                _ => return Err(bad_line()),
+            },
+        });
+    }
+    Ok(events)
+}
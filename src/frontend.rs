@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+
+use chipeite::Key;
+
+#[cfg(any(feature = "sdl", feature = "tui"))]
+use crate::machine::Machine;
+
+#[cfg(any(feature = "sdl", feature = "tui"))]
+pub trait Frontend {
+    // Feeds key changes to `f`, false once the user asked to quit.
+    fn poll_keys<F: FnMut(Input, bool)>(&mut self, f: F) -> anyhow::Result<bool>;
+
+    // The machine's framebuffer, plus whatever else the frontend shows of it.
+    fn draw(&mut self, machine: &dyn Machine) -> anyhow::Result<()>;
+
+    fn beep(&mut self, on: bool);
+
+    // e.g. `paused, 15 ipf`, shown next to the title.
+    fn set_status(&mut self, status: &str);
+}
+
+#[cfg(any(feature = "sdl", feature = "tui"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Key(Key),
+    // CHIP-8X's second hex keypad.
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    SecondKey(Key),
+    Command(Command),
+}
+
+// Emulator controls, from hotkeys or the overlay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    // Pauses or resumes.
+    Pause,
+    // Runs one frame and pauses.
+    Advance,
+    // One instruction.
+    Step,
+    // Runs flat out while held.
+    FastForward,
+    // Toggles running at a quarter of the speed.
+    SlowMotion,
+    // Cycles through the instructions per frame presets.
+    Speed,
+    // Starts the ROM over.
+    Reset,
+    // Reads the ROM file again and starts it over.
+    Reload,
+    // Writes the machine to the --state file.
+    SaveState,
+    // From the memory view.
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    Poke {
+        addr: u16,
+        value: u8,
+    },
+}
+
+// The names hotkeys are bound by in the config file.
+const COMMAND_NAMES: [(&str, Command); 9] = [
+    ("pause", Command::Pause),
+    ("frameAdvance", Command::Advance),
+    ("step", Command::Step),
+    ("fastForward", Command::FastForward),
+    ("slowMotion", Command::SlowMotion),
+    ("speed", Command::Speed),
+    ("reset", Command::Reset),
+    ("reload", Command::Reload),
+    ("saveState", Command::SaveState),
+];
+
+impl Command {
+    fn from_name(name: &str) -> Option<Self> {
+        COMMAND_NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, command)| command)
+    }
+
+    fn name(self) -> &'static str {
+        COMMAND_NAMES
+            .iter()
+            .find(|&&(_, command)| command == self)
+            .map_or("poke", |(name, _)| name)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(not(any(feature = "sdl", feature = "tui")), allow(dead_code))]
+pub struct Palette {
+    pub background: [u8; 3],
+    pub foreground: [u8; 3],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            background: [0, 0, 0],
+            foreground: [255, 255, 255],
+        }
+    }
+}
+
+// Host keys beyond the hex keypad, e.g. the arrow keys, bound by name
+// ("up", "player2A", ...) to the CHIP-8 key a program expects.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(not(any(feature = "sdl", feature = "tui")), allow(dead_code))]
+pub struct Keymap(HashMap<String, Key>);
+
+impl Keymap {
+    pub fn new(bindings: HashMap<String, Key>) -> Self {
+        Self(bindings)
+    }
+
+    #[cfg(any(feature = "sdl", feature = "tui"))]
+    pub fn get(&self, action: &str) -> Option<Key> {
+        self.0.get(action).copied()
+    }
+
+    // Whether `key`, by hotkey name, presses a CHIP-8 key for this program.
+    fn uses(&self, key: &str) -> bool {
+        KEYMAP_KEYS
+            .iter()
+            .any(|&(host, action)| host == key && self.0.contains_key(action))
+    }
+}
+
+// The keymap's host keys that have hotkey names, the arrow keys don't.
+const KEYMAP_KEYS: [(&str, &str); 8] = [
+    ("space", "a"),
+    ("return", "b"),
+    ("i", "player2Up"),
+    ("k", "player2Down"),
+    ("j", "player2Left"),
+    ("l", "player2Right"),
+    ("u", "player2A"),
+    ("o", "player2B"),
+];
+
+// Host keys for the emulator's own controls, by key name in lowercase, e.g.
+// "f5", "tab" or "p".
+#[derive(Debug, Clone)]
+pub struct Hotkeys(HashMap<String, Command>);
+
+impl Hotkeys {
+    #[cfg(any(feature = "sdl", feature = "tui", test))]
+    pub fn get(&self, key: &str) -> Option<Command> {
+        self.0.get(key).copied()
+    }
+
+    // Moves each action, e.g. "fastForward", to its key. The others keep
+    // their keys, which can't be taken from them, and neither can the hex
+    // keys or the keys `keymap` uses.
+    pub fn rebind(
+        &mut self,
+        bindings: &HashMap<String, String>,
+        keymap: &Keymap,
+    ) -> anyhow::Result<()> {
+        let mut bindings: Vec<_> = bindings.iter().collect();
+        bindings.sort();
+        let mut commands = Vec::new();
+        for (action, key) in bindings {
+            let command = Command::from_name(action)
+                .ok_or_else(|| anyhow::anyhow!("unknown hotkey action {action}"))?;
+            let key = key.to_lowercase();
+            if !is_hotkey_name(&key) {
+                anyhow::bail!("{action} can't be bound to {key}, see the README for the keys");
+            }
+            if RESERVED_KEYS.contains(&key.as_str()) {
+                anyhow::bail!("{action} can't be bound to {key}, which opens a debug panel");
+            }
+            let hex_key = key.len() == 1 && key.chars().all(|c| c.is_ascii_hexdigit());
+            if hex_key || keymap.uses(&key) {
+                anyhow::bail!("{action} can't be bound to {key}, which the keypad uses");
+            }
+            commands.push((key, command));
+        }
+        self.0
+            .retain(|_, bound| commands.iter().all(|(_, command)| command != bound));
+        for (key, command) in commands {
+            if let Some(other) = self.0.insert(key.clone(), command) {
+                anyhow::bail!(
+                    "{} and {} are both bound to {key}",
+                    other.name(),
+                    command.name()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+// F1-F3 toggle the debug panels in the window.
+const RESERVED_KEYS: [&str; 3] = ["f1", "f2", "f3"];
+
+// Letters, digits, F1-F12, tab, space, return and backspace.
+fn is_hotkey_name(key: &str) -> bool {
+    let function_key = key
+        .strip_prefix('f')
+        .and_then(|n| n.parse::<u8>().ok())
+        .is_some_and(|n| (1..=12).contains(&n) && !key.starts_with("f0"));
+    let mut chars = key.chars();
+    let single =
+        matches!((chars.next(), chars.next()), (Some(c), None) if c.is_ascii_alphanumeric());
+    single || function_key || ["tab", "space", "return", "backspace"].contains(&key)
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Self(HashMap::from([
+            ("f5".to_owned(), Command::Pause),
+            ("f6".to_owned(), Command::Advance),
+            ("f10".to_owned(), Command::Step),
+            ("tab".to_owned(), Command::FastForward),
+            ("f7".to_owned(), Command::SlowMotion),
+            ("f8".to_owned(), Command::Speed),
+            ("f9".to_owned(), Command::Reset),
+            ("f12".to_owned(), Command::Reload),
+            ("f4".to_owned(), Command::SaveState),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rebind_with(keymap: &Keymap, bindings: &[(&str, &str)]) -> anyhow::Result<Hotkeys> {
+        let mut hotkeys = Hotkeys::default();
+        let bindings = bindings
+            .iter()
+            .map(|&(action, key)| (action.to_owned(), key.to_owned()))
+            .collect();
+        hotkeys.rebind(&bindings, keymap).map(|()| hotkeys)
+    }
+
+    fn rebind(bindings: &[(&str, &str)]) -> anyhow::Result<Hotkeys> {
+        rebind_with(&Keymap::default(), bindings)
+    }
+
+    #[test]
+    fn hotkeys_are_validated() {
+        let hotkeys = rebind(&[("pause", "P"), ("fastForward", "f5")]).unwrap();
+        assert_eq!(hotkeys.get("p"), Some(Command::Pause));
+        assert_eq!(hotkeys.get("f5"), Some(Command::FastForward));
+        assert_eq!(hotkeys.get("tab"), None);
+
+        let error = rebind(&[("pause", "tab")]).unwrap_err().to_string();
+        assert_eq!(error, "fastForward and pause are both bound to tab");
+        assert!(rebind(&[("pause", "p"), ("step", "p")]).is_err());
+        assert!(rebind(&[("pause", "f2")]).is_err());
+        assert!(rebind(&[("pause", "f13")]).is_err());
+        assert!(rebind(&[("pause", "escape")]).is_err());
+        assert!(rebind(&[("pause", "backspace")]).is_ok());
+        assert!(rebind(&[("pausing", "p")]).is_err());
+    }
+
+    #[test]
+    fn keypad_keys_are_not_hotkeys() {
+        let error = rebind(&[("pause", "B")]).unwrap_err().to_string();
+        assert_eq!(error, "pause can't be bound to b, which the keypad uses");
+        assert!(rebind(&[("pause", "7")]).is_err());
+        assert!(rebind(&[("pause", "g")]).is_ok());
+
+        let keymap = Keymap::new(HashMap::from([
+            ("a".to_owned(), Key::Six),
+            ("player2Up".to_owned(), Key::Two),
+        ]));
+        assert!(rebind_with(&keymap, &[("pause", "space")]).is_err());
+        assert!(rebind_with(&keymap, &[("pause", "i")]).is_err());
+        assert!(rebind_with(&keymap, &[("pause", "return")]).is_ok());
+        assert!(rebind_with(&keymap, &[("pause", "k")]).is_ok());
+    }
+}
@@ -0,0 +1,582 @@
+// The GDB remote serial protocol on top of a `Chip8` and a `Debugger`,
+// without the socket: hand `Session::receive` whatever the client sent and
+// send back what it returns. Registers are v0-vf, i, pc, sp (the stack
+// depth, read only), dt and st, the 16 bit ones little endian, and memory is
+// ram. Watchpoints are on ram.
+
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{
+    cpu::error::Error,
+    debugger::{Debugger, Options, Stop, Watch},
+    memory::FOUR_K,
+    trace::Record,
+    Chip8,
+};
+
+// The biggest packet we take, in bytes.
+const PACKET_SIZE: usize = 0x1000;
+
+// Names and sizes in bytes, in gdb register number order.
+const REGISTERS: [(&str, usize); 21] = [
+    ("v0", 1),
+    ("v1", 1),
+    ("v2", 1),
+    ("v3", 1),
+    ("v4", 1),
+    ("v5", 1),
+    ("v6", 1),
+    ("v7", 1),
+    ("v8", 1),
+    ("v9", 1),
+    ("va", 1),
+    ("vb", 1),
+    ("vc", 1),
+    ("vd", 1),
+    ("ve", 1),
+    ("vf", 1),
+    ("i", 2),
+    ("pc", 2),
+    ("sp", 1),
+    ("dt", 1),
+    ("st", 1),
+];
+
+#[derive(Debug, Default)]
+pub struct Session {
+    buffer: Vec<u8>,
+    no_ack: bool,
+    // Sent again when the client answers with a `-`.
+    last_sent: Vec<u8>,
+    // Continued, so the client waits for a stop reply.
+    running: bool,
+    closed: bool,
+    // Debugger watchpoint ids by their Z packet kind, address and length.
+    watchpoints: HashMap<(char, usize, usize), u32>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // After a detach or kill, the connection can go.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn receive(
+        &mut self,
+        bytes: &[u8],
+        chip8: &mut Chip8,
+        debugger: &mut Debugger,
+        mut observe: Option<&mut dyn FnMut(&Record)>,
+    ) -> Result<Vec<u8>, Error> {
+        self.buffer.extend_from_slice(bytes);
+        let mut out = Vec::new();
+        while let Some(&first) = self.buffer.first() {
+            if first != b'$' {
+                self.buffer.remove(0);
+                match first {
+                    b'-' => out.extend_from_slice(&self.last_sent),
+                    // ^C
+                    0x03 if self.running => {
+                        debugger.pause();
+                        self.running = false;
+                        self.send(&mut out, "T02");
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            let Some(end) = self.buffer.iter().position(|&b| b == b'#') else {
+                break;
+            };
+            if self.buffer.len() < end + 3 {
+                break;
+            }
+            let packet: Vec<u8> = self.buffer.drain(..end + 3).collect();
+            let data = unescape(&packet[1..end]);
+            let checksum = std::str::from_utf8(&packet[end + 1..])
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok());
+            if checksum != Some(sum(&packet[1..end])) {
+                if !self.no_ack {
+                    out.push(b'-');
+                }
+                continue;
+            }
+            if !self.no_ack {
+                out.push(b'+');
+            }
+            let command = String::from_utf8_lossy(&data);
+            let observe = observe
+                .as_mut()
+                .map(|f| &mut **f as &mut dyn FnMut(&Record));
+            if let Some(reply) = self.handle(&command, chip8, debugger, observe)? {
+                self.send(&mut out, &reply);
+            }
+        }
+        Ok(out)
+    }
+
+    // The stop reply for a client waiting since its last continue.
+    pub fn stopped(&mut self, stop: Stop) -> Vec<u8> {
+        let mut out = Vec::new();
+        if std::mem::take(&mut self.running) {
+            let reply = self.stop_reply(stop);
+            self.send(&mut out, &reply);
+        }
+        out
+    }
+
+    // None for a continue, which is answered once the debugger stops.
+    fn handle(
+        &mut self,
+        command: &str,
+        chip8: &mut Chip8,
+        debugger: &mut Debugger,
+        observe: Option<&mut dyn FnMut(&Record)>,
+    ) -> Result<Option<String>, Error> {
+        let mut chars = command.chars();
+        let Some(kind) = chars.next() else {
+            return Ok(Some(String::new()));
+        };
+        let args = chars.as_str();
+        let reply = match kind {
+            '?' => "S05".to_owned(),
+            'g' => (0..REGISTERS.len())
+                .map(|n| hex(&read_register(chip8, n)))
+                .collect(),
+            'G' => match unhex(args) {
+                Some(bytes) => {
+                    let mut bytes = bytes.as_slice();
+                    for (n, (_, size)) in REGISTERS.iter().enumerate() {
+                        if bytes.len() < *size {
+                            break;
+                        }
+                        write_register(chip8, n, &bytes[..*size]);
+                        bytes = &bytes[*size..];
+                    }
+                    "OK".to_owned()
+                }
+                None => "E01".to_owned(),
+            },
+            'p' => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < REGISTERS.len() => hex(&read_register(chip8, n)),
+                _ => "E01".to_owned(),
+            },
+            'P' => {
+                let register = args.split_once('=').and_then(|(n, value)| {
+                    Some((usize::from_str_radix(n, 16).ok()?, unhex(value)?))
+                });
+                match register {
+                    Some((n, value)) if n < REGISTERS.len() => {
+                        write_register(chip8, n, &value);
+                        "OK".to_owned()
+                    }
+                    _ => "E01".to_owned(),
+                }
+            }
+            'm' => match parse_range(args) {
+                Some((addr, _)) if addr >= FOUR_K => "E14".to_owned(),
+                Some((addr, len)) => hex(&chip8.ram()[addr..(addr + len).min(FOUR_K)]),
+                None => "E01".to_owned(),
+            },
+            'M' => {
+                let write = args
+                    .split_once(':')
+                    .and_then(|(range, data)| Some((parse_range(range)?, unhex(data)?)));
+                match write {
+                    Some(((addr, len), data)) if data.len() == len && addr + len <= FOUR_K => {
+                        chip8.ram_mut()[addr..addr + len].copy_from_slice(&data);
+                        "OK".to_owned()
+                    }
+                    Some(_) => "E14".to_owned(),
+                    None => "E01".to_owned(),
+                }
+            }
+            // Software and hardware breakpoints are the same thing here.
+            'Z' | 'z' => {
+                let mut fields = args.split(',');
+                let watch = fields.next().and_then(|kind| kind.chars().next());
+                let addr = fields
+                    .next()
+                    .and_then(|addr| usize::from_str_radix(addr, 16).ok());
+                let len = fields
+                    .next()
+                    .and_then(|len| usize::from_str_radix(len, 16).ok());
+                let end = addr.zip(len).and_then(|(addr, len)| addr.checked_add(len));
+                match (watch, addr, len.zip(end)) {
+                    (Some('0' | '1'), Some(addr @ 0..FOUR_K), _) => {
+                        if kind == 'Z' {
+                            debugger.add_breakpoint(addr as u16);
+                        } else {
+                            debugger.remove_breakpoint(addr as u16);
+                        }
+                        "OK".to_owned()
+                    }
+                    (Some(watch @ '2'..='4'), Some(addr), Some((len, end))) => {
+                        let key = (watch, addr, len);
+                        if kind == 'Z' {
+                            let watch = Watch::Ram {
+                                range: addr..end,
+                                read: watch != '2',
+                                write: watch != '3',
+                            };
+                            let id = debugger.add_watchpoint(watch, Options::default());
+                            if let Some(old) = self.watchpoints.insert(key, id) {
+                                debugger.remove_watchpoint(old);
+                            }
+                        } else if let Some(id) = self.watchpoints.remove(&key) {
+                            debugger.remove_watchpoint(id);
+                        }
+                        "OK".to_owned()
+                    }
+                    (Some('0'..='4'), _, _) => "E01".to_owned(),
+                    _ => String::new(),
+                }
+            }
+            'c' | 's' => {
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    chip8.set_pc(addr);
+                }
+                return self.resume(kind == 's', chip8, debugger, observe);
+            }
+            'v' => {
+                if args == "Cont?" {
+                    "vCont;c;C;s;S".to_owned()
+                } else if let Some(actions) = args.strip_prefix("Cont;") {
+                    // One thread, so the first action is the one.
+                    match actions.chars().next() {
+                        Some(action @ ('c' | 'C' | 's' | 'S')) => {
+                            let step = action.eq_ignore_ascii_case(&'s');
+                            return self.resume(step, chip8, debugger, observe);
+                        }
+                        _ => "E01".to_owned(),
+                    }
+                } else {
+                    String::new()
+                }
+            }
+            'q' => self.query(args),
+            'Q' if args == "StartNoAckMode" => {
+                // This packet is still acked, the ones after it aren't.
+                self.no_ack = true;
+                "OK".to_owned()
+            }
+            'H' | 'T' => "OK".to_owned(),
+            'D' | 'k' => {
+                debugger.clear_breakpoints();
+                for (_, id) in self.watchpoints.drain() {
+                    debugger.remove_watchpoint(id);
+                }
+                debugger.resume();
+                self.closed = true;
+                if kind == 'k' {
+                    return Ok(None);
+                }
+                "OK".to_owned()
+            }
+            _ => String::new(),
+        };
+        Ok(Some(reply))
+    }
+
+    fn resume(
+        &mut self,
+        step: bool,
+        chip8: &mut Chip8,
+        debugger: &mut Debugger,
+        observe: Option<&mut dyn FnMut(&Record)>,
+    ) -> Result<Option<String>, Error> {
+        if step {
+            let stop = debugger.step(chip8, observe)?;
+            return Ok(Some(self.stop_reply(stop)));
+        }
+        debugger.resume();
+        self.running = true;
+        Ok(None)
+    }
+
+    fn stop_reply(&self, stop: Stop) -> String {
+        match stop {
+            Stop::Breakpoint(_) => "T05swbreak:;".to_owned(),
+            Stop::Watchpoint { id, .. } => {
+                let watch = self.watchpoints.iter().find(|(_, &wp)| wp == id);
+                match watch {
+                    Some((&(kind, addr, _), _)) => {
+                        let name = match kind {
+                            '2' => "watch",
+                            '3' => "rwatch",
+                            _ => "awatch",
+                        };
+                        format!("T05{name}:{addr:x};")
+                    }
+                    None => "S05".to_owned(),
+                }
+            }
+            Stop::Step => "S05".to_owned(),
+        }
+    }
+
+    fn query(&self, args: &str) -> String {
+        if args.starts_with("Supported") {
+            return format!(
+                "PacketSize={PACKET_SIZE:x};qXfer:features:read+;QStartNoAckMode+;swbreak+;hwbreak+"
+            );
+        }
+        if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+            let xml = target_xml();
+            return match parse_range(range) {
+                Some((offset, len)) => {
+                    let chunk = xml.get(offset.min(xml.len())..).unwrap_or_default();
+                    match chunk.get(..len) {
+                        Some(part) if part.len() < chunk.len() => format!("m{part}"),
+                        _ => format!("l{chunk}"),
+                    }
+                }
+                None => "E01".to_owned(),
+            };
+        }
+        match args {
+            "Attached" => "1",
+            "C" => "QC1",
+            "fThreadInfo" => "m1",
+            "sThreadInfo" => "l",
+            "Symbol::" => "OK",
+            _ => "",
+        }
+        .to_owned()
+    }
+
+    fn send(&mut self, out: &mut Vec<u8>, reply: &str) {
+        let mut data = Vec::with_capacity(reply.len());
+        for byte in reply.bytes() {
+            if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+                data.extend([b'}', byte ^ 0x20]);
+            } else {
+                data.push(byte);
+            }
+        }
+        let mut packet = vec![b'$'];
+        packet.extend_from_slice(&data);
+        packet.extend(format!("#{:02x}", sum(&data)).bytes());
+        out.extend_from_slice(&packet);
+        self.last_sent = packet;
+    }
+}
+
+fn read_register(chip8: &Chip8, n: usize) -> Vec<u8> {
+    let value = match n {
+        0..=15 => chip8.registers().values()[n] as u16,
+        16 => chip8.registers().image,
+        17 => chip8.pc(),
+        18 => chip8.stack().len() as u16,
+        19 => chip8.delay_timer() as u16,
+        _ => chip8.sound_timer() as u16,
+    };
+    value.to_le_bytes()[..REGISTERS[n].1].to_vec()
+}
+
+fn write_register(chip8: &mut Chip8, n: usize, bytes: &[u8]) {
+    let mut value = [0; 2];
+    for (dst, src) in value.iter_mut().zip(bytes) {
+        *dst = *src;
+    }
+    let value = u16::from_le_bytes(value);
+    match n {
+        0..=15 => chip8.registers_mut()[(n as u8).into()] = value as u8,
+        16 => chip8.registers_mut().image = value,
+        17 => chip8.set_pc(value),
+        19 => chip8.set_delay_timer(value as u8),
+        20 => chip8.set_sound_timer(value as u8),
+        _ => {}
+    }
+}
+
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\"><feature name=\"org.chipeite.chip8\">",
+    );
+    for (name, size) in REGISTERS {
+        let kind = match name {
+            "pc" => "code_ptr",
+            "i" => "data_ptr",
+            _ => "uint8",
+        };
+        let _ = write!(
+            xml,
+            "<reg name=\"{name}\" bitsize=\"{}\" type=\"{kind}\"/>",
+            size * 8
+        );
+    }
+    xml.push_str("</feature></target>");
+    xml
+}
+
+// `addr,len` in hex, as long as the range doesn't run past the end of the
+// address space.
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+    addr.checked_add(len)?;
+    Some((addr, len))
+}
+
+fn sum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'}' => out.extend(bytes.next().map(|b| b ^ 0x20)),
+            byte => out.push(byte),
+        }
+    }
+    out
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|n| u8::from_str_radix(s.get(n..n + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Config;
+
+    fn packet(data: &str) -> Vec<u8> {
+        format!("${data}#{:02x}", sum(data.as_bytes())).into_bytes()
+    }
+
+    struct Client {
+        session: Session,
+        chip8: Chip8,
+        debugger: Debugger,
+    }
+
+    impl Client {
+        fn new() -> Self {
+            // 200 LD V1, 5; 202 ADD V1, 1; 204 JP 202
+            let rom = [0x61, 0x05, 0x71, 0x01, 0x12, 0x02];
+            let mut debugger = Debugger::new();
+            debugger.pause();
+            Self {
+                session: Session::new(),
+                chip8: Chip8::new(&rom, Config::default()).unwrap(),
+                debugger,
+            }
+        }
+
+        fn send(&mut self, data: &str) -> String {
+            let out = self
+                .session
+                .receive(&packet(data), &mut self.chip8, &mut self.debugger, None)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        }
+    }
+
+    #[test]
+    fn registers_memory_and_breakpoints() {
+        let mut client = Client::new();
+        assert_eq!(client.send("?"), "+$S05#b8");
+        assert_eq!(client.send("QStartNoAckMode"), "+$OK#9a");
+        assert_eq!(client.send("m200,4"), "$61057101#95");
+        assert_eq!(client.send("p11"), "$0002#c2");
+
+        assert_eq!(client.send("s"), "$S05#b8");
+        assert_eq!(client.send("p1"), "$05#65");
+        assert_eq!(client.send("P1=2a"), "$OK#9a");
+        assert_eq!(client.send("M300,2:beef"), "$OK#9a");
+        assert_eq!(&client.chip8.ram()[0x300..0x302], &[0xBE, 0xEF]);
+        assert!(client.send("g").starts_with("$002a000000"));
+
+        assert_eq!(client.send("Z0,204,2"), "$OK#9a");
+        assert_eq!(client.send("c"), "");
+        let stop = client
+            .debugger
+            .run_frame(&mut client.chip8, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(client.session.stopped(stop)).unwrap(),
+            "$T05swbreak:;#1d"
+        );
+        assert_eq!(client.chip8.registers().values()[1], 0x2B);
+    }
+
+    #[test]
+    fn write_watchpoints() {
+        let mut client = Client::new();
+        // 202 LD [I], V0 with I at 300
+        assert_eq!(client.send("M202,2:f055"), "+$OK#9a");
+        assert_eq!(client.send("P10=0003"), "+$OK#9a");
+        assert_eq!(client.send("Z2,300,1"), "+$OK#9a");
+        assert_eq!(client.send("Z3,300,1"), "+$OK#9a");
+        assert_eq!(client.send("z3,300,1"), "+$OK#9a");
+        assert_eq!(client.send("Z5,300,1"), "+$#00");
+        client.send("c");
+        let stop = client
+            .debugger
+            .run_frame(&mut client.chip8, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(client.session.stopped(stop), packet("T05watch:300;"));
+        assert_eq!(client.chip8.pc(), 0x204);
+    }
+
+    #[test]
+    fn stepping_sets_off_watchpoints() {
+        let mut client = Client::new();
+        // 202 LD [I], V0 with I at 300
+        assert_eq!(client.send("M202,2:f055"), "+$OK#9a");
+        assert_eq!(client.send("P10=0003"), "+$OK#9a");
+        assert_eq!(client.send("Z2,300,1"), "+$OK#9a");
+        assert_eq!(client.send("s"), "+$S05#b8");
+        let reply = client.send("s");
+        assert_eq!(reply.as_bytes()[1..], packet("T05watch:300;"));
+        assert_eq!(client.chip8.pc(), 0x204);
+    }
+
+    #[test]
+    fn bad_checksums_are_nacked() {
+        let mut client = Client::new();
+        let out = client
+            .session
+            .receive(b"$?#00+", &mut client.chip8, &mut client.debugger, None)
+            .unwrap();
+        assert_eq!(out, b"-");
+        assert!(client
+            .send("qXfer:features:read:target.xml:0,10")
+            .starts_with("+$m<?xml"));
+    }
+
+    #[test]
+    fn oversized_lengths_are_errors() {
+        let mut client = Client::new();
+        assert_eq!(client.send("m200,ffffffffffffffff"), "+$E01#a6");
+        assert_eq!(client.send("Mfff,ffffffffffffffff:00"), "+$E01#a6");
+        assert_eq!(client.send("Z2,1,ffffffffffffffff"), "+$E01#a6");
+        assert_eq!(
+            client.send("qXfer:features:read:target.xml:1,ffffffffffffffff"),
+            "+$E01#a6"
+        );
+    }
+}
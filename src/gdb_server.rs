@@ -0,0 +1,99 @@
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+};
+
+use anyhow::Context;
+use chipeite::{
+    debugger::{Debugger, Stop},
+    gdb::Session,
+    trace::Record,
+    Chip8,
+};
+
+use crate::machine::DebugServer;
+
+// `--gdb`, the remote serial protocol on a local TCP port.
+pub struct GdbServer {
+    listener: TcpListener,
+    client: Option<(TcpStream, Session)>,
+}
+
+impl GdbServer {
+    // Blocks until gdb connects, so breakpoints can go in before the ROM
+    // runs. Later clients can connect whenever the last one has gone.
+    pub fn listen(port: u16) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .with_context(|| format!("couldn't listen on port {port}"))?;
+        eprintln!("waiting for gdb on {}", listener.local_addr()?);
+        let (stream, _) = listener.accept()?;
+        listener.set_nonblocking(true)?;
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            client: Some((stream, Session::new())),
+        })
+    }
+}
+
+impl DebugServer for GdbServer {
+    fn poll(
+        &mut self,
+        chip8: &mut Chip8,
+        debugger: &mut Debugger,
+        observe: Option<&mut dyn FnMut(&Record)>,
+    ) -> anyhow::Result<()> {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(true)?;
+                    debugger.pause();
+                    self.client = Some((stream, Session::new()));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let Some((stream, session)) = &mut self.client else {
+            return Ok(());
+        };
+        let mut received = Vec::new();
+        let mut buffer = [0; 4096];
+        let connected = loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => break false,
+                Ok(n) => received.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break true,
+                Err(_) => break false,
+            }
+        };
+        let reply = session.receive(&received, chip8, debugger, observe)?;
+        send(stream, &reply)?;
+        if !connected || session.is_closed() {
+            // Gone without detaching, let the ROM run on.
+            debugger.clear_breakpoints();
+            debugger.clear_watchpoints();
+            debugger.resume();
+            self.client = None;
+        }
+        Ok(())
+    }
+
+    fn stopped(&mut self, stop: Stop) -> anyhow::Result<()> {
+        if let Some((stream, session)) = &mut self.client {
+            send(stream, &session.stopped(stop))?;
+        }
+        Ok(())
+    }
+}
+
+// Replies are short, so the socket just blocks for them.
+fn send(stream: &mut TcpStream, bytes: &[u8]) -> io::Result<()> {
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    stream.set_nonblocking(false)?;
+    let result = stream.write_all(bytes);
+    stream.set_nonblocking(true)?;
+    result
+}
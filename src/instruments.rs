@@ -0,0 +1,87 @@
+use std::{cell::RefCell, fs, path::PathBuf, rc::Rc};
+
+use anyhow::Context;
+use chipeite::{
+    coverage::{Coverage, Symbols},
+    profile::Profiler,
+    trace::Record,
+    Variant,
+};
+
+use crate::machine::Observer;
+
+const HOTTEST_ADDRESSES: usize = 20;
+
+// `--profile` and `--folded-stacks`, written when the emulator exits.
+pub struct Profile {
+    pub profiler: Profiler,
+    pub report: Option<PathBuf>,
+    pub folded_stacks: Option<PathBuf>,
+}
+
+impl Observer for Profile {
+    fn observe(&mut self, record: &Record) {
+        self.profiler.observe(record);
+    }
+
+    fn reset(&mut self) {
+        self.profiler.reset();
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if let Some(path) = &self.report {
+            fs::write(path, self.profiler.report(HOTTEST_ADDRESSES))
+                .with_context(|| format!("couldn't write {}", path.display()))?;
+        }
+        if let Some(path) = &self.folded_stacks {
+            fs::write(path, self.profiler.folded())
+                .with_context(|| format!("couldn't write {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+// `--coverage` and `--lcov`, written when the emulator exits.
+pub struct CoverageReport {
+    // Shared with the bus observer that marks data reads and writes.
+    pub coverage: Rc<RefCell<Coverage>>,
+    pub rom: Vec<u8>,
+    pub rom_name: String,
+    pub load_address: u16,
+    pub variant: Variant,
+    pub listing: Option<PathBuf>,
+    pub lcov: Option<PathBuf>,
+    pub symbols: Option<Symbols>,
+}
+
+impl Observer for CoverageReport {
+    fn observe(&mut self, record: &Record) {
+        self.coverage.borrow_mut().observe(record);
+    }
+
+    // What ran of the old program says nothing about the new one.
+    fn reload(&mut self, rom: &[u8]) {
+        *self.coverage.borrow_mut() = Coverage::new();
+        self.rom = rom.to_vec();
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        let coverage = self.coverage.borrow();
+        if let Some(path) = &self.listing {
+            let listing = coverage.listing(&self.rom, self.load_address, self.variant);
+            fs::write(path, listing)
+                .with_context(|| format!("couldn't write {}", path.display()))?;
+        }
+        if let Some(path) = &self.lcov {
+            let lcov = match &self.symbols {
+                Some(symbols) => coverage.lcov_with_symbols(symbols),
+                None => {
+                    let end = self.load_address + self.rom.len() as u16;
+                    coverage.lcov(&self.rom_name, self.load_address..end)
+                }
+            };
+            fs::write(path, lcov).with_context(|| format!("couldn't write {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,20 @@
+pub mod chip8;
+pub mod coverage;
+pub mod cpu;
+pub mod dap;
+pub mod database;
+pub mod debugger;
+pub mod gdb;
+pub mod memory;
+pub mod profile;
+pub mod rom;
+pub mod sprite;
+pub mod trace;
+pub mod vip;
+
+pub use chip8::{snapshot::Snapshot, Chip8, Config, Segment};
+pub use cpu::{disassembler::disassemble, error::Error, quirks::Quirks, variant::Variant};
+pub use memory::{
+    color_map::ColorMap, error::LoadError, framebuffer::FrameBuffer, key_state::Key,
+    registers::Registers,
+};
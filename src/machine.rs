@@ -0,0 +1,379 @@
+use std::{
+    cell::{Ref, RefCell},
+    rc::Rc,
+};
+
+use anyhow::{anyhow, Context};
+use chipeite::{
+    debugger::{Debugger, Stop},
+    memory::access::AccessKind,
+    trace::{Record, Tracer},
+    vip::Vip,
+    Chip8, ColorMap, Error, FrameBuffer, Key, LoadError, Snapshot,
+};
+
+// What the frame loop needs, either the CHIP-8 interpreter or a whole VIP.
+pub trait Machine {
+    fn press_key(&mut self, key: Key);
+    fn release_key(&mut self, key: Key);
+    fn press_second_key(&mut self, _key: Key) {}
+    fn release_second_key(&mut self, _key: Key) {}
+    fn run_frame(&mut self) -> anyhow::Result<()>;
+    fn framebuffer(&self) -> &FrameBuffer;
+    fn is_beeping(&self) -> bool;
+    fn colors(&self) -> Option<&ColorMap> {
+        None
+    }
+    // For the debug overlay, None on a VIP.
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    fn chip8(&self) -> Option<&Chip8> {
+        None
+    }
+    fn is_paused(&self) -> bool {
+        false
+    }
+    fn set_paused(&mut self, _paused: bool) {}
+    // One instruction, while paused.
+    fn step(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+    // Starts the program over without touching the window.
+    fn reset(&mut self) -> anyhow::Result<()>;
+    // Starts over with a different ROM image.
+    fn reload(&mut self, rom: &[u8]) -> anyhow::Result<()>;
+    // For save states, None on a VIP.
+    fn snapshot(&self) -> Option<Snapshot> {
+        None
+    }
+    // Restores `snapshot` around the ROM that's loaded now.
+    fn rebase(&mut self, _snapshot: &Snapshot) {}
+    // None where there's no such thing, on a VIP.
+    fn instructions_per_frame(&self) -> Option<usize> {
+        None
+    }
+    fn set_instructions_per_frame(&mut self, _n: usize) {}
+    // Sets a byte of ram from the memory view.
+    fn poke(&mut self, _addr: u16, _value: u8) {}
+    // How many frames ago each byte of ram was written, for the memory view.
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    fn write_ages(&self) -> Option<Ref<'_, Vec<u16>>> {
+        None
+    }
+    // Called once the frame loop is done, even if the program crashed.
+    fn finish(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+// Sees every instruction the interpreter runs, e.g. the profiler.
+pub trait Observer {
+    fn observe(&mut self, record: &Record);
+    // The program started over from a reset or save state.
+    fn reset(&mut self) {}
+    // Or from a new ROM image.
+    fn reload(&mut self, _rom: &[u8]) {
+        self.reset()
+    }
+    fn finish(&mut self) -> anyhow::Result<()>;
+}
+
+// Drives the debugger from outside, e.g. gdb over TCP. Polled once a frame.
+pub trait DebugServer {
+    fn poll(
+        &mut self,
+        chip8: &mut Chip8,
+        debugger: &mut Debugger,
+        observe: Option<&mut dyn FnMut(&Record)>,
+    ) -> anyhow::Result<()>;
+    fn stopped(&mut self, stop: Stop) -> anyhow::Result<()>;
+    // A logpoint's message.
+    fn log(&mut self, text: &str) -> anyhow::Result<()> {
+        eprintln!("{text}");
+        Ok(())
+    }
+    fn finish(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+// The CHIP-8 interpreter with whatever is watching it run. Frames go through
+// the debugger even without a server, for pausing and stepping in the window.
+pub struct Instrumented {
+    pub chip8: Chip8,
+    pub observers: Vec<Box<dyn Observer>>,
+    pub debugger: Debugger,
+    pub server: Option<Box<dyn DebugServer>>,
+    // Filled in by a bus observer, aged every frame.
+    write_ages: Rc<RefCell<Vec<u16>>>,
+}
+
+impl Instrumented {
+    // Starts paused when there's a server, so the client can set up first.
+    pub fn new(
+        mut chip8: Chip8,
+        observers: Vec<Box<dyn Observer>>,
+        server: Option<Box<dyn DebugServer>>,
+    ) -> Self {
+        let mut debugger = Debugger::new();
+        if server.is_some() {
+            debugger.pause();
+        }
+        let write_ages = Rc::new(RefCell::new(vec![u16::MAX; chip8.ram().len()]));
+        let ages = write_ages.clone();
+        chip8.subscribe(move |access| {
+            if access.kind == AccessKind::Write {
+                ages.borrow_mut()[access.addr] = 0;
+            }
+        });
+        Self {
+            chip8,
+            observers,
+            debugger,
+            server,
+            write_ages,
+        }
+    }
+
+    // A frame, or a single instruction for `step`.
+    fn run(&mut self, step: bool) -> anyhow::Result<()> {
+        let observers = &mut self.observers;
+        let observed = !observers.is_empty();
+        let mut notify = |record: &Record| {
+            for observer in observers.iter_mut() {
+                observer.observe(record);
+            }
+        };
+        let mut observe = observed.then_some(&mut notify as &mut dyn FnMut(&Record));
+        let debugger = &mut self.debugger;
+        if let Some(server) = &mut self.server {
+            let observe = observe
+                .as_mut()
+                .map(|f| &mut **f as &mut dyn FnMut(&Record));
+            server.poll(&mut self.chip8, debugger, observe)?;
+        }
+        let result = if step {
+            debugger.step(&mut self.chip8, observe).map(Some)
+        } else {
+            debugger.run_frame(&mut self.chip8, observe)
+        };
+        for text in debugger.take_logs() {
+            match &mut self.server {
+                Some(server) => server.log(&text)?,
+                None => eprintln!("{text}"),
+            }
+        }
+        let result = match result {
+            Ok(Some(stop)) => {
+                if let Some(server) = &mut self.server {
+                    server.stopped(stop)?;
+                }
+                Ok(())
+            }
+            result => result.map(|_| ()),
+        };
+        let result = result.map_err(|e| match e {
+            Error::MachineCodeRoutine { .. } => anyhow!("{e}, try running it with --vip"),
+            e => e.into(),
+        });
+        if let Some(e) = self.chip8.tracer_mut().and_then(Tracer::take_error) {
+            return Err(anyhow::Error::new(e).context("couldn't write the trace"));
+        }
+        result
+    }
+
+    // With the new image after a reload.
+    fn started_over(&mut self, rom: Option<&[u8]>) {
+        self.write_ages.borrow_mut().fill(u16::MAX);
+        for observer in &mut self.observers {
+            match rom {
+                Some(rom) => observer.reload(rom),
+                None => observer.reset(),
+            }
+        }
+    }
+}
+
+impl Machine for Instrumented {
+    fn press_key(&mut self, key: Key) {
+        self.chip8.press_key(key)
+    }
+
+    fn release_key(&mut self, key: Key) {
+        self.chip8.release_key(key)
+    }
+
+    fn press_second_key(&mut self, key: Key) {
+        self.chip8.press_second_key(key)
+    }
+
+    fn release_second_key(&mut self, key: Key) {
+        self.chip8.release_second_key(key)
+    }
+
+    fn run_frame(&mut self) -> anyhow::Result<()> {
+        let paused = self.debugger.is_paused();
+        self.run(false)?;
+        if !paused {
+            for age in self.write_ages.borrow_mut().iter_mut() {
+                *age = age.saturating_add(1);
+            }
+        }
+        Ok(())
+    }
+
+    fn framebuffer(&self) -> &FrameBuffer {
+        self.chip8.framebuffer()
+    }
+
+    fn is_beeping(&self) -> bool {
+        self.chip8.is_beeping()
+    }
+
+    fn colors(&self) -> Option<&ColorMap> {
+        self.chip8.colors()
+    }
+
+    fn chip8(&self) -> Option<&Chip8> {
+        Some(&self.chip8)
+    }
+
+    fn is_paused(&self) -> bool {
+        self.debugger.is_paused()
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        if paused {
+            self.debugger.pause();
+        } else {
+            self.debugger.resume();
+        }
+    }
+
+    fn step(&mut self) -> anyhow::Result<()> {
+        self.run(true)
+    }
+
+    // Breakpoints, watchpoints and whether it's paused carry over.
+    fn reset(&mut self) -> anyhow::Result<()> {
+        self.chip8.reset();
+        self.started_over(None);
+        Ok(())
+    }
+
+    fn reload(&mut self, rom: &[u8]) -> anyhow::Result<()> {
+        self.chip8.reload(rom)?;
+        self.started_over(Some(rom));
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        Some(self.chip8.snapshot())
+    }
+
+    fn rebase(&mut self, snapshot: &Snapshot) {
+        self.chip8.rebase(snapshot);
+        self.started_over(None);
+    }
+
+    fn instructions_per_frame(&self) -> Option<usize> {
+        Some(self.chip8.config().instructions_per_frame)
+    }
+
+    fn set_instructions_per_frame(&mut self, n: usize) {
+        self.chip8.set_instructions_per_frame(n);
+    }
+
+    fn poke(&mut self, addr: u16, value: u8) {
+        if let Some(byte) = self.chip8.ram_mut().get_mut(addr as usize) {
+            *byte = value;
+            self.write_ages.borrow_mut()[addr as usize] = 0;
+        }
+    }
+
+    fn write_ages(&self) -> Option<Ref<'_, Vec<u16>>> {
+        Some(self.write_ages.borrow())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        for observer in &mut self.observers {
+            observer.finish()?;
+        }
+        if let Some(server) = &mut self.server {
+            server.finish()?;
+        }
+        if let Some(tracer) = self.chip8.tracer_mut() {
+            tracer.finish().context("couldn't write the trace")?;
+        }
+        Ok(())
+    }
+}
+
+// A whole VIP. There's no debugger, so pausing just stops running frames.
+// Resets power it up again from the same images.
+pub struct VipMachine {
+    vip: Vip,
+    paused: bool,
+    interpreter: Vec<u8>,
+    monitor: Option<Vec<u8>>,
+    program: Vec<u8>,
+}
+
+impl VipMachine {
+    pub fn new(
+        interpreter: Vec<u8>,
+        monitor: Option<Vec<u8>>,
+        program: Vec<u8>,
+    ) -> Result<Self, LoadError> {
+        Ok(Self {
+            vip: Vip::new(&interpreter, monitor.as_deref(), &program)?,
+            paused: false,
+            interpreter,
+            monitor,
+            program,
+        })
+    }
+}
+
+impl Machine for VipMachine {
+    fn press_key(&mut self, key: Key) {
+        self.vip.press_key(key)
+    }
+
+    fn release_key(&mut self, key: Key) {
+        self.vip.release_key(key)
+    }
+
+    fn run_frame(&mut self) -> anyhow::Result<()> {
+        if !self.paused {
+            self.vip.run_frame();
+        }
+        Ok(())
+    }
+
+    fn framebuffer(&self) -> &FrameBuffer {
+        self.vip.framebuffer()
+    }
+
+    fn reset(&mut self) -> anyhow::Result<()> {
+        let program = self.program.clone();
+        self.reload(&program)
+    }
+
+    fn reload(&mut self, rom: &[u8]) -> anyhow::Result<()> {
+        self.vip = Vip::new(&self.interpreter, self.monitor.as_deref(), rom)?;
+        self.program = rom.to_vec();
+        Ok(())
+    }
+
+    fn is_beeping(&self) -> bool {
+        self.vip.is_beeping()
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+}
@@ -1,21 +1,258 @@
-mod cpu;
-mod memory;
+#[cfg(any(feature = "sdl", feature = "tui"))]
+mod dap_server;
+mod difftrace;
+mod frontend;
+#[cfg(any(feature = "sdl", feature = "tui"))]
+mod gdb_server;
+#[cfg(any(feature = "sdl", feature = "tui"))]
+mod instruments;
+#[cfg(any(feature = "sdl", feature = "tui"))]
+mod machine;
+#[cfg(feature = "sdl")]
+mod overlay;
+#[cfg(any(feature = "sdl", feature = "tui"))]
+mod pacing;
+#[cfg(any(feature = "sdl", feature = "tui"))]
+mod reload;
+mod settings;
 mod structopt;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "sdl")]
 mod video;
 
-use std::fs;
+use std::{fs, path::Path};
 
-use crate::structopt::Opt;
+use crate::structopt::{Cli, Opt, Subcommand};
 use ::structopt::StructOpt;
-use cpu::Cpu;
-use memory::Memory;
-use video::Video;
+use anyhow::Context;
+use chipeite::rom::{self, Rom};
+use settings::Settings;
+
+// Everything that runs a ROM in a window or the terminal.
+#[cfg(any(feature = "sdl", feature = "tui"))]
+use {
+    crate::structopt::{DapTransport, FrontendKind},
+    chipeite::{
+        coverage::{Coverage, Symbols},
+        profile::Profiler,
+        trace::{Filter, Tracer},
+        Chip8,
+    },
+    frontend::{Command, Frontend, Input},
+    machine::{DebugServer, Instrumented, Machine, Observer, VipMachine},
+    pacing::Pacing,
+    reload::Reloader,
+    std::{
+        cell::RefCell,
+        fs::File,
+        io::BufWriter,
+        rc::Rc,
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+#[cfg(any(feature = "sdl", feature = "tui"))]
+const FRAME_LENGTH: Duration = Duration::from_nanos(1_000_000_000 / 60);
 
 fn main() -> anyhow::Result<()> {
-    let opts = Opt::from_args();
-    let mut cpu = Cpu::new();
-    let mut memory = Memory::new(&fs::read(&opts.rom_path)?, Video::new(opts.scale_factor));
+    let cli = Cli::from_args();
+    match &cli.command {
+        Some(Subcommand::DiffTrace(opts)) => difftrace::run(opts),
+        None => emulate(&cli.run),
+    }
+}
+
+#[cfg(not(any(feature = "sdl", feature = "tui")))]
+fn emulate(_opts: &Opt) -> anyhow::Result<()> {
+    anyhow::bail!("chipeite was built without a frontend, only difftrace works")
+}
+
+#[cfg(any(feature = "sdl", feature = "tui"))]
+fn emulate(opts: &Opt) -> anyhow::Result<()> {
+    let (rom, settings) = load(opts)?;
+    let profiling = opts.profile.is_some() || opts.folded_stacks.is_some();
+    let covering = opts.coverage.is_some() || opts.lcov.is_some();
+    let debugging = opts.gdb.is_some() || opts.dap.is_some();
+    if opts.gdb.is_some() && opts.dap.is_some() {
+        anyhow::bail!("--gdb and --dap can't be used together");
+    }
+    if opts.dap == Some(DapTransport::Stdio) && opts.frontend == FrontendKind::Tui {
+        anyhow::bail!("--dap stdio needs the terminal, use the sdl frontend or a port");
+    }
+    let symbols = match &opts.symbols {
+        Some(path) => Some(
+            Symbols::parse(&String::from_utf8_lossy(&read(path)?))
+                .with_context(|| format!("couldn't parse {}", path.display()))?,
+        ),
+        None => None,
+    };
+    let mut machine: Box<dyn Machine> = match &opts.vip {
+        Some(_) if opts.trace.is_some() || profiling || covering || debugging => {
+            anyhow::bail!("--trace, --profile, --coverage and debuggers don't work with --vip")
+        }
+        Some(_) if opts.state.is_some() => anyhow::bail!("--state doesn't work with --vip"),
+        Some(interpreter) => {
+            let monitor = opts.vip_monitor.as_deref().map(read).transpose()?;
+            Box::new(
+                VipMachine::new(read(interpreter)?, monitor, rom.bytes.clone())
+                    .context("couldn't set up the VIP")?,
+            )
+        }
+        None => {
+            let mut chip8 = Chip8::new(&rom.bytes, settings.config.clone())
+                .with_context(|| format!("couldn't load {}", opts.rom_path().display()))?;
+            if let Some(path) = &opts.trace {
+                let file = File::create(path)
+                    .with_context(|| format!("couldn't create {}", path.display()))?;
+                let filter = Filter {
+                    addresses: opts.trace_addresses.clone(),
+                    frames: opts.trace_frames.clone(),
+                };
+                chip8.set_tracer(Tracer::new(
+                    Box::new(BufWriter::new(file)),
+                    opts.trace_format,
+                    filter,
+                ));
+            }
+            let mut observers: Vec<Box<dyn Observer>> = Vec::new();
+            if profiling {
+                observers.push(Box::new(instruments::Profile {
+                    profiler: Profiler::new(),
+                    report: opts.profile.clone(),
+                    folded_stacks: opts.folded_stacks.clone(),
+                }));
+            }
+            if covering {
+                let coverage = Rc::new(RefCell::new(Coverage::new()));
+                let accesses = coverage.clone();
+                chip8.subscribe(move |access| accesses.borrow_mut().access(access));
+                observers.push(Box::new(instruments::CoverageReport {
+                    coverage,
+                    rom: rom.bytes.clone(),
+                    rom_name: opts.rom_path().display().to_string(),
+                    load_address: settings.config.load_address,
+                    variant: settings.config.variant,
+                    listing: opts.coverage.clone(),
+                    lcov: opts.lcov.clone(),
+                    symbols: symbols.clone(),
+                }));
+            }
+            let server: Option<Box<dyn DebugServer>> = match (opts.gdb, opts.dap) {
+                (Some(port), _) => Some(Box::new(gdb_server::GdbServer::listen(port)?)),
+                (_, Some(transport)) => {
+                    // Source paths in the symbols are relative to the file.
+                    let source_dir = opts
+                        .symbols
+                        .as_deref()
+                        .and_then(Path::parent)
+                        .map(Path::to_path_buf)
+                        .unwrap_or_default();
+                    Some(Box::new(dap_server::DapServer::new(
+                        transport,
+                        symbols.unwrap_or_default(),
+                        source_dir,
+                    )?))
+                }
+                (None, None) => None,
+            };
+            Box::new(Instrumented::new(chip8, observers, server))
+        }
+    };
+    let watched = opts.watch.then(|| {
+        std::iter::once(opts.rom_path().to_owned())
+            .chain(opts.watch_source.clone())
+            .collect()
+    });
+    let mut reloader = Reloader::new(opts.rom_path(), watched, opts.state.clone());
+    let result = match opts.frontend {
+        #[cfg(feature = "sdl")]
+        FrontendKind::Sdl => {
+            let video = video::Video::new(opts.scale_factor, &settings, machine.framebuffer());
+            run(machine.as_mut(), video, &mut reloader)
+        }
+        #[cfg(feature = "tui")]
+        FrontendKind::Tui => run(machine.as_mut(), tui::Tui::new(&settings)?, &mut reloader),
+        #[allow(unreachable_patterns)]
+        kind => Err(anyhow::anyhow!(
+            "chipeite was built without the {kind:?} frontend"
+        )),
+    };
+    let finished = machine.finish();
+    result.and(finished)
+}
+
+// The ROM and how to run it.
+fn load(opts: &Opt) -> anyhow::Result<(Rom, Settings)> {
+    let rom = read_rom(opts.rom_path())?;
+    let settings = settings::resolve(opts, &rom)?;
+    Ok((rom, settings))
+}
+
+fn read_rom(path: &Path) -> anyhow::Result<Rom> {
+    rom::decode(&read(path)?).with_context(|| format!("couldn't open {}", path.display()))
+}
+
+fn read(path: &Path) -> anyhow::Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("couldn't read {}", path.display()))
+}
+
+#[cfg(any(feature = "sdl", feature = "tui"))]
+fn run(
+    machine: &mut dyn Machine,
+    mut frontend: impl Frontend,
+    reloader: &mut Reloader,
+) -> anyhow::Result<()> {
+    let mut pacing = Pacing::new();
     loop {
-        cpu.execute(&mut memory);
+        let frame_start = Instant::now();
+        let mut commands = Vec::new();
+        let running = frontend.poll_keys(|input, pressed| match (input, pressed) {
+            (Input::Key(key), true) => machine.press_key(key),
+            (Input::Key(key), false) => machine.release_key(key),
+            (Input::SecondKey(key), true) => machine.press_second_key(key),
+            (Input::SecondKey(key), false) => machine.release_second_key(key),
+            (Input::Command(command), pressed) => commands.push((command, pressed)),
+        })?;
+        if !running {
+            return Ok(());
+        }
+        for (command, pressed) in commands {
+            match command {
+                Command::FastForward => pacing.fast_forward = pressed,
+                _ if !pressed => {}
+                Command::Pause => machine.set_paused(!machine.is_paused()),
+                Command::Advance => {
+                    machine.set_paused(false);
+                    machine.run_frame()?;
+                    machine.set_paused(true);
+                }
+                Command::Step => {
+                    machine.set_paused(true);
+                    machine.step()?;
+                }
+                Command::SlowMotion => pacing.slow_motion = !pacing.slow_motion,
+                Command::Speed => {
+                    if let Some(n) = machine.instructions_per_frame() {
+                        machine.set_instructions_per_frame(pacing::next_preset(n));
+                    }
+                }
+                Command::Reset => machine.reset()?,
+                Command::Reload => reloader.reload(machine),
+                Command::SaveState => reloader.save_state(machine),
+                Command::Poke { addr, value } => machine.poke(addr, value),
+            }
+        }
+        reloader.poll(machine);
+        for _ in 0..pacing.frames() {
+            machine.run_frame()?;
+        }
+        frontend.set_status(&pacing.status(machine));
+        frontend.draw(machine)?;
+        frontend.beep(machine.is_beeping());
+        if let Some(left) = FRAME_LENGTH.checked_sub(frame_start.elapsed()) {
+            thread::sleep(left);
+        }
     }
 }
@@ -1,13 +1,25 @@
+pub mod access;
+pub mod bus;
+pub mod color_map;
+pub mod error;
 pub mod framebuffer;
 pub mod key_state;
 pub mod registers;
 pub mod stack;
 
-use crate::video::Video;
+use self::{
+    access::AccessKind,
+    bus::{Bus, Mapping, Observers},
+    color_map::ColorMap,
+    error::{BusError, LoadError},
+    framebuffer::FrameBuffer,
+    key_state::KeyState,
+    registers::Registers,
+    stack::Stack,
+};
 
-use self::{framebuffer::FrameBuffer, key_state::KeyState, registers::Registers, stack::Stack};
-
-const FOUR_K: usize = 4 * 1024;
+pub const FOUR_K: usize = 4 * 1024;
+pub const PROGRAM_START: u16 = 0x200;
 
 pub struct Memory {
     pub framebuffer: FrameBuffer,
@@ -15,24 +27,76 @@ pub struct Memory {
     pub registers: Registers,
     pub stack: Stack,
     pub key_state: KeyState,
-    pub video: Video,
+    // CHIP-8X only
+    pub colors: ColorMap,
+    pub second_key_state: KeyState,
+    pub port_input: Option<u8>,
+    pub port_output: u8,
+    pub mapping: Mapping,
+    pub observers: Observers,
 }
 
 impl Memory {
-    pub fn new(program: &[u8], video: Video) -> Self {
+    pub fn new() -> Self {
         let mut this = Self {
             framebuffer: FrameBuffer::default(),
             ram: [0; FOUR_K],
             registers: Registers::default(),
             stack: Stack::default(),
             key_state: KeyState::default(),
-            video,
+            colors: ColorMap::default(),
+            second_key_state: KeyState::default(),
+            port_input: None,
+            port_output: 0,
+            mapping: Mapping::default(),
+            observers: Observers::default(),
         };
         this.ram[0..80].copy_from_slice(&FONT_SPEC);
-        let program_len = program.len();
-        this.ram[0x200..(0x200 + program_len)].copy_from_slice(program);
         this
     }
+
+    // Copies `bytes` into ram at `addr`, refusing anything that would run
+    // past the end.
+    pub fn load(&mut self, addr: u16, bytes: &[u8]) -> Result<(), LoadError> {
+        let start = addr as usize;
+        let dest = self
+            .ram
+            .get_mut(start..start + bytes.len())
+            .ok_or(LoadError::DoesNotFit {
+                addr,
+                len: bytes.len(),
+            })?;
+        dest.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+// Bytes go through `mapping`, then to ram.
+impl Bus for Memory {
+    fn read(&mut self, addr: usize) -> Result<u8, BusError> {
+        let addr = self.mapping.map(addr)?;
+        self.observers.notify(AccessKind::Read, addr);
+        Ok(self.ram[addr])
+    }
+
+    fn write(&mut self, addr: usize, value: u8) -> Result<(), BusError> {
+        let addr = self.mapping.map_write(addr)?;
+        self.observers.notify(AccessKind::Write, addr);
+        self.ram[addr] = value;
+        Ok(())
+    }
+
+    fn fetch(&mut self, addr: usize) -> Result<u8, BusError> {
+        let addr = self.mapping.map(addr)?;
+        self.observers.notify(AccessKind::Fetch, addr);
+        Ok(self.ram[addr])
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 const FONT_SPEC: [u8; 80] = [
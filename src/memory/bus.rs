@@ -0,0 +1,102 @@
+use super::{
+    access::{Access, AccessKind},
+    error::BusError,
+    FOUR_K, PROGRAM_START,
+};
+
+// Everything the interpreter reads from or writes to ram, instruction fetches
+// included, goes through a bus, one byte at a time.
+pub trait Bus {
+    fn read(&mut self, addr: usize) -> Result<u8, BusError>;
+    fn write(&mut self, addr: usize, value: u8) -> Result<(), BusError>;
+    fn fetch(&mut self, addr: usize) -> Result<u8, BusError> {
+        self.read(addr)
+    }
+}
+
+// How addresses map onto the 4K of ram.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Mapping {
+    // Addresses past the end wrap around, like on interpreters that only
+    // decode 12 address bits, instead of being errors.
+    pub mirror: bool,
+    // Writes below 0x200, where the font and the interpreter live, are
+    // errors.
+    pub write_protect: bool,
+}
+
+impl Mapping {
+    pub fn map(&self, addr: usize) -> Result<usize, BusError> {
+        if self.mirror {
+            Ok(addr % FOUR_K)
+        } else if addr < FOUR_K {
+            Ok(addr)
+        } else {
+            Err(BusError::OutOfBounds { addr })
+        }
+    }
+
+    pub fn map_write(&self, addr: usize) -> Result<usize, BusError> {
+        let mapped = self.map(addr)?;
+        if self.write_protect && mapped < PROGRAM_START as usize {
+            return Err(BusError::WriteProtected { addr: mapped });
+        }
+        Ok(mapped)
+    }
+}
+
+pub type Subscriber = Box<dyn FnMut(&Access)>;
+
+// Who hears about accesses. Carried over when a snapshot is restored.
+#[derive(Default)]
+pub struct Observers {
+    // What the current instruction did, for the debugger.
+    log: Option<Vec<Access>>,
+    subscribers: Vec<(u32, Subscriber)>,
+    next_id: u32,
+}
+
+impl Observers {
+    pub fn notify(&mut self, kind: AccessKind, addr: usize) {
+        let access = Access { kind, addr };
+        for (_, subscriber) in &mut self.subscribers {
+            subscriber(&access);
+        }
+        if let Some(log) = &mut self.log {
+            if kind != AccessKind::Fetch {
+                log.push(access);
+            }
+        }
+    }
+
+    // The id is for `unsubscribe`.
+    pub fn subscribe(&mut self, subscriber: Subscriber) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscribers.push((id, subscriber));
+        id
+    }
+
+    // False if there was no such subscriber.
+    pub fn unsubscribe(&mut self, id: u32) -> bool {
+        let len = self.subscribers.len();
+        self.subscribers.retain(|(other, _)| *other != id);
+        self.subscribers.len() != len
+    }
+
+    pub fn set_logging(&mut self, on: bool) {
+        if on != self.log.is_some() {
+            self.log = on.then(Vec::new);
+        }
+    }
+
+    pub fn clear_log(&mut self) {
+        if let Some(log) = &mut self.log {
+            log.clear();
+        }
+    }
+
+    pub fn log(&self) -> &[Access] {
+        self.log.as_deref().unwrap_or_default()
+    }
+}
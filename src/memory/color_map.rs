@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+const COLUMNS: usize = 8;
+const ROWS: usize = 32;
+
+// VP-590 colours, in the order of the 3 bit colour values.
+pub const FOREGROUNDS: [[u8; 3]; 8] = [
+    [0x00, 0x00, 0x00], // black
+    [0xFF, 0x00, 0x00], // red
+    [0x00, 0x00, 0xFF], // blue
+    [0xFF, 0x00, 0xFF], // violet
+    [0x00, 0xFF, 0x00], // green
+    [0xFF, 0xFF, 0x00], // yellow
+    [0x00, 0xFF, 0xFF], // aqua
+    [0xFF, 0xFF, 0xFF], // white
+];
+// What 02A0 cycles through.
+pub const BACKGROUNDS: [[u8; 3]; 4] = [
+    [0x00, 0x00, 0x80], // blue
+    [0x00, 0x00, 0x00], // black
+    [0x00, 0x80, 0x00], // green
+    [0x80, 0x00, 0x00], // red
+];
+const RED: u8 = 1;
+
+// CHIP-8X colour attributes, one per 8 pixel wide strip of a line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Unchecked")]
+pub struct ColorMap {
+    cells: Vec<[u8; COLUMNS]>,
+    background: usize,
+}
+
+// What a save state holds, before it's known to index the colour tables.
+#[derive(Deserialize)]
+struct Unchecked {
+    cells: Vec<[u8; COLUMNS]>,
+    background: usize,
+}
+
+impl TryFrom<Unchecked> for ColorMap {
+    type Error = String;
+    fn try_from(map: Unchecked) -> Result<Self, Self::Error> {
+        if map.cells.len() != ROWS {
+            return Err(format!("{} colour rows instead of {ROWS}", map.cells.len()));
+        }
+        if map
+            .cells
+            .iter()
+            .flatten()
+            .any(|&cell| cell as usize >= FOREGROUNDS.len())
+        {
+            return Err("foreground colour out of range".to_owned());
+        }
+        if map.background >= BACKGROUNDS.len() {
+            return Err(format!("background colour {} out of range", map.background));
+        }
+        Ok(Self {
+            cells: map.cells,
+            background: map.background,
+        })
+    }
+}
+
+impl Default for ColorMap {
+    fn default() -> Self {
+        Self {
+            cells: vec![[RED; COLUMNS]; ROWS],
+            background: 0,
+        }
+    }
+}
+
+impl ColorMap {
+    pub fn foreground(&self, x: usize, y: usize) -> [u8; 3] {
+        FOREGROUNDS[self.cells[y % ROWS][x / 8 % COLUMNS] as usize]
+    }
+
+    pub fn background(&self) -> [u8; 3] {
+        BACKGROUNDS[self.background]
+    }
+
+    pub fn cycle_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUNDS.len();
+    }
+
+    // Columns are 8 pixels wide, rows one line high, both clipped to the
+    // screen.
+    pub fn fill(
+        &mut self,
+        columns: std::ops::Range<usize>,
+        rows: std::ops::Range<usize>,
+        color: u8,
+    ) {
+        for row in self.cells.iter_mut().take(rows.end).skip(rows.start) {
+            for cell in row.iter_mut().take(columns.end).skip(columns.start) {
+                *cell = color & 0x7;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn save_states_are_checked() {
+        let map = ColorMap::default();
+        let json = serde_json::to_string(&map).unwrap();
+        assert_eq!(serde_json::from_str::<ColorMap>(&json).unwrap(), map);
+
+        let short = r#"{"cells": [[1, 1, 1, 1, 1, 1, 1, 1]], "background": 0}"#;
+        assert!(serde_json::from_str::<ColorMap>(short).is_err());
+        let cells = vec![[1u8; COLUMNS]; ROWS];
+        let mut bad = serde_json::json!({ "cells": cells, "background": 4 });
+        assert!(serde_json::from_value::<ColorMap>(bad.clone()).is_err());
+        bad["background"] = 3.into();
+        bad["cells"][31][7] = 8.into();
+        assert!(serde_json::from_value::<ColorMap>(bad).is_err());
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    DoesNotFit { addr: u16, len: usize },
+    EntryPoint { addr: u16 },
+    TooLarge { len: usize, max: usize },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            LoadError::DoesNotFit { addr, len } => write!(
+                f,
+                "{len} byte image does not fit at {addr:#05X}, only {} bytes of ram are left",
+                super::FOUR_K.saturating_sub(addr as usize)
+            ),
+            LoadError::EntryPoint { addr } => {
+                write!(f, "entry point {addr:#05X} is outside of ram")
+            }
+            LoadError::TooLarge { len, max } => {
+                write!(f, "{len} byte image is too large, at most {max} bytes fit")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusError {
+    OutOfBounds { addr: usize },
+    WriteProtected { addr: usize },
+}
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            BusError::OutOfBounds { addr } => write!(f, "{addr:#X} is outside of ram"),
+            BusError::WriteProtected { addr } => write!(f, "{addr:#05X} is write protected"),
+        }
+    }
+}
+
+impl std::error::Error for BusError {}
@@ -1,12 +1,15 @@
+use std::fmt;
+
+#[derive(Clone, PartialEq, Eq)]
 pub struct FrameBuffer {
-    vec: Box<[[bool; 64]; 32]>,
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
 }
 
 impl Default for FrameBuffer {
     fn default() -> Self {
-        Self {
-            vec: vec![[false; 64]; 32].try_into().unwrap(),
-        }
+        Self::new(64, 32)
     }
 }
 
@@ -17,29 +20,72 @@ pub struct Pixel {
 }
 
 impl FrameBuffer {
-    pub fn reset(&mut self) {
-        for line in self.vec.iter_mut() {
-            line.fill(false)
+    // At most 64 pixels wide, `rows` packs a line into a u64.
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(width <= 64, "framebuffers are at most 64 pixels wide");
+        Self {
+            width,
+            height,
+            pixels: vec![false; width * height],
         }
     }
 
+    pub fn reset(&mut self) {
+        self.pixels.fill(false)
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = Pixel> + '_ {
-        self.vec.iter().enumerate().flat_map(|(y, lines)| {
-            lines
-                .iter()
-                .enumerate()
-                .map(move |(x, &state)| Pixel { x, y, state })
+        self.pixels.iter().enumerate().map(|(i, &state)| Pixel {
+            x: i % self.width,
+            y: i / self.width,
+            state,
         })
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width + x]
+    }
+
+    fn lines_mut(&mut self) -> std::slice::ChunksMut<'_, bool> {
+        self.pixels.chunks_mut(self.width)
+    }
+
+    // One u64 per line, most significant bit is the leftmost pixel.
+    pub fn rows(&self) -> Vec<u64> {
+        self.pixels
+            .chunks(self.width)
+            .map(|line| line.iter().fold(0, |acc, &b| (acc << 1) | b as u64))
+            .collect()
+    }
+
+    // 64 pixels wide and as high as there are rows.
+    pub fn from_rows(rows: &[u64]) -> Self {
+        let mut this = Self::new(64, rows.len());
+        for (line, row) in this.lines_mut().zip(rows) {
+            for (x, v) in line.iter_mut().enumerate() {
+                *v = row & (1 << (63 - x)) != 0;
+            }
+        }
+        this
+    }
+
+    // Sprites start at (x, y) wrapped around the screen and are clipped at
+    // the edges.
     pub fn write(&mut self, x: usize, y: usize, bytes: &[u8]) -> bool {
+        let (x, y) = (x % self.width, y % self.height);
         let mut colided = false;
-        self.vec
-            .iter_mut()
+        self.lines_mut()
             .skip(y)
-            .flat_map(|line| line.iter_mut())
-            .skip(x)
-            .zip(bytes.iter().flat_map(|u| iterate_u8(*u)))
+            .zip(bytes)
+            .flat_map(|(line, byte)| line.iter_mut().skip(x).zip(iterate_u8(*byte)))
             .for_each(|(v, b)| {
                 let old = *v;
                 *v ^= b;
@@ -49,6 +95,36 @@ impl FrameBuffer {
             });
         colided
     }
+
+    // Like `write`, but pixels past an edge continue on the opposite side.
+    pub fn write_wrapping(&mut self, x: usize, y: usize, bytes: &[u8]) -> bool {
+        let (width, height) = (self.width, self.height);
+        let mut colided = false;
+        for (row, byte) in bytes.iter().enumerate() {
+            let line = (y + row) % height * width;
+            for (col, b) in iterate_u8(*byte).enumerate() {
+                let v = &mut self.pixels[line + (x + col) % width];
+                let old = *v;
+                *v ^= b;
+                if !colided && old && !*v {
+                    colided = true
+                }
+            }
+        }
+        colided
+    }
+}
+
+impl fmt::Display for FrameBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in self.pixels.chunks(self.width) {
+            for &pixel in line {
+                f.write_str(if pixel { "#" } else { "." })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
 }
 
 fn iterate_u8(u: u8) -> impl Iterator<Item = bool> {
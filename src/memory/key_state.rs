@@ -1,10 +1,8 @@
-use sdl2::keyboard::Keycode;
-
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy)]
 pub struct KeyState(u16);
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Key {
     Zero,
     One,
@@ -30,31 +28,6 @@ impl Key {
     }
 }
 
-impl TryFrom<Keycode> for Key {
-    type Error = ();
-    fn try_from(value: Keycode) -> Result<Self, Self::Error> {
-        match value {
-            Keycode::Num0 => Ok(Key::Zero),
-            Keycode::Num1 => Ok(Key::One),
-            Keycode::Num2 => Ok(Key::Two),
-            Keycode::Num3 => Ok(Key::Three),
-            Keycode::Num4 => Ok(Key::Four),
-            Keycode::Num5 => Ok(Key::Five),
-            Keycode::Num6 => Ok(Key::Six),
-            Keycode::Num7 => Ok(Key::Seven),
-            Keycode::Num8 => Ok(Key::Eight),
-            Keycode::Num9 => Ok(Key::Nine),
-            Keycode::A => Ok(Key::A),
-            Keycode::B => Ok(Key::B),
-            Keycode::C => Ok(Key::C),
-            Keycode::D => Ok(Key::D),
-            Keycode::E => Ok(Key::E),
-            Keycode::F => Ok(Key::F),
-            _ => Err(()),
-        }
-    }
-}
-
 impl KeyState {
     pub fn is_set(&self, index: Key) -> bool {
         (self.0 & index.selector()) != 0
@@ -67,6 +40,18 @@ impl KeyState {
     pub fn unset(&mut self, index: Key) {
         self.0 &= !index.selector()
     }
+
+    pub fn first_pressed(&self) -> Option<Key> {
+        (0..16).map(Key::from).find(|&k| self.is_set(k))
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
 }
 
 impl From<u8> for Key {
@@ -133,4 +118,13 @@ mod test {
             assert!(!keystate.is_set(k));
         }
     }
+
+    #[test]
+    fn first_pressed_picks_the_lowest_key() {
+        let mut keystate = KeyState::default();
+        assert_eq!(keystate.first_pressed(), None);
+        keystate.set(Key::C);
+        keystate.set(Key::Four);
+        assert_eq!(keystate.first_pressed(), Some(Key::Four));
+    }
 }
@@ -1,6 +1,7 @@
 use std::ops::{Index, IndexMut};
 
 #[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Register {
     V0,
     V1,
@@ -20,12 +21,22 @@ pub enum Register {
     VF,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Debug)]
 pub struct Registers {
     values: [u8; 16],
     pub image: u16,
 }
 
+impl Registers {
+    pub fn new(values: [u8; 16], image: u16) -> Self {
+        Self { values, image }
+    }
+
+    pub fn values(&self) -> &[u8; 16] {
+        &self.values
+    }
+}
+
 impl Index<Register> for Registers {
     type Output = u8;
     fn index(&self, index: Register) -> &Self::Output {
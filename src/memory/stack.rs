@@ -1,3 +1,4 @@
+#[derive(Clone)]
 pub struct Stack {
     sp: usize,
     bytes: [u16; 64 / 2],
@@ -13,16 +14,28 @@ impl Default for Stack {
 }
 
 impl Stack {
-    pub fn call(&mut self, current_pc: u16) {
-        self.bytes[self.sp] = current_pc;
+    // None when the stack is already full.
+    pub fn call(&mut self, current_pc: u16) -> Option<()> {
+        *self.bytes.get_mut(self.sp)? = current_pc;
         self.sp += 1;
+        Some(())
     }
 
-    pub fn ret(&mut self) -> u16 {
-        self.sp = self
-            .sp
-            .checked_sub(1)
-            .expect("illegal instruction, tried to return but stack is empty");
-        self.bytes[self.sp]
+    // None when the stack is empty.
+    pub fn ret(&mut self) -> Option<u16> {
+        self.sp = self.sp.checked_sub(1)?;
+        Some(self.bytes[self.sp])
+    }
+
+    pub fn entries(&self) -> &[u16] {
+        &self.bytes[..self.sp]
+    }
+
+    pub fn from_entries(entries: &[u16]) -> Self {
+        let mut this = Self::default();
+        for &entry in entries.iter().take(this.bytes.len()) {
+            this.call(entry);
+        }
+        this
     }
 }
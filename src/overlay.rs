@@ -0,0 +1,347 @@
+pub mod font;
+
+use chipeite::{
+    disassemble,
+    memory::PROGRAM_START,
+    sprite::{self, Format, Sprite},
+    Chip8, Key,
+};
+
+use crate::frontend::Command;
+
+// Columns of text the panels are laid out for.
+pub const COLUMNS: usize = 24;
+pub const MEMORY_COLUMNS: usize = 4 + 3 * BYTES_PER_ROW;
+
+pub const SPRITE_COLUMNS: usize = 32;
+
+// Rows of bytes the memory view shows at once.
+pub const MEMORY_ROWS: usize = 32;
+const BYTES_PER_ROW: usize = 8;
+// Bytes written this many frames ago or less stand out.
+const RECENT_FRAMES: u16 = 30;
+
+// Rows of sprites the sprite viewer shows at once.
+const SPRITE_ROWS: usize = 6;
+
+// How many instructions the disassembly shows before and after the pc.
+const BEFORE_PC: u16 = 5;
+const AFTER_PC: u16 = 10;
+
+// Keypad order on the COSMAC VIP.
+const KEYPAD: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Plain,
+    // The pc, pressed keys and the byte being edited.
+    Highlight,
+    // Where I points.
+    Marked,
+    // Recently written.
+    Written,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Click {
+    Command(Command),
+    // Starts typing a new value for this byte.
+    Edit(u16),
+    // Scrolls the memory view to this address.
+    Show(u16),
+    SpriteFormat(Format),
+    // Moves the sprite viewer by this many bytes.
+    MoveSprites(i64),
+    ShowSprites(u16),
+    // Saves the sprite at this address as a PNG.
+    Export(u16),
+}
+
+// A run of text on one line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+    pub click: Option<Click>,
+}
+
+impl Span {
+    fn plain(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            style: Style::Plain,
+            click: None,
+        }
+    }
+
+    fn styled(text: impl Into<String>, style: Style) -> Self {
+        Self {
+            style,
+            ..Self::plain(text)
+        }
+    }
+
+    fn highlighted(text: impl Into<String>, highlighted: bool) -> Self {
+        let style = if highlighted {
+            Style::Highlight
+        } else {
+            Style::Plain
+        };
+        Self::styled(text, style)
+    }
+
+    fn button(text: &str, click: Click) -> Self {
+        Self {
+            click: Some(click),
+            ..Self::plain(format!("[{text}]"))
+        }
+    }
+
+    fn toggle(text: &str, click: Click, on: bool) -> Self {
+        Self {
+            click: Some(click),
+            ..Self::highlighted(format!("[{text}]"), on)
+        }
+    }
+}
+
+pub type Line = Vec<Span>;
+
+// The debug panel: registers, timers, the stack, the keypad, a disassembly
+// around the pc and the pause and step buttons.
+pub fn panel(chip8: &Chip8, paused: bool) -> Vec<Line> {
+    let registers = chip8.registers();
+    let mut lines = vec![
+        vec![Span::plain(format!(
+            "PC {:03X}  I {:03X}  SP {:X}",
+            chip8.pc(),
+            registers.image,
+            chip8.stack().len()
+        ))],
+        vec![Span::plain(format!(
+            "DT {:02X}  ST {:02X}",
+            chip8.delay_timer(),
+            chip8.sound_timer()
+        ))],
+        Vec::new(),
+    ];
+    for (row, values) in registers.values().chunks(4).enumerate() {
+        let text: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(n, value)| format!("V{:X} {value:02X}", row * 4 + n))
+            .collect();
+        lines.push(vec![Span::plain(text.join(" "))]);
+    }
+
+    lines.push(Vec::new());
+    let stack: Vec<String> = chip8
+        .stack()
+        .iter()
+        .map(|addr| format!("{addr:03X}"))
+        .collect();
+    if stack.is_empty() {
+        lines.push(vec![Span::plain("STACK -")]);
+    }
+    for (n, entries) in stack.chunks(4).enumerate() {
+        let label = if n == 0 { "STACK" } else { "     " };
+        lines.push(vec![Span::plain(format!("{label} {}", entries.join(" ")))]);
+    }
+
+    lines.push(Vec::new());
+    let keys = chip8.key_state();
+    for (n, row) in KEYPAD.iter().enumerate() {
+        let label = if n == 0 { "KEYS " } else { "     " };
+        let mut line = vec![Span::plain(label)];
+        for &key in row {
+            line.push(Span::highlighted(
+                format!("{key:X}"),
+                keys.is_set(Key::from(key)),
+            ));
+            line.push(Span::plain(" "));
+        }
+        lines.push(line);
+    }
+
+    lines.push(Vec::new());
+    let pc = chip8.pc();
+    let ram = chip8.ram();
+    let first = pc.saturating_sub(2 * BEFORE_PC);
+    for addr in (first..=pc.saturating_add(2 * AFTER_PC)).step_by(2) {
+        let (Some(&hi), Some(&lo)) = (ram.get(addr as usize), ram.get(addr as usize + 1)) else {
+            break;
+        };
+        let opcode = u16::from_be_bytes([hi, lo]);
+        let mnemonic = disassemble(opcode, chip8.config().variant);
+        let text = format!("{addr:03X} {opcode:04X} {mnemonic}");
+        lines.push(vec![Span::highlighted(text, addr == pc)]);
+    }
+
+    lines.push(Vec::new());
+    let pause = if paused { "RUN" } else { "PAUSE" };
+    lines.push(vec![
+        Span::button(pause, Click::Command(Command::Pause)),
+        Span::plain(" "),
+        Span::button("STEP", Click::Command(Command::Step)),
+    ]);
+    lines
+}
+
+// A hex dump of ram from `start`, with the bytes at the pc and I marked and
+// recent writes standing out. `editing` is the byte being typed into and its
+// first digit, if that's been typed.
+pub fn memory(
+    chip8: &Chip8,
+    start: u16,
+    ages: Option<&[u16]>,
+    editing: Option<(u16, Option<u8>)>,
+) -> Vec<Line> {
+    let (pc, i) = (chip8.pc() as usize, chip8.registers().image as usize);
+    let mut lines = vec![
+        vec![
+            Span::button("PC", Click::Show(pc as u16)),
+            Span::plain(" "),
+            Span::button("I", Click::Show(i as u16)),
+            Span::plain(" "),
+            Span::button("200", Click::Show(PROGRAM_START)),
+        ],
+        Vec::new(),
+    ];
+    let ram = chip8.ram();
+    let start = start as usize;
+    for row in (start..ram.len()).step_by(BYTES_PER_ROW).take(MEMORY_ROWS) {
+        let mut line = vec![Span::plain(format!("{row:03X} "))];
+        for (addr, &value) in ram.iter().enumerate().skip(row).take(BYTES_PER_ROW) {
+            let (text, style) = match editing {
+                Some((at, first)) if at as usize == addr => {
+                    let text = first.map_or("__".to_owned(), |digit| format!("{digit:X}_"));
+                    (text, Style::Highlight)
+                }
+                _ if addr == pc || addr == pc + 1 => (format!("{value:02X}"), Style::Highlight),
+                _ if addr == i => (format!("{value:02X}"), Style::Marked),
+                _ if ages.is_some_and(|ages| ages[addr] <= RECENT_FRAMES) => {
+                    (format!("{value:02X}"), Style::Written)
+                }
+                _ => (format!("{value:02X}"), Style::Plain),
+            };
+            line.push(Span {
+                click: Some(Click::Edit(addr as u16)),
+                ..Span::styled(text, style)
+            });
+            line.push(Span::plain(" "));
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+// `addr` as the first address of a row, with a full view of ram after it.
+fn memory_start(addr: i64, len: usize) -> u16 {
+    let last = len.saturating_sub(MEMORY_ROWS * BYTES_PER_ROW) as i64;
+    let row = BYTES_PER_ROW as i64;
+    (addr.clamp(0, last) / row * row) as u16
+}
+
+// The memory view moved by `rows`.
+pub fn scroll(start: u16, rows: i64, len: usize) -> u16 {
+    memory_start(start as i64 + rows * BYTES_PER_ROW as i64, len)
+}
+
+// A few rows above `addr`, so what comes before it shows too.
+pub fn show(addr: u16, len: usize) -> u16 {
+    memory_start(addr as i64 - 4 * BYTES_PER_ROW as i64, len)
+}
+
+// Sprites per row and rows of them in the sprite viewer.
+pub fn sprite_grid(format: Format) -> (usize, usize) {
+    let columns = if format.width() == 8 { 8 } else { 4 };
+    (columns, SPRITE_ROWS)
+}
+
+// The sprite viewer's format buttons, moves and jumps. The sprite the last
+// Dxyn drew from can be jumped to, and `status` goes under it all.
+pub fn sprite_controls(chip8: &Chip8, start: u16, format: Format, status: &str) -> Vec<Line> {
+    let xo = format.planes() == 2;
+    let rows = if format.width() == 8 {
+        format.height()
+    } else {
+        8
+    } as u8;
+    let eight = |rows: u8| {
+        let rows = rows.clamp(1, 15);
+        Click::SpriteFormat(if xo {
+            Format::XoChip(rows)
+        } else {
+            Format::Chip8(rows)
+        })
+    };
+    let sixteen = Click::SpriteFormat(if xo { Format::XoChip(0) } else { Format::Schip });
+    let toggle_xo = Click::SpriteFormat(match format {
+        Format::Chip8(rows) => Format::XoChip(rows),
+        Format::Schip => Format::XoChip(0),
+        Format::XoChip(0) => Format::Schip,
+        Format::XoChip(rows) => Format::Chip8(rows),
+    });
+    let page = (format.bytes() * sprite_grid(format).0 * SPRITE_ROWS) as i64;
+    let mut jumps = vec![
+        Span::button("I", Click::ShowSprites(chip8.registers().image)),
+        Span::plain(" "),
+        Span::button("200", Click::ShowSprites(PROGRAM_START)),
+    ];
+    if let Some((addr, _)) = chip8.last_sprite() {
+        jumps.push(Span::plain(" "));
+        jumps.push(Span::button("DXYN", Click::ShowSprites(addr)));
+    }
+    vec![
+        vec![
+            Span::toggle("8XN", eight(rows), format.width() == 8),
+            Span::plain(" "),
+            Span::toggle("16X16", sixteen, format.width() == 16),
+            Span::plain(" "),
+            Span::toggle("XO", toggle_xo, xo),
+        ],
+        vec![
+            Span::button("-", eight(rows - 1)),
+            Span::plain(format!(" {:2} ROWS ", format.height())),
+            Span::button("+", eight(rows + 1)),
+        ],
+        vec![
+            Span::button("<", Click::MoveSprites(-page)),
+            Span::plain(" "),
+            Span::button("-1", Click::MoveSprites(-1)),
+            Span::plain(format!(" {start:03X} ")),
+            Span::button("+1", Click::MoveSprites(1)),
+            Span::plain(" "),
+            Span::button(">", Click::MoveSprites(page)),
+        ],
+        jumps,
+        vec![Span::plain(status)],
+        Vec::new(),
+    ]
+}
+
+// A page of sprites from `start`, and whether the last Dxyn drew from each.
+pub fn sprites(chip8: &Chip8, start: u16, format: Format) -> Vec<(Sprite, bool)> {
+    let (columns, rows) = sprite_grid(format);
+    let ram = chip8.ram();
+    let end = (start as usize + columns * rows * format.bytes()).min(ram.len());
+    sprite::sheet(ram, start, end as u16, format)
+        .into_iter()
+        .map(|sprite| {
+            let drawn = chip8
+                .last_sprite()
+                .is_some_and(|(addr, len)| sprite.overlaps(addr, len));
+            (sprite, drawn)
+        })
+        .collect()
+}
+
+// Where the sprite viewer starts after moving by `bytes`, kept within ram.
+pub fn move_sprites(start: u16, bytes: i64, len: usize) -> u16 {
+    (start as i64 + bytes).clamp(0, len as i64 - 1) as u16
+}
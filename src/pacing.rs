@@ -0,0 +1,59 @@
+use crate::machine::Machine;
+
+// Frames run per displayed frame while fast-forwarding.
+const FAST_FORWARD: usize = 8;
+// Slow motion runs a frame every this many displayed frames.
+const SLOW_MOTION: u64 = 4;
+// What the speed hotkey cycles through.
+const PRESETS: [usize; 8] = [7, 10, 15, 20, 30, 100, 200, 1000];
+
+// How many frames the frame loop runs each time round, from the hotkeys.
+// Pausing is the machine's, so the debugger and the overlay agree on it.
+#[derive(Debug, Default)]
+pub struct Pacing {
+    pub fast_forward: bool,
+    pub slow_motion: bool,
+    frame: u64,
+}
+
+impl Pacing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Frames to run before the next one is shown.
+    pub fn frames(&mut self) -> usize {
+        self.frame += 1;
+        if self.fast_forward {
+            FAST_FORWARD
+        } else if self.slow_motion {
+            self.frame.is_multiple_of(SLOW_MOTION) as usize
+        } else {
+            1
+        }
+    }
+
+    // e.g. `paused, 15 ipf`.
+    pub fn status(&self, machine: &dyn Machine) -> String {
+        let mut parts = Vec::new();
+        if machine.is_paused() {
+            parts.push("paused".to_owned());
+        } else if self.fast_forward {
+            parts.push(format!("fast forward x{FAST_FORWARD}"));
+        } else if self.slow_motion {
+            parts.push(format!("slow motion 1/{SLOW_MOTION}"));
+        }
+        if let Some(n) = machine.instructions_per_frame() {
+            parts.push(format!("{n} ipf"));
+        }
+        parts.join(", ")
+    }
+}
+
+// The next preset up from `n`, back to the first after the last.
+pub fn next_preset(n: usize) -> usize {
+    PRESETS
+        .into_iter()
+        .find(|&preset| preset > n)
+        .unwrap_or(PRESETS[0])
+}
@@ -0,0 +1,293 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use crate::{memory::FOUR_K, trace::Record};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Subroutine {
+    pub calls: u64,
+    // Instructions run while it was anywhere on the call stack.
+    pub inclusive: u64,
+    // Instructions run in its own body.
+    pub exclusive: u64,
+}
+
+// Builds a profile out of trace records, see `Chip8::run_frame_with`. The
+// call stack follows 2nnn and the stack depth in the records, so returns
+// need no special handling.
+pub struct Profiler {
+    instructions: u64,
+    counts: Vec<u64>,
+    mnemonics: HashMap<u16, String>,
+    stack: Vec<u16>,
+    subroutines: BTreeMap<u16, Subroutine>,
+    folded: HashMap<Vec<u16>, u64>,
+    key_wait_frames: u64,
+    delay_wait_frames: u64,
+    delay_wait_instructions: u64,
+    last_key_wait_frame: Option<u64>,
+    last_delay_wait_frame: Option<u64>,
+    last_delay_read: Option<(u16, u64)>,
+}
+
+// A loop this short around an Fx07 is waiting for the delay timer.
+const SPIN_LOOP_LENGTH: u64 = 4;
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            instructions: 0,
+            counts: vec![0; FOUR_K],
+            mnemonics: HashMap::new(),
+            stack: Vec::new(),
+            subroutines: BTreeMap::new(),
+            folded: HashMap::new(),
+            key_wait_frames: 0,
+            delay_wait_frames: 0,
+            delay_wait_instructions: 0,
+            last_key_wait_frame: None,
+            last_delay_wait_frame: None,
+            last_delay_read: None,
+        }
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&mut self, record: &Record) {
+        let pc = record.pc;
+        self.instructions += 1;
+        self.counts[pc as usize] += 1;
+        self.mnemonics
+            .entry(pc)
+            .or_insert_with(|| record.mnemonic.clone());
+
+        // The first instruction seen is where the program starts.
+        if self.stack.is_empty() {
+            self.stack.push(pc);
+            self.subroutines.entry(pc).or_default().calls += 1;
+        }
+        self.stack.truncate(record.sp + 1);
+        for (n, &addr) in self.stack.iter().enumerate() {
+            if !self.stack[..n].contains(&addr) {
+                self.subroutines.entry(addr).or_default().inclusive += 1;
+            }
+        }
+        if let Some(&top) = self.stack.last() {
+            self.subroutines.entry(top).or_default().exclusive += 1;
+        }
+        match self.folded.get_mut(self.stack.as_slice()) {
+            Some(count) => *count += 1,
+            None => {
+                self.folded.insert(self.stack.clone(), 1);
+            }
+        }
+
+        match (record.opcode >> 12, record.opcode & 0xFF) {
+            (0x2, _) => {
+                let addr = record.opcode & 0xFFF;
+                self.stack.push(addr);
+                self.subroutines.entry(addr).or_default().calls += 1;
+            }
+            (0xF, 0x0A) => {
+                count_frame(
+                    &mut self.key_wait_frames,
+                    &mut self.last_key_wait_frame,
+                    record.frame,
+                );
+            }
+            (0xF, 0x07) => {
+                if let Some((last_pc, last_cycle)) = self.last_delay_read {
+                    let since = record.cycle.checked_sub(last_cycle);
+                    if let Some(since) = since.filter(|&n| last_pc == pc && n <= SPIN_LOOP_LENGTH) {
+                        self.delay_wait_instructions += since;
+                        count_frame(
+                            &mut self.delay_wait_frames,
+                            &mut self.last_delay_wait_frame,
+                            record.frame,
+                        );
+                    }
+                }
+                self.last_delay_read = Some((pc, record.cycle));
+            }
+            _ => {}
+        }
+    }
+
+    // The program started over, e.g. a reset: forgets where it was but
+    // keeps the counts.
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.last_key_wait_frame = None;
+        self.last_delay_wait_frame = None;
+        self.last_delay_read = None;
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn count(&self, addr: u16) -> u64 {
+        self.counts[addr as usize]
+    }
+
+    pub fn subroutine(&self, addr: u16) -> Option<Subroutine> {
+        self.subroutines.get(&addr).copied()
+    }
+
+    // Frames in which the program sat on an Fx0A.
+    pub fn key_wait_frames(&self) -> u64 {
+        self.key_wait_frames
+    }
+
+    // Frames in which it spun on the delay timer.
+    pub fn delay_wait_frames(&self) -> u64 {
+        self.delay_wait_frames
+    }
+
+    // The `top` hottest addresses and every subroutine, as plain text.
+    pub fn report(&self, top: usize) -> String {
+        let total = self.instructions.max(1) as f64;
+        let percent = |n: u64| 100.0 * n as f64 / total;
+        let mut out = String::new();
+        let _ = writeln!(out, "{} instructions", self.instructions);
+        let _ = writeln!(
+            out,
+            "{} frames waiting for a key (Fx0A)",
+            self.key_wait_frames
+        );
+        let _ = writeln!(
+            out,
+            "{} frames spinning on the delay timer, {} instructions ({:.1}%)",
+            self.delay_wait_frames,
+            self.delay_wait_instructions,
+            percent(self.delay_wait_instructions)
+        );
+
+        let mut hottest: Vec<_> = (0..FOUR_K as u16)
+            .filter(|&addr| self.count(addr) > 0)
+            .collect();
+        hottest.sort_by_key(|&addr| std::cmp::Reverse(self.count(addr)));
+        let _ = writeln!(out, "\nhottest addresses");
+        for addr in hottest.into_iter().take(top) {
+            let count = self.count(addr);
+            let mnemonic = self.mnemonics.get(&addr).map_or("", String::as_str);
+            let _ = writeln!(
+                out,
+                "  {addr:03X}  {count:>10} {:>5.1}%  {mnemonic}",
+                percent(count)
+            );
+        }
+
+        let mut subroutines: Vec<_> = self.subroutines.iter().collect();
+        subroutines.sort_by_key(|(_, s)| std::cmp::Reverse(s.inclusive));
+        let _ = writeln!(
+            out,
+            "\nsubroutines      calls  inclusive          exclusive"
+        );
+        for (&addr, s) in subroutines {
+            let _ = writeln!(
+                out,
+                "  {:<10} {:>8} {:>10} {:>5.1}% {:>10} {:>5.1}%",
+                name(addr),
+                s.calls,
+                s.inclusive,
+                percent(s.inclusive),
+                s.exclusive,
+                percent(s.exclusive)
+            );
+        }
+        out
+    }
+
+    // One `outer;inner count` line per call stack, for flamegraph.pl,
+    // inferno and speedscope.
+    pub fn folded(&self) -> String {
+        let mut lines: Vec<_> = self
+            .folded
+            .iter()
+            .map(|(stack, count)| {
+                let names: Vec<_> = stack.iter().map(|&addr| name(addr)).collect();
+                format!("{} {count}", names.join(";"))
+            })
+            .collect();
+        lines.sort();
+        lines.iter().map(|line| format!("{line}\n")).collect()
+    }
+}
+
+fn name(addr: u16) -> String {
+    format!("sub_{addr:03X}")
+}
+
+fn count_frame(frames: &mut u64, last: &mut Option<u64>, frame: u64) {
+    if *last != Some(frame) {
+        *frames += 1;
+        *last = Some(frame);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Chip8, Config};
+
+    #[rustfmt::skip]
+    const ROM: &[u8] = &[
+        0x22, 0x0A, // 200 CALL 20A
+        0xF1, 0x07, // 202 LD V1, DT
+        0x31, 0x00, // 204 SE V1, 0
+        0x12, 0x02, // 206 JP 202
+        0x12, 0x08, // 208 JP 208
+        0x61, 0x03, // 20A LD V1, 3
+        0xF1, 0x15, // 20C LD DT, V1
+        0x00, 0xEE, // 20E RET
+    ];
+
+    #[test]
+    fn follows_calls_and_delay_loops() {
+        let mut chip8 = Chip8::new(ROM, Config::default()).unwrap();
+        let mut profiler = Profiler::new();
+        for _ in 0..5 {
+            chip8
+                .run_frame_with(&mut |record| profiler.observe(record))
+                .unwrap();
+        }
+        assert_eq!(profiler.instructions(), 50);
+        let sub = profiler.subroutine(0x20A).unwrap();
+        assert_eq!((sub.calls, sub.inclusive, sub.exclusive), (1, 3, 3));
+        let main = profiler.subroutine(0x200).unwrap();
+        assert_eq!((main.inclusive, main.exclusive), (50, 47));
+        assert_eq!(profiler.delay_wait_frames(), 4);
+        assert!(profiler
+            .folded()
+            .lines()
+            .eq(["sub_200 47", "sub_200;sub_20A 3"]));
+    }
+
+    #[test]
+    fn carries_on_after_a_reset() {
+        let mut chip8 = Chip8::new(ROM, Config::default()).unwrap();
+        let mut profiler = Profiler::new();
+        for _ in 0..3 {
+            chip8
+                .run_frame_with(&mut |record| profiler.observe(record))
+                .unwrap();
+        }
+        chip8.reset();
+        profiler.reset();
+        for _ in 0..3 {
+            chip8
+                .run_frame_with(&mut |record| profiler.observe(record))
+                .unwrap();
+        }
+        assert_eq!(profiler.instructions(), 60);
+        assert_eq!(profiler.subroutine(0x20A).unwrap().calls, 2);
+        assert_eq!(profiler.subroutine(0x200).unwrap().calls, 2);
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use chipeite::Snapshot;
+
+use crate::machine::Machine;
+
+// Files are looked at every this many frames.
+const POLL_FRAMES: u64 = 15;
+
+// Reads the ROM file again, from the hotkey or when --watch sees it change,
+// and puts the --state save state back on top.
+pub struct Reloader {
+    rom_path: PathBuf,
+    watcher: Option<Watcher>,
+    state: Option<PathBuf>,
+}
+
+impl Reloader {
+    pub fn new(rom_path: &Path, watched: Option<Vec<PathBuf>>, state: Option<PathBuf>) -> Self {
+        Self {
+            rom_path: rom_path.to_owned(),
+            watcher: watched.map(Watcher::new),
+            state,
+        }
+    }
+
+    // Once a frame.
+    pub fn poll(&mut self, machine: &mut dyn Machine) {
+        if self.watcher.as_mut().is_some_and(Watcher::poll) {
+            self.reload(machine);
+        }
+    }
+
+    // A ROM that doesn't load leaves the old one running, to be fixed and
+    // reloaded again.
+    pub fn reload(&mut self, machine: &mut dyn Machine) {
+        let reloaded = crate::read_rom(&self.rom_path).and_then(|rom| machine.reload(&rom.bytes));
+        if let Err(e) = reloaded.and_then(|()| self.restore(machine)) {
+            eprintln!("couldn't reload: {e:#}");
+        }
+    }
+
+    // Nothing to restore until the state was saved.
+    fn restore(&self, machine: &mut dyn Machine) -> anyhow::Result<()> {
+        let Some(path) = self.state.as_deref().filter(|path| path.exists()) else {
+            return Ok(());
+        };
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("couldn't read {}", path.display()))?;
+        let snapshot: Snapshot = serde_json::from_str(&text)
+            .with_context(|| format!("couldn't parse {}", path.display()))?;
+        machine.rebase(&snapshot);
+        Ok(())
+    }
+
+    pub fn save_state(&self, machine: &dyn Machine) {
+        let (Some(path), Some(snapshot)) = (&self.state, machine.snapshot()) else {
+            eprintln!("save states need --state");
+            return;
+        };
+        let saved = serde_json::to_string(&snapshot)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| Ok(fs::write(path, json)?));
+        if let Err(e) = saved {
+            eprintln!("couldn't save {}: {e:#}", path.display());
+        }
+    }
+}
+
+// Polls modification times. A change counts once the file has stopped
+// changing, so a half-written ROM isn't loaded.
+struct Watcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    changing: bool,
+    frame: u64,
+}
+
+impl Watcher {
+    fn new(paths: Vec<PathBuf>) -> Self {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let modified = modified(&path);
+                (path, modified)
+            })
+            .collect();
+        Self {
+            files,
+            changing: false,
+            frame: 0,
+        }
+    }
+
+    fn poll(&mut self) -> bool {
+        self.frame += 1;
+        if !self.frame.is_multiple_of(POLL_FRAMES) {
+            return false;
+        }
+        let mut changed = false;
+        for (path, last) in &mut self.files {
+            let modified = modified(path);
+            if modified != *last {
+                *last = modified;
+                changed = true;
+            }
+        }
+        if changed {
+            self.changing = true;
+            return false;
+        }
+        std::mem::take(&mut self.changing)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn touch(path: &Path, seconds: u64) {
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    // The frames `poll` said to reload on, up to frame `last`.
+    fn reloads(watcher: &mut Watcher, last: u64) -> Vec<u64> {
+        (watcher.frame + 1..=last)
+            .filter(|_| watcher.poll())
+            .collect()
+    }
+
+    #[test]
+    fn reloads_once_the_file_settles() {
+        let path = std::env::temp_dir().join(format!("chipeite-watch-{}.ch8", std::process::id()));
+        fs::write(&path, [0x12, 0x00]).unwrap();
+        touch(&path, 1000);
+        let mut watcher = Watcher::new(vec![path.clone()]);
+        assert!(reloads(&mut watcher, 20).is_empty());
+
+        // Seen at frame 30, still the same at 45.
+        touch(&path, 1001);
+        assert_eq!(reloads(&mut watcher, 100), [45]);
+
+        // Changing again while it settles puts the reload off.
+        touch(&path, 1002);
+        assert!(reloads(&mut watcher, 110).is_empty());
+        touch(&path, 1003);
+        assert_eq!(reloads(&mut watcher, 200), [135]);
+        fs::remove_file(&path).unwrap();
+    }
+}
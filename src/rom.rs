@@ -0,0 +1,216 @@
+pub mod cartridge;
+pub mod error;
+pub mod octo;
+
+use std::io::{Cursor, Read};
+
+use self::{cartridge::Options, error::RomError};
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const ROM_EXTENSIONS: &[&str] = &["ch8", "c8", "c8x", "hex", "gif"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Binary,
+    HexText,
+    Cartridge,
+    Zip,
+}
+
+pub struct Rom {
+    pub format: Format,
+    pub bytes: Vec<u8>,
+    // Only set for Octo cartridges.
+    pub options: Option<Options>,
+}
+
+// Unwraps whatever container the program came in. The format is sniffed from
+// the content, anything unrecognised is taken as a raw binary.
+pub fn decode(data: &[u8]) -> Result<Rom, RomError> {
+    if cartridge::MAGIC.iter().any(|magic| data.starts_with(magic)) {
+        let cartridge = cartridge::decode(data)?;
+        return Ok(Rom {
+            format: Format::Cartridge,
+            bytes: octo::assemble(&cartridge.program)?,
+            options: Some(cartridge.options),
+        });
+    }
+    if data.starts_with(ZIP_MAGIC) {
+        let rom = decode(&unzip(data)?)?;
+        return Ok(Rom {
+            format: Format::Zip,
+            ..rom
+        });
+    }
+    if let Some(bytes) = std::str::from_utf8(data).ok().and_then(hex_text) {
+        return Ok(Rom {
+            format: Format::HexText,
+            bytes,
+            options: None,
+        });
+    }
+    Ok(Rom {
+        format: Format::Binary,
+        bytes: data.to_vec(),
+        options: None,
+    })
+}
+
+// The only file in the archive, or the only one that looks like a ROM.
+fn unzip(data: &[u8]) -> Result<Vec<u8>, RomError> {
+    let err = |e: zip::result::ZipError| RomError::Zip(e.to_string());
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(err)?;
+    let mut files = Vec::new();
+    for index in 0..archive.len() {
+        let file = archive.by_index(index).map_err(err)?;
+        if file.is_file() {
+            files.push((index, file.name().to_owned()));
+        }
+    }
+    if files.len() > 1 {
+        files.retain(|(_, name)| {
+            let extension = name.rsplit_once('.').map(|(_, e)| e.to_ascii_lowercase());
+            extension.is_some_and(|e| ROM_EXTENSIONS.contains(&e.as_str()))
+        });
+    }
+    let index = match &files[..] {
+        [] => return Err(RomError::NoRomInZip),
+        [(index, _)] => *index,
+        _ => {
+            return Err(RomError::AmbiguousZip(
+                files.into_iter().map(|(_, name)| name).collect(),
+            ))
+        }
+    };
+    let mut rom = Vec::new();
+    archive
+        .by_index(index)
+        .map_err(err)?
+        .read_to_end(&mut rom)
+        .map_err(|e| RomError::Zip(e.to_string()))?;
+    Ok(rom)
+}
+
+// Hex bytes separated by whitespace or commas, with optional `0x` prefixes,
+// `0200:` style addresses and `#`, `;` or `//` comments.
+fn hex_text(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for line in text.lines() {
+        let line = ["#", ";", "//"].iter().fold(line, |line, comment| {
+            line.split(comment).next().unwrap_or_default()
+        });
+        for token in line.split(|c: char| c.is_whitespace() || c == ',') {
+            if token.is_empty() || token.ends_with(':') {
+                continue;
+            }
+            let digits = token.strip_prefix("0x").unwrap_or(token);
+            if digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            for pair in digits.as_bytes().chunks(2) {
+                bytes.push(u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?);
+            }
+        }
+    }
+    (!bytes.is_empty()).then_some(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    const PROGRAM: [u8; 4] = [0x00, 0xE0, 0x12, 0x00];
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn binaries_are_taken_as_is() {
+        let rom = decode(&PROGRAM).unwrap();
+        assert_eq!(rom.format, Format::Binary);
+        assert_eq!(rom.bytes, PROGRAM);
+    }
+
+    #[test]
+    fn hex_listings_are_parsed() {
+        for text in [
+            "00E0 1200\n",
+            "0x00, 0xE0, 0x12, 0x00",
+            "0200: 00 E0 # clear\n0202: 12 00 ; loop\n",
+        ] {
+            let rom = decode(text.as_bytes()).unwrap();
+            assert_eq!(rom.format, Format::HexText, "{text}");
+            assert_eq!(rom.bytes, PROGRAM, "{text}");
+        }
+        assert_eq!(decode(b"hello").unwrap().format, Format::Binary);
+    }
+
+    #[test]
+    fn zipped_roms_are_unpacked() {
+        let data = zip(&[("readme.txt", b"hi"), ("game/pong.ch8", &PROGRAM)]);
+        let rom = decode(&data).unwrap();
+        assert_eq!(rom.format, Format::Zip);
+        assert_eq!(rom.bytes, PROGRAM);
+
+        let data = zip(&[("a.ch8", &PROGRAM), ("b.ch8", &PROGRAM)]);
+        assert!(matches!(decode(&data), Err(RomError::AmbiguousZip(_))));
+    }
+
+    #[test]
+    fn cartridges_carry_their_options() {
+        let gif =
+            cartridge::encode(r#"{"program": "0x00 0xE0 0x12 0x00", "options": {"tickrate": 7}}"#);
+        let rom = decode(&gif).unwrap();
+        assert_eq!(rom.format, Format::Cartridge);
+        assert_eq!(rom.bytes, PROGRAM);
+        assert_eq!(rom.options.unwrap().tickrate, Some(7));
+
+        let gif = cartridge::encode(r#"{"program": ": main loop"}"#);
+        assert!(matches!(decode(&gif), Err(RomError::Octo { line: 1, .. })));
+    }
+
+    #[test]
+    fn cartridges_with_octo_source_are_assembled() {
+        // Laid out like the payload Octo's "save cartridge" writes.
+        let gif = cartridge::encode(
+            r##"{
+                "program": ": main\n  clear\n  i := hex v0\n  sprite v0 v0 5\n  loop again\n",
+                "options": {
+                    "tickrate": 20,
+                    "fillColor": "#FFCC00",
+                    "fillColor2": "#FF6600",
+                    "blendColor": "#662200",
+                    "backgroundColor": "#996600",
+                    "buzzColor": "#FFAA00",
+                    "quietColor": "#000000",
+                    "shiftQuirks": false,
+                    "loadStoreQuirks": false,
+                    "vfOrderQuirks": false,
+                    "clipQuirks": false,
+                    "vBlankQuirks": false,
+                    "jumpQuirks": false,
+                    "logicQuirks": false,
+                    "screenRotation": 0,
+                    "maxSize": 3215,
+                    "touchInputMode": "none",
+                    "fontStyle": "octo"
+                }
+            }"##,
+        );
+        let cartridge = cartridge::decode(&gif).unwrap();
+        assert_eq!(cartridge.options.tickrate, Some(20));
+        assert_eq!(
+            decode(&gif).unwrap().bytes,
+            [0x00, 0xE0, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06]
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use std::io::Cursor;
+
+use serde::Deserialize;
+
+use crate::{cpu::quirks::Quirks, database::parse_color};
+
+use super::error::RomError;
+
+pub const MAGIC: [&[u8]; 2] = [b"GIF87a", b"GIF89a"];
+
+// The settings Octo saves next to the program.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Options {
+    pub tickrate: Option<usize>,
+    pub background_color: Option<String>,
+    pub fill_color: Option<String>,
+    pub shift_quirks: bool,
+    pub load_store_quirks: bool,
+    pub clip_quirks: bool,
+    pub jump_quirks: bool,
+    pub v_blank_quirks: bool,
+    pub logic_quirks: bool,
+}
+
+impl Options {
+    pub fn quirks(&self) -> Quirks {
+        Quirks {
+            shift: self.shift_quirks,
+            memory_leave_i_unchanged: self.load_store_quirks,
+            wrap: !self.clip_quirks,
+            jump: self.jump_quirks,
+            vblank: self.v_blank_quirks,
+            logic: self.logic_quirks,
+            ..Quirks::default()
+        }
+    }
+
+    // Background and foreground.
+    pub fn palette(&self) -> Option<[[u8; 3]; 2]> {
+        Some([
+            parse_color(self.background_color.as_deref()?)?,
+            parse_color(self.fill_color.as_deref()?)?,
+        ])
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Cartridge {
+    pub program: String,
+    #[serde(default)]
+    pub options: Options,
+}
+
+// The payload lives in the low nibble of every pixel's palette index, across
+// all frames, two pixels per byte with the high nibble first. It starts with
+// a big endian u32 length followed by that many bytes of JSON.
+pub fn decode(gif: &[u8]) -> Result<Cartridge, RomError> {
+    let err = |e: &dyn std::fmt::Display| RomError::Cartridge(e.to_string());
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(Cursor::new(gif)).map_err(|e| err(&e))?;
+    let mut nibbles = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(|e| err(&e))? {
+        nibbles.extend(frame.buffer.iter().map(|i| i & 0xF));
+    }
+    let bytes: Vec<u8> = nibbles.chunks_exact(2).map(|n| n[0] << 4 | n[1]).collect();
+
+    let (len, payload) = bytes
+        .split_first_chunk::<4>()
+        .ok_or_else(|| err(&"no payload"))?;
+    let payload = payload
+        .get(..u32::from_be_bytes(*len) as usize)
+        .ok_or_else(|| err(&"payload is truncated"))?;
+    serde_json::from_slice(payload).map_err(|e| err(&e))
+}
+
+#[cfg(test)]
+pub(super) fn encode(json: &str) -> Vec<u8> {
+    let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+    payload.extend(json.bytes());
+    let mut indices: Vec<u8> = payload.iter().flat_map(|b| [b >> 4, b & 0xF]).collect();
+    let width = 32;
+    indices.resize(indices.len().next_multiple_of(width), 0);
+    let palette: Vec<u8> = (0..16).flat_map(|i| [i * 16, i * 16, i * 16]).collect();
+
+    let mut gif = Vec::new();
+    let mut encoder = gif::Encoder::new(
+        &mut gif,
+        width as u16,
+        (indices.len() / width) as u16,
+        &palette,
+    )
+    .unwrap();
+    let frame = gif::Frame {
+        width: width as u16,
+        height: (indices.len() / width) as u16,
+        buffer: indices.into(),
+        ..gif::Frame::default()
+    };
+    encoder.write_frame(&frame).unwrap();
+    drop(encoder);
+    gif
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn payload_round_trips() {
+        let gif = encode(
+            r#"{"program": ": main 0x12 0x00", "options": {"tickrate": 20, "clipQuirks": true}}"#,
+        );
+        let cartridge = decode(&gif).unwrap();
+        assert_eq!(cartridge.program, ": main 0x12 0x00");
+        assert_eq!(cartridge.options.tickrate, Some(20));
+        assert!(!cartridge.options.quirks().wrap);
+    }
+}
@@ -0,0 +1,31 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomError {
+    Cartridge(String),
+    // Octo source the assembler couldn't handle.
+    Octo { line: usize, message: String },
+    Zip(String),
+    NoRomInZip,
+    AmbiguousZip(Vec<String>),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::Cartridge(e) => write!(f, "broken Octo cartridge: {e}"),
+            RomError::Octo { line, message } => {
+                write!(f, "the cartridge's Octo source, line {line}: {message}")
+            }
+            RomError::Zip(e) => write!(f, "broken zip archive: {e}"),
+            RomError::NoRomInZip => write!(f, "the zip archive contains no ROM"),
+            RomError::AmbiguousZip(names) => write!(
+                f,
+                "the zip archive contains several ROMs: {}",
+                names.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
@@ -0,0 +1,690 @@
+// An assembler for the core of Octo, the language cartridges store: labels,
+// :const, :alias, :org, :next, :unpack, :byte, the CHIP-8, SCHIP and XO-CHIP
+// statements, `if ... then`, `if ... begin ... else ... end` and
+// `loop ... while ... again`. Macros, :calc, :stringmode and the `<`, `>`,
+// `<=` and `>=` comparisons aren't supported.
+
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use super::error::RomError;
+
+const START: u16 = 0x200;
+
+const UNSUPPORTED: [&str; 6] = [
+    ":macro",
+    ":calc",
+    ":stringmode",
+    ":assert",
+    ":pointer",
+    ":string",
+];
+
+// The program as it's laid out from 0x200. Like Octo it starts with a jump to
+// `main`, unless `main` comes first or there isn't one.
+pub fn assemble(source: &str) -> Result<Vec<u8>, RomError> {
+    let tokens = source
+        .lines()
+        .enumerate()
+        .flat_map(|(n, line)| {
+            let code = line.split('#').next().unwrap_or_default();
+            code.split_whitespace().map(move |token| (token, n + 1))
+        })
+        .collect();
+    Assembler::new(tokens).run()
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Fill {
+    // The low 12 bits of the instruction.
+    Address,
+    // The word after `i := long`.
+    Long,
+    // The high nibble of the address, for `v0 :=` in :unpack.
+    High,
+    // The low byte, for `v1 :=` in :unpack.
+    Low,
+}
+
+// A label used before it's defined.
+struct Patch<'a> {
+    at: u16,
+    name: &'a str,
+    line: usize,
+    fill: Fill,
+}
+
+enum Block {
+    Loop { start: u16, exits: Vec<u16> },
+    If { jump: u16 },
+    Else { jump: u16 },
+}
+
+// The skips for a condition, for `then` (skip unless it holds) and for
+// `begin` and `while` (skip if it holds, over a jump).
+struct Condition {
+    then: u16,
+    begin: u16,
+}
+
+struct Assembler<'a> {
+    tokens: Vec<(&'a str, usize)>,
+    pos: usize,
+    line: usize,
+    image: Vec<u8>,
+    here: u16,
+    labels: HashMap<&'a str, u16>,
+    consts: HashMap<&'a str, i32>,
+    aliases: HashMap<&'a str, u16>,
+    patches: Vec<Patch<'a>>,
+    blocks: Vec<(Block, usize)>,
+}
+
+impl<'a> Assembler<'a> {
+    fn new(tokens: Vec<(&'a str, usize)>) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            line: 1,
+            image: Vec::new(),
+            here: START,
+            labels: HashMap::new(),
+            consts: HashMap::new(),
+            aliases: HashMap::new(),
+            patches: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Result<Vec<u8>, RomError> {
+        let defines_main = |w: &[(&str, usize)]| w[0].0 == ":" && w[1].0 == "main";
+        let main_first = self.tokens.len() >= 2 && defines_main(&self.tokens[..2]);
+        if !main_first && self.tokens.windows(2).any(defines_main) {
+            self.reference("main", 0x1000, Fill::Address)?;
+        }
+        while let Some(token) = self.next() {
+            self.statement(token)?;
+        }
+        if let Some(&(_, line)) = self.blocks.last() {
+            return Err(RomError::Octo {
+                line,
+                message: "this block is never closed".to_owned(),
+            });
+        }
+        for patch in std::mem::take(&mut self.patches) {
+            let value = self.labels.get(patch.name).ok_or_else(|| RomError::Octo {
+                line: patch.line,
+                message: format!("{} is never defined", patch.name),
+            })?;
+            self.line = patch.line;
+            self.fill(patch.at, patch.fill, *value as i32)?;
+        }
+        Ok(self.image)
+    }
+
+    fn statement(&mut self, token: &'a str) -> Result<(), RomError> {
+        match token {
+            ":" => {
+                let name = self.name()?;
+                self.define(name, self.here)?;
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.define(name, self.here + 1)?;
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.known(i32::from(i16::MIN)..=0xFFFF, "a value")?;
+                self.check_unused(name)?;
+                self.consts.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+            }
+            ":org" => self.here = self.known(i32::from(START)..=0xFFFF, "an address")? as u16,
+            ":byte" => {
+                let byte = self.byte()?;
+                self.write(byte)?;
+            }
+            ":call" => {
+                let target = self.expect("an address")?;
+                self.reference(target, 0x2000, Fill::Address)?;
+            }
+            ":unpack" => {
+                let nibble = self.nibble()?;
+                let target = self.expect("a label")?;
+                self.reference(target, 0x6000 | nibble << 4, Fill::High)?;
+                self.reference(target, 0x6100, Fill::Low)?;
+            }
+            ":breakpoint" => {
+                self.expect("a name")?;
+            }
+            ":monitor" => {
+                self.expect("an address")?;
+                self.expect("a length or format")?;
+            }
+            "return" | ";" => self.emit(0x00EE)?,
+            "clear" => self.emit(0x00E0)?,
+            "exit" => self.emit(0x00FD)?,
+            "lores" => self.emit(0x00FE)?,
+            "hires" => self.emit(0x00FF)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(0x00C0 | n)?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(0x00D0 | n)?;
+            }
+            "scroll-right" => self.emit(0x00FB)?,
+            "scroll-left" => self.emit(0x00FC)?,
+            "audio" => self.emit(0xF002)?,
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(0xF001 | n << 8)?;
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(0xF033 | x << 8)?;
+            }
+            "save" | "load" => self.save_or_load(token == "save")?,
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(0xF075 | x << 8)?;
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(0xF085 | x << 8)?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(0xD000 | x << 8 | y << 4 | n)?;
+            }
+            "jump" | "jump0" | "native" => {
+                let opcode = match token {
+                    "jump" => 0x1000,
+                    "jump0" => 0xB000,
+                    _ => 0x0000,
+                };
+                let target = self.expect("an address")?;
+                self.reference(target, opcode, Fill::Address)?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.operator(&[":="])?;
+                let x = self.register()?;
+                let opcode = match token {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.emit(opcode | x << 8)?;
+            }
+            "i" => self.i_statement()?,
+            "if" => self.if_statement()?,
+            "else" => match self.blocks.pop() {
+                Some((Block::If { jump }, line)) => {
+                    let end = self.here;
+                    self.emit(0x1000)?;
+                    self.fill(jump, Fill::Address, i32::from(self.here))?;
+                    self.blocks.push((Block::Else { jump: end }, line));
+                }
+                _ => return Err(self.error("else without if ... begin")),
+            },
+            "end" => match self.blocks.pop() {
+                Some((Block::If { jump } | Block::Else { jump }, _)) => {
+                    self.fill(jump, Fill::Address, i32::from(self.here))?;
+                }
+                _ => return Err(self.error("end without if ... begin")),
+            },
+            "loop" => {
+                let start = self.here;
+                self.blocks.push((
+                    Block::Loop {
+                        start,
+                        exits: Vec::new(),
+                    },
+                    self.line,
+                ));
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.emit(condition.begin)?;
+                let exit = self.here;
+                self.emit(0x1000)?;
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find_map(|(block, _)| match block {
+                        Block::Loop { exits, .. } => Some(exits),
+                        _ => None,
+                    }) {
+                    Some(exits) => exits.push(exit),
+                    None => return Err(self.error("while outside a loop")),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some((Block::Loop { start, exits }, _)) => {
+                    self.emit(0x1000 | start)?;
+                    for exit in exits {
+                        self.fill(exit, Fill::Address, i32::from(self.here))?;
+                    }
+                }
+                _ => return Err(self.error("again without loop")),
+            },
+            _ if UNSUPPORTED.contains(&token) => {
+                return Err(self.error(format!(
+                    "{token} isn't supported, export a .ch8 from Octo instead"
+                )))
+            }
+            _ => {
+                if let Some(x) = self.register_name(token) {
+                    return self.register_statement(x);
+                }
+                match parse_number(token).or_else(|| self.consts.get(token).copied()) {
+                    Some(value) if (-128..=255).contains(&value) => self.write(value as u8)?,
+                    Some(_) => return Err(self.error(format!("{token} doesn't fit in a byte"))),
+                    None if is_name(token) => self.reference(token, 0x2000, Fill::Address)?,
+                    None => return Err(self.error(format!("unexpected {token}"))),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn i_statement(&mut self) -> Result<(), RomError> {
+        if self.operator(&[":=", "+="])? == "+=" {
+            let x = self.register()?;
+            return self.emit(0xF01E | x << 8);
+        }
+        match self.expect("an address")? {
+            "hex" => {
+                let x = self.register()?;
+                self.emit(0xF029 | x << 8)
+            }
+            "bighex" => {
+                let x = self.register()?;
+                self.emit(0xF030 | x << 8)
+            }
+            "long" => {
+                let target = self.expect("an address")?;
+                self.reference(target, 0xF000, Fill::Long)
+            }
+            target => self.reference(target, 0xA000, Fill::Address),
+        }
+    }
+
+    fn register_statement(&mut self, x: u16) -> Result<(), RomError> {
+        let operator = self.operator(&[":=", "+=", "-=", "=-", "|=", "&=", "^=", ">>=", "<<="])?;
+        let source = self.expect("a register or a value")?;
+        if let Some(y) = self.register_name(source) {
+            let logic = match operator {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                _ => 0xE,
+            };
+            return self.emit(0x8000 | x << 8 | y << 4 | logic);
+        }
+        match (operator, source) {
+            (":=", "random") => {
+                let mask = self.byte()?;
+                self.emit(0xC000 | x << 8 | u16::from(mask))
+            }
+            (":=", "key") => self.emit(0xF00A | x << 8),
+            (":=", "delay") => self.emit(0xF007 | x << 8),
+            (":=" | "+=" | "-=", _) => {
+                self.pos -= 1;
+                let mut value = self.byte()?;
+                if operator == "-=" {
+                    value = value.wrapping_neg();
+                }
+                let opcode = if operator == ":=" { 0x6000 } else { 0x7000 };
+                self.emit(opcode | x << 8 | u16::from(value))
+            }
+            _ => Err(self.error(format!("{operator} needs a register, got {source}"))),
+        }
+    }
+
+    // `save vx`, or XO-CHIP's `save vx - vy`.
+    fn save_or_load(&mut self, save: bool) -> Result<(), RomError> {
+        let x = self.register()?;
+        if self.peek() == Some("-") {
+            self.pos += 1;
+            let y = self.register()?;
+            return self.emit(if save { 0x5002 } else { 0x5003 } | x << 8 | y << 4);
+        }
+        self.emit(if save { 0xF055 } else { 0xF065 } | x << 8)
+    }
+
+    fn if_statement(&mut self) -> Result<(), RomError> {
+        let condition = self.condition()?;
+        match self.expect("then or begin")? {
+            "then" => self.emit(condition.then),
+            "begin" => {
+                self.emit(condition.begin)?;
+                let jump = self.here;
+                self.emit(0x1000)?;
+                self.blocks.push((Block::If { jump }, self.line));
+                Ok(())
+            }
+            token => Err(self.error(format!("expected then or begin, got {token}"))),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, RomError> {
+        let x = self.register()?;
+        let operator = self.operator(&["==", "!=", "key", "-key", "<", ">", "<=", ">="])?;
+        let (equal, not_equal) = match operator {
+            "key" => {
+                return Ok(Condition {
+                    then: 0xE0A1 | x << 8,
+                    begin: 0xE09E | x << 8,
+                })
+            }
+            "-key" => {
+                return Ok(Condition {
+                    then: 0xE09E | x << 8,
+                    begin: 0xE0A1 | x << 8,
+                })
+            }
+            "==" | "!=" => {
+                let other = self.expect("a register or a value")?;
+                match self.register_name(other) {
+                    Some(y) => (0x5000 | x << 8 | y << 4, 0x9000 | x << 8 | y << 4),
+                    None => {
+                        self.pos -= 1;
+                        let n = u16::from(self.byte()?);
+                        (0x3000 | x << 8 | n, 0x4000 | x << 8 | n)
+                    }
+                }
+            }
+            _ => {
+                return Err(self.error(format!(
+                    "{operator} comparisons aren't supported, export a .ch8 from Octo instead"
+                )))
+            }
+        };
+        // The skip instructions skip when the condition doesn't hold.
+        Ok(if operator == "==" {
+            Condition {
+                then: not_equal,
+                begin: equal,
+            }
+        } else {
+            Condition {
+                then: equal,
+                begin: not_equal,
+            }
+        })
+    }
+
+    // An instruction whose operand can be a label defined further on.
+    fn reference(&mut self, target: &'a str, opcode: u16, fill: Fill) -> Result<(), RomError> {
+        let at = self.here;
+        self.emit(opcode)?;
+        if matches!(fill, Fill::Long) {
+            self.emit(0)?;
+        }
+        match parse_number(target)
+            .or_else(|| self.consts.get(target).copied())
+            .or_else(|| self.labels.get(target).map(|&addr| i32::from(addr)))
+        {
+            Some(value) => self.fill(at, fill, value),
+            None if is_name(target) => {
+                self.patches.push(Patch {
+                    at,
+                    name: target,
+                    line: self.line,
+                    fill,
+                });
+                Ok(())
+            }
+            None => Err(self.error(format!("expected an address, got {target}"))),
+        }
+    }
+
+    fn fill(&mut self, at: u16, fill: Fill, value: i32) -> Result<(), RomError> {
+        let limit = if matches!(fill, Fill::Long) {
+            0xFFFF
+        } else {
+            0xFFF
+        };
+        if !(0..=limit).contains(&value) {
+            return Err(self.error(format!("{value:#X} is out of range for an address")));
+        }
+        let at = usize::from(at - START);
+        let value = value as u16;
+        match fill {
+            Fill::Address => {
+                self.image[at] |= (value >> 8) as u8;
+                self.image[at + 1] = value as u8;
+            }
+            Fill::Long => self.image[at + 2..at + 4].copy_from_slice(&value.to_be_bytes()),
+            Fill::High => self.image[at + 1] |= (value >> 8) as u8,
+            Fill::Low => self.image[at + 1] = value as u8,
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, opcode: u16) -> Result<(), RomError> {
+        let [hi, lo] = opcode.to_be_bytes();
+        self.write(hi)?;
+        self.write(lo)
+    }
+
+    fn write(&mut self, byte: u8) -> Result<(), RomError> {
+        let at = usize::from(self.here - START);
+        if at >= self.image.len() {
+            self.image.resize(at + 1, 0);
+        }
+        self.image[at] = byte;
+        self.here = self
+            .here
+            .checked_add(1)
+            .ok_or_else(|| self.error("the program runs past the end of memory"))?;
+        Ok(())
+    }
+
+    fn define(&mut self, name: &'a str, addr: u16) -> Result<(), RomError> {
+        self.check_unused(name)?;
+        self.labels.insert(name, addr);
+        Ok(())
+    }
+
+    fn check_unused(&self, name: &str) -> Result<(), RomError> {
+        if self.labels.contains_key(name) || self.consts.contains_key(name) {
+            return Err(self.error(format!("{name} is already defined")));
+        }
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<&'a str, RomError> {
+        let token = self.expect("a name")?;
+        if !is_name(token) || self.register_name(token).is_some() {
+            return Err(self.error(format!("{token} can't be a name")));
+        }
+        Ok(token)
+    }
+
+    fn register(&mut self) -> Result<u16, RomError> {
+        let token = self.expect("a register")?;
+        self.register_name(token)
+            .ok_or_else(|| self.error(format!("expected a register, got {token}")))
+    }
+
+    fn register_name(&self, token: &str) -> Option<u16> {
+        if let Some(&x) = self.aliases.get(token) {
+            return Some(x);
+        }
+        let digit = token.strip_prefix(['v', 'V'])?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u16::from_str_radix(digit, 16).ok()
+    }
+
+    fn operator(&mut self, expected: &[&'a str]) -> Result<&'a str, RomError> {
+        let token = self.expect(expected[0])?;
+        if !expected.contains(&token) {
+            return Err(self.error(format!("expected {}, got {token}", expected.join(" or "))));
+        }
+        Ok(token)
+    }
+
+    // A number, or a constant or label that's already defined.
+    fn known(&mut self, range: RangeInclusive<i32>, what: &str) -> Result<i32, RomError> {
+        let token = self.expect(what)?;
+        let value = parse_number(token)
+            .or_else(|| self.consts.get(token).copied())
+            .or_else(|| self.labels.get(token).map(|&addr| i32::from(addr)))
+            .ok_or_else(|| self.error(format!("expected {what}, got {token}")))?;
+        if !range.contains(&value) {
+            return Err(self.error(format!("{token} is out of range for {what}")));
+        }
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<u8, RomError> {
+        Ok(self.known(-128..=255, "a byte")? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u16, RomError> {
+        Ok(self.known(0..=15, "a nibble")? as u16)
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let &(token, line) = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        self.line = line;
+        Some(token)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|&(token, _)| token)
+    }
+
+    fn expect(&mut self, what: &str) -> Result<&'a str, RomError> {
+        self.next()
+            .ok_or_else(|| self.error(format!("expected {what} at the end")))
+    }
+
+    fn error(&self, message: impl Into<String>) -> RomError {
+        RomError::Octo {
+            line: self.line,
+            message: message.into(),
+        }
+    }
+}
+
+fn parse_number(token: &str) -> Option<i32> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16)
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i32::from_str_radix(bin, 2)
+    } else {
+        digits.parse()
+    }
+    .ok()?;
+    Some(if negative { -value } else { value })
+}
+
+fn is_name(token: &str) -> bool {
+    token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && token
+            .chars()
+            .all(|c| c.is_alphanumeric() || "_-".contains(c))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn error_line(source: &str) -> usize {
+        match assemble(source) {
+            Err(RomError::Octo { line, .. }) => line,
+            other => panic!("{source} gave {other:?}"),
+        }
+    }
+
+    #[test]
+    fn main_first_needs_no_jump() {
+        let rom = assemble(": main\n  clear\n  i := hex v0\n  sprite v0 v0 5\n  loop again\n");
+        assert_eq!(
+            rom.unwrap(),
+            [0x00, 0xE0, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06]
+        );
+        // Bare numbers are bytes.
+        let rom = assemble("0x12 0x00 # jump\n 0b1010 255 -1");
+        assert_eq!(rom.unwrap(), [0x12, 0x00, 0x0A, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn labels_can_come_later() {
+        let rom = assemble(
+            ": draw sprite v0 v1 4 ;\n\
+             :const SPEED 5\n\
+             :alias y v1\n\
+             : main v0 := SPEED y += 1 draw jump main",
+        );
+        #[rustfmt::skip]
+        assert_eq!(rom.unwrap(), [
+            0x12, 0x06, // jump main
+            0xD0, 0x14, 0x00, 0xEE, // draw
+            0x60, 0x05, 0x71, 0x01, 0x22, 0x02, 0x12, 0x06,
+        ]);
+
+        let rom = assemble(": main :unpack 0xA data i := data ;\n: data 0x12 -1 0b101");
+        #[rustfmt::skip]
+        assert_eq!(rom.unwrap(), [
+            0x60, 0xA2, 0x61, 0x08, 0xA2, 0x08, 0x00, 0xEE,
+            0x12, 0xFF, 0x05,
+        ]);
+    }
+
+    #[test]
+    fn blocks_become_skips_and_jumps() {
+        let rom = assemble(
+            ": main
+               loop
+                 v0 += 1
+                 while v0 != 10
+                 if v0 == 5 begin v1 := 1 else v1 := 2 end
+                 if v0 key then clear
+               again",
+        );
+        #[rustfmt::skip]
+        assert_eq!(rom.unwrap(), [
+            0x70, 0x01,
+            0x40, 0x0A, 0x12, 0x16, // while
+            0x30, 0x05, 0x12, 0x0E, 0x61, 0x01, 0x12, 0x10, 0x61, 0x02, // if begin else end
+            0xE0, 0xA1, 0x00, 0xE0, // if then
+            0x12, 0x00, // again
+        ]);
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        assert_eq!(error_line(": main\n  jump nowhere\n"), 2);
+        assert_eq!(error_line(": main\n  loop\n  clear\n"), 2);
+        assert_eq!(error_line(": main\n\n  if v0 < 3 then clear\n"), 3);
+        assert_eq!(error_line(":macro twice X { X X }"), 1);
+        assert_eq!(error_line(": main v0 := 256"), 1);
+        assert_eq!(error_line(": main : main"), 1);
+    }
+}
@@ -0,0 +1,177 @@
+use std::{collections::HashMap, fs};
+
+use anyhow::{anyhow, Context};
+use chipeite::{
+    database::{self, Database},
+    memory::bus::Mapping,
+    rom::Rom,
+    Config, Segment, Variant,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    frontend::{Hotkeys, Keymap, Palette},
+    structopt::Opt,
+};
+
+const SUPPORTED_PLATFORMS: &[&str] = &["originalChip8", "hybridVIP", "modernChip8", "chip8x"];
+
+pub struct Settings {
+    pub config: Config,
+    pub title: String,
+    pub palette: Palette,
+    pub keymap: Keymap,
+    pub hotkeys: Hotkeys,
+}
+
+// What --config reads, e.g. `{"hotkeys": {"pause": "p"}}`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    // Action to key name.
+    #[serde(default)]
+    hotkeys: HashMap<String, String>,
+}
+
+// ROM database entry first, then whatever was given on the command line.
+pub fn resolve(opts: &Opt, rom: &Rom) -> anyhow::Result<Settings> {
+    let db = Database::embedded();
+    let mut settings = Settings {
+        config: Config {
+            entry_point: opts.entry_point,
+            mapping: Mapping {
+                mirror: opts.mirror_ram,
+                write_protect: opts.write_protect,
+            },
+            ..Config::default()
+        },
+        title: "chipeite".to_owned(),
+        palette: Palette::default(),
+        keymap: Keymap::default(),
+        hotkeys: Hotkeys::default(),
+    };
+    let mut platform = None;
+
+    if let Some(info) = db.lookup(&rom.bytes).filter(|_| !opts.no_database) {
+        eprintln!("found {} in the ROM database", info.title);
+        settings.title = format!("chipeite - {}", info.title);
+        if let Some(tickrate) = info.tickrate {
+            settings.config.instructions_per_frame = tickrate;
+        }
+        if let Some(quirks) = info.quirks {
+            settings.config.quirks = quirks;
+        }
+        if let [background, foreground, ..] = info.palette[..] {
+            settings.palette = Palette {
+                background,
+                foreground,
+            };
+        }
+        settings.keymap = Keymap::new(info.keys);
+        platform = info.platform;
+    }
+
+    // Octo cartridges know how they want to be run.
+    if let Some(options) = &rom.options {
+        settings.config.quirks = options.quirks();
+        if let Some(tickrate) = options.tickrate {
+            settings.config.instructions_per_frame = tickrate;
+        }
+        if let Some([background, foreground]) = options.palette() {
+            settings.palette = Palette {
+                background,
+                foreground,
+            };
+        }
+    }
+
+    if let Some(id) = &opts.platform {
+        settings.config.quirks = db
+            .platform_quirks(id)
+            .ok_or_else(|| anyhow!("unknown platform {id}"))?;
+        if let Some(tickrate) = db.platform_tickrate(id) {
+            settings.config.instructions_per_frame = tickrate;
+        }
+        platform = Some(id.clone());
+    }
+    if let Some(tickrate) = opts.tickrate {
+        settings.config.instructions_per_frame = tickrate;
+    }
+    if !opts.quirks.is_empty() {
+        let overrides = opts
+            .quirks
+            .iter()
+            .map(|q| (q.name.clone(), Value::Bool(q.enabled)))
+            .collect();
+        settings.config.quirks =
+            database::with_overrides(settings.config.quirks, &overrides).map_err(|e| anyhow!(e))?;
+    }
+
+    if let Some(path) = &opts.config {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("couldn't read {}", path.display()))?;
+        let file: ConfigFile = serde_json::from_str(&text)
+            .with_context(|| format!("couldn't parse {}", path.display()))?;
+        settings
+            .hotkeys
+            .rebind(&file.hotkeys, &settings.keymap)
+            .with_context(|| format!("bad hotkeys in {}", path.display()))?;
+    }
+
+    for segment in &opts.segments {
+        let bytes = fs::read(&segment.path)
+            .with_context(|| format!("couldn't read {}", segment.path.display()))?;
+        settings.config.segments.push(Segment {
+            addr: segment.addr,
+            bytes,
+        });
+    }
+
+    // Nothing lists hi-res programs, they are recognised by their first jump.
+    let variant = platform
+        .as_deref()
+        .map(Variant::from_platform)
+        .filter(|&variant| variant != Variant::Chip8)
+        .unwrap_or_else(|| Variant::detect(&rom.bytes));
+    settings.config.variant = variant;
+    settings.config.load_address = opts.load_address.unwrap_or(variant.load_address());
+
+    if let Some(platform) = platform.filter(|p| !SUPPORTED_PLATFORMS.contains(&p.as_str())) {
+        eprintln!("warning: {platform} programs are not supported, running it as CHIP-8");
+    }
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod test {
+    use ::structopt::StructOpt;
+    use chipeite::rom::{cartridge::Options, Format};
+
+    use super::*;
+
+    #[test]
+    fn cartridge_options_reach_the_config() {
+        let opts = Opt::from_iter(["chipeite", "game.gif"]);
+        let rom = Rom {
+            format: Format::Cartridge,
+            bytes: vec![0x12, 0x00],
+            options: Some(Options {
+                tickrate: Some(20),
+                background_color: Some("#996600".to_owned()),
+                fill_color: Some("#FFCC00".to_owned()),
+                clip_quirks: true,
+                shift_quirks: true,
+                ..Options::default()
+            }),
+        };
+        let settings = resolve(&opts, &rom).unwrap();
+        assert_eq!(settings.config.instructions_per_frame, 20);
+        assert!(settings.config.quirks.shift && !settings.config.quirks.wrap);
+        assert_eq!(settings.palette.foreground, [0xFF, 0xCC, 0x00]);
+
+        let opts = Opt::from_iter(["chipeite", "--tickrate", "7", "game.gif"]);
+        let settings = resolve(&opts, &rom).unwrap();
+        assert_eq!(settings.config.instructions_per_frame, 7);
+    }
+}
@@ -0,0 +1,218 @@
+// Graphics in ram, decoded the ways Dxyn can draw them.
+
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // 8 pixels wide, a byte per row.
+    Chip8(u8),
+    // 16x16, two bytes per row.
+    Schip,
+    // Both XO-CHIP bitplanes, the second plane's bytes right after the
+    // first's. Zero rows is a 16x16 pair.
+    XoChip(u8),
+}
+
+impl Format {
+    pub fn width(self) -> usize {
+        match self {
+            Format::Chip8(_) | Format::XoChip(1..) => 8,
+            Format::Schip | Format::XoChip(0) => 16,
+        }
+    }
+
+    pub fn height(self) -> usize {
+        match self {
+            Format::Chip8(rows) | Format::XoChip(rows @ 1..) => rows as usize,
+            Format::Schip | Format::XoChip(0) => 16,
+        }
+    }
+
+    pub fn planes(self) -> usize {
+        match self {
+            Format::XoChip(_) => 2,
+            _ => 1,
+        }
+    }
+
+    // Bytes one sprite takes up in ram.
+    pub fn bytes(self) -> usize {
+        self.width() / 8 * self.height() * self.planes()
+    }
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::Chip8(8)
+    }
+}
+
+// `8x5`, `16x16`, `xo8x5` or `xo16x16`.
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (xo, size) = match s.strip_prefix("xo") {
+            Some(size) => (true, size),
+            None => (false, s),
+        };
+        let rows = match size.split_once('x') {
+            Some(("16", "16")) => Some(0),
+            Some(("8", rows)) => rows.parse().ok().filter(|rows| (1..16).contains(rows)),
+            _ => None,
+        };
+        match (xo, rows) {
+            (true, Some(rows)) => Ok(Format::XoChip(rows)),
+            (false, Some(0)) => Ok(Format::Schip),
+            (false, Some(rows)) => Ok(Format::Chip8(rows)),
+            (_, None) => Err(format!("unknown sprite format {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Format::XoChip(_) = self {
+            write!(f, "xo")?;
+        }
+        write!(f, "{}x{}", self.width(), self.height())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sprite {
+    pub addr: u16,
+    pub format: Format,
+    // A plane bit per pixel, row by row: 1 for the first plane, 2 for the
+    // second.
+    pixels: Vec<u8>,
+}
+
+impl Sprite {
+    // Bytes past the end of ram read as zero.
+    pub fn decode(ram: &[u8], addr: u16, format: Format) -> Self {
+        let (width, height) = (format.width(), format.height());
+        let plane_len = width / 8 * height;
+        let byte = |i: usize| ram.get(addr as usize + i).copied().unwrap_or(0);
+        let mut pixels = vec![0; width * height];
+        for plane in 0..format.planes() {
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                let (x, y) = (i % width, i / width);
+                let bits = byte(plane * plane_len + y * width / 8 + x / 8);
+                if bits & (0x80 >> (x % 8)) != 0 {
+                    *pixel |= 1 << plane;
+                }
+            }
+        }
+        Self {
+            addr,
+            format,
+            pixels,
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.format.width() + x]
+    }
+
+    // Whether any of its bytes are in `addr..addr + len`.
+    pub fn overlaps(&self, addr: u16, len: usize) -> bool {
+        let start = self.addr as usize;
+        let addr = addr as usize;
+        start < addr + len && addr < start + self.format.bytes()
+    }
+
+    // An indexed PNG, a colour from `colors` per plane combination.
+    pub fn png(&self, colors: &[[u8; 3]; 4]) -> Result<Vec<u8>, png::EncodingError> {
+        let mut out = Vec::new();
+        let (width, height) = (self.format.width(), self.format.height());
+        let mut encoder = png::Encoder::new(&mut out, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(colors[..1 << self.format.planes()].concat());
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+        Ok(out)
+    }
+}
+
+// The sprites laid out one after another from `start`, as many as fit
+// before `end`.
+pub fn sheet(ram: &[u8], start: u16, end: u16, format: Format) -> Vec<Sprite> {
+    (start as usize..end as usize)
+        .step_by(format.bytes())
+        .take_while(|addr| addr + format.bytes() <= end as usize)
+        .map(|addr| Sprite::decode(ram, addr as u16, format))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats_parse_and_print() {
+        for (text, format) in [
+            ("8x5", Format::Chip8(5)),
+            ("16x16", Format::Schip),
+            ("xo8x3", Format::XoChip(3)),
+            ("xo16x16", Format::XoChip(0)),
+        ] {
+            assert_eq!(text.parse(), Ok(format));
+            assert_eq!(format.to_string(), text);
+        }
+        assert_eq!(Format::Schip.bytes(), 32);
+        assert_eq!(Format::XoChip(0).bytes(), 64);
+        assert!("8x0".parse::<Format>().is_err());
+        assert!("xo16x8".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn sprites_decode_from_ram() {
+        let mut ram = vec![0; 0x40];
+        ram[0x10..0x12].copy_from_slice(&[0b1000_0001, 0b0100_0000]);
+        ram[0x12] = 0b1100_0000;
+
+        let sprite = Sprite::decode(&ram, 0x10, Format::Chip8(2));
+        assert_eq!(
+            (sprite.pixel(0, 0), sprite.pixel(7, 0), sprite.pixel(1, 1)),
+            (1, 1, 1)
+        );
+        assert_eq!(sprite.pixel(0, 1), 0);
+
+        let pair = Sprite::decode(&ram, 0x11, Format::XoChip(1));
+        assert_eq!((pair.pixel(0, 0), pair.pixel(1, 0)), (2, 3));
+
+        let wide = Sprite::decode(&ram, 0x10, Format::Schip);
+        assert_eq!(
+            (wide.pixel(9, 0), wide.pixel(1, 1), wide.pixel(0, 15)),
+            (1, 1, 0)
+        );
+        assert_eq!(wide.pixel(1, 0), 0);
+
+        let past_the_end = Sprite::decode(&ram, 0x3F, Format::Schip);
+        assert_eq!(past_the_end.pixels.iter().sum::<u8>(), 0);
+
+        let sheet = sheet(&ram, 0x10, 0x1E, Format::Chip8(5));
+        let addrs: Vec<u16> = sheet.iter().map(|sprite| sprite.addr).collect();
+        assert_eq!(addrs, [0x10, 0x15]);
+        assert!(sheet[1].overlaps(0x12, 4));
+        assert!(!sheet[1].overlaps(0x1A, 2));
+    }
+
+    #[test]
+    fn exports_png() {
+        let ram = [0xF0, 0x90, 0xF0];
+        let sprite = Sprite::decode(&ram, 0, Format::Chip8(3));
+        let colors = [[0, 0, 0], [255, 255, 255], [255, 0, 0], [0, 0, 255]];
+        let png = sprite.png(&colors).unwrap();
+
+        let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (8, 3));
+        assert_eq!(
+            reader.info().palette.as_deref(),
+            Some(&colors[..2].concat()[..])
+        );
+        assert_eq!(&pixels[8..16], &[1, 0, 0, 1, 0, 0, 0, 0]);
+    }
+}
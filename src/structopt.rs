@@ -1,11 +1,267 @@
-use std::path::PathBuf;
-use structopt::StructOpt;
+use std::{
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chipeite::trace;
+use structopt::{
+    clap::{AppSettings, ArgSettings},
+    StructOpt,
+};
 
 #[derive(StructOpt, Debug)]
-#[structopt(name = "chipeite", about = "A CHIP-8 emulator")]
+#[structopt(
+    name = "chipeite",
+    about = "A CHIP-8 emulator",
+    setting = AppSettings::SubcommandsNegateReqs
+)]
+pub struct Cli {
+    #[structopt(subcommand)]
+    pub command: Option<Subcommand>,
+    #[structopt(flatten)]
+    pub run: Opt,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Subcommand {
+    #[structopt(
+        name = "difftrace",
+        about = "Compares a ROM's execution against another emulator's trace"
+    )]
+    DiffTrace(DiffTraceOpt),
+}
+
+// How to run a ROM.
+#[derive(StructOpt, Debug)]
+// Without a frontend only difftrace reads them.
+#[cfg_attr(not(any(feature = "sdl", feature = "tui")), allow(dead_code))]
 pub struct Opt {
     #[structopt(default_value = "1", short = "s", long = "scale")]
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
     pub scale_factor: usize,
+    #[structopt(
+        default_value = "sdl",
+        long = "frontend",
+        possible_values = &["sdl", "tui"]
+    )]
+    pub frontend: FrontendKind,
+    // Instructions per frame, wins over the ROM database and --platform.
+    #[structopt(long = "tickrate")]
+    pub tickrate: Option<usize>,
+    // Platform id from the ROM database, e.g. `modernChip8` or `chip48`.
+    #[structopt(long = "platform")]
+    pub platform: Option<String>,
+    // e.g. `--quirks shift=true,vblank=false`
+    #[structopt(long = "quirks", use_delimiter = true, number_of_values = 1)]
+    pub quirks: Vec<QuirkOverride>,
+    // Don't look the ROM up in the ROM database.
+    #[structopt(long = "no-database")]
+    pub no_database: bool,
+    // Where the ROM is loaded, e.g. `0x600` for ETI-660 programs. Defaults to
+    // 0x200, or 0x300 for CHIP-8X.
+    #[structopt(long = "load-address", parse(try_from_str = parse_address))]
+    pub load_address: Option<u16>,
+    // Defaults to the load address.
+    #[structopt(long = "entry-point", parse(try_from_str = parse_address))]
+    pub entry_point: Option<u16>,
+    // e.g. `--load data.bin@0x300`, can be given several times.
+    #[structopt(long = "load", number_of_values = 1)]
+    pub segments: Vec<SegmentArg>,
+    // Addresses past 4K wrap around instead of stopping the ROM.
+    #[structopt(long = "mirror-ram")]
+    pub mirror_ram: bool,
+    // Writes below 0x200 stop the ROM with an error.
+    #[structopt(long = "write-protect")]
+    pub write_protect: bool,
+    // Runs the ROM on an emulated COSMAC VIP with this CHIP-8 interpreter
+    // image at 0000.
+    #[structopt(long = "vip", parse(from_os_str))]
+    pub vip: Option<PathBuf>,
+    // The VIP's monitor ROM, a built-in stand-in is used without it.
+    #[structopt(long = "vip-monitor", parse(from_os_str), requires = "vip")]
+    pub vip_monitor: Option<PathBuf>,
+    // Reloads the ROM when the file changes.
+    #[structopt(long = "watch")]
+    pub watch: bool,
+    // Reloads when this changes too, e.g. the program's assembler source.
+    #[structopt(long = "watch-source", parse(from_os_str), requires = "watch")]
+    pub watch_source: Option<PathBuf>,
+    // The save state file. The save state hotkey writes it, reloads
+    // restore it.
+    #[structopt(long = "state", parse(from_os_str))]
+    pub state: Option<PathBuf>,
+    // JSON settings that aren't per ROM, e.g. hotkeys. See the README.
+    #[structopt(long = "config", parse(from_os_str))]
+    pub config: Option<PathBuf>,
+    // Writes every instruction executed to this file.
+    #[structopt(long = "trace", parse(from_os_str))]
+    pub trace: Option<PathBuf>,
+    #[structopt(
+        long = "trace-format",
+        default_value = "text",
+        possible_values = &["text", "csv", "json"]
+    )]
+    pub trace_format: trace::Format,
+    // Only trace instructions in this range, e.g. `0x200-0x2ff`.
+    #[structopt(long = "trace-addresses", parse(try_from_str = parse_address_range))]
+    pub trace_addresses: Option<RangeInclusive<u16>>,
+    // Only trace these frames, e.g. `600-660`.
+    #[structopt(long = "trace-frames", parse(try_from_str = parse_frame_range))]
+    pub trace_frames: Option<RangeInclusive<u64>>,
+    // Writes the hottest addresses and time per subroutine here on exit.
+    #[structopt(long = "profile", parse(from_os_str))]
+    pub profile: Option<PathBuf>,
+    // Call stacks in the folded format flamegraph tools read, on exit.
+    #[structopt(long = "folded-stacks", parse(from_os_str))]
+    pub folded_stacks: Option<PathBuf>,
+    // Writes the ROM annotated with what was run, read and written on exit.
+    #[structopt(long = "coverage", parse(from_os_str))]
+    pub coverage: Option<PathBuf>,
+    // Writes an lcov tracefile on exit.
+    #[structopt(long = "lcov", parse(from_os_str))]
+    pub lcov: Option<PathBuf>,
+    // `address file:line` per line, for --lcov by source line and --dap
+    // source breakpoints.
+    #[structopt(long = "symbols", parse(from_os_str))]
+    pub symbols: Option<PathBuf>,
+    // Waits for gdb on this local TCP port before running the ROM.
+    #[structopt(long = "gdb")]
+    pub gdb: Option<u16>,
+    // Debug Adapter Protocol for editors, `stdio` or a local TCP port.
+    #[structopt(long = "dap")]
+    pub dap: Option<DapTransport>,
+    // Only missing with a subcommand, see `rom_path()`.
+    #[structopt(parse(from_os_str), set = ArgSettings::Required)]
+    rom_path: Option<PathBuf>,
+}
+
+impl Opt {
+    pub fn rom_path(&self) -> &Path {
+        self.rom_path
+            .as_deref()
+            .expect("the ROM is required without a subcommand")
+    }
+}
+
+// `chipeite difftrace`, takes the same options for running the ROM.
+#[derive(StructOpt, Debug)]
+pub struct DiffTraceOpt {
+    #[structopt(flatten)]
+    pub run: Opt,
+    // CSV or JSON lines, see the README for the columns.
     #[structopt(parse(from_os_str))]
-    pub rom_path: PathBuf,
+    pub reference: PathBuf,
+    // Key presses by frame, one `frame key down|up` per line.
+    #[structopt(long = "input", parse(from_os_str))]
+    pub input: Option<PathBuf>,
+    // How many instructions to show before and after the divergence.
+    #[structopt(long = "context", default_value = "8")]
+    pub context: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontendKind {
+    Sdl,
+    Tui,
+}
+
+impl FromStr for FrontendKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sdl" => Ok(FrontendKind::Sdl),
+            "tui" => Ok(FrontendKind::Tui),
+            _ => Err(format!("unknown frontend {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DapTransport {
+    Stdio,
+    Tcp(u16),
+}
+
+impl FromStr for DapTransport {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stdio" => Ok(DapTransport::Stdio),
+            port => port
+                .parse()
+                .map(DapTransport::Tcp)
+                .map_err(|_| format!("expected stdio or a port, got {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QuirkOverride {
+    pub name: String,
+    pub enabled: bool,
+}
+
+impl FromStr for QuirkOverride {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, enabled) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected quirk=true|false, got {s}"))?;
+        Ok(Self {
+            name: name.to_owned(),
+            enabled: enabled
+                .parse()
+                .map_err(|_| format!("{enabled} is not a bool"))?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SegmentArg {
+    pub path: PathBuf,
+    pub addr: u16,
+}
+
+impl FromStr for SegmentArg {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, addr) = s
+            .rsplit_once('@')
+            .ok_or_else(|| format!("expected file@address, got {s}"))?;
+        Ok(Self {
+            path: path.into(),
+            addr: parse_address(addr)?,
+        })
+    }
+}
+
+// Hex with a 0x prefix, decimal otherwise.
+fn parse_address(s: &str) -> Result<u16, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("{s} is not an address"))
+}
+
+// `start-end`, both ends included.
+fn parse_range<T>(
+    s: &str,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Result<RangeInclusive<T>, String> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| format!("expected start-end, got {s}"))?;
+    Ok(parse(start)?..=parse(end)?)
+}
+
+fn parse_address_range(s: &str) -> Result<RangeInclusive<u16>, String> {
+    parse_range(s, parse_address)
+}
+
+fn parse_frame_range(s: &str) -> Result<RangeInclusive<u64>, String> {
+    parse_range(s, |n| {
+        n.parse().map_err(|_| format!("{n} is not a frame number"))
+    })
 }
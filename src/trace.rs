@@ -0,0 +1,279 @@
+pub mod error;
+pub mod reference;
+
+use std::{
+    fmt,
+    io::{self, Write},
+    ops::RangeInclusive,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cpu::{disassembler::disassemble, Cpu},
+    memory::Memory,
+};
+
+// The machine right before one instruction runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    pub cycle: u64,
+    pub frame: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub mnemonic: String,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub sp: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl Record {
+    // None when the pc is too close to the end of ram to fetch from.
+    pub(crate) fn capture(cycle: u64, frame: u64, cpu: &Cpu, memory: &Memory) -> Option<Self> {
+        let pc = cpu.program_counter();
+        let bytes = memory.ram.get(pc as usize..pc as usize + 2)?;
+        let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+        Some(Self {
+            cycle,
+            frame,
+            pc,
+            opcode,
+            mnemonic: disassemble(opcode, cpu.variant()),
+            v: *memory.registers.values(),
+            i: memory.registers.image,
+            sp: memory.stack.entries().len(),
+            delay_timer: cpu.delay_timer(),
+            sound_timer: cpu.sound_timer(),
+        })
+    }
+
+    fn csv(&self) -> String {
+        let v: Vec<_> = self.v.iter().map(u8::to_string).collect();
+        format!(
+            "{},{},{},{},\"{}\",{},{},{},{},{}",
+            self.cycle,
+            self.frame,
+            self.pc,
+            self.opcode,
+            self.mnemonic,
+            v.join(","),
+            self.i,
+            self.sp,
+            self.delay_timer,
+            self.sound_timer
+        )
+    }
+}
+
+// The text format, one line per record.
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let v: Vec<_> = self.v.iter().map(|v| format!("{v:02X}")).collect();
+        write!(
+            f,
+            "{:>10} {:>7}  {:03X}: {:04X}  {:<16} V={} I={:03X} SP={:X} DT={:02X} ST={:02X}",
+            self.cycle,
+            self.frame,
+            self.pc,
+            self.opcode,
+            self.mnemonic,
+            v.join(" "),
+            self.i,
+            self.sp,
+            self.delay_timer,
+            self.sound_timer
+        )
+    }
+}
+
+const CSV_HEADER: &str = "cycle,frame,pc,opcode,mnemonic,v0,v1,v2,v3,v4,v5,v6,v7,v8,v9,va,vb,vc,vd,ve,vf,i,sp,delay_timer,sound_timer";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Text,
+    Csv,
+    // One JSON object per line.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown trace format {s}")),
+        }
+    }
+}
+
+// Which instructions make it into the trace, everything by default.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Filter {
+    pub addresses: Option<RangeInclusive<u16>>,
+    pub frames: Option<RangeInclusive<u64>>,
+}
+
+impl Filter {
+    pub fn matches(&self, pc: u16, frame: u64) -> bool {
+        self.addresses.as_ref().is_none_or(|r| r.contains(&pc))
+            && self.frames.as_ref().is_none_or(|r| r.contains(&frame))
+    }
+}
+
+pub struct Tracer {
+    out: Box<dyn Write>,
+    format: Format,
+    filter: Filter,
+    started: bool,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>, format: Format, filter: Filter) -> Self {
+        Self {
+            out,
+            format,
+            filter,
+            started: false,
+            error: None,
+        }
+    }
+
+    pub fn wants(&self, pc: u16, frame: u64) -> bool {
+        self.error.is_none() && self.filter.matches(pc, frame)
+    }
+
+    // Writing stops at the first error, which `take_error` hands out.
+    pub fn record(&mut self, record: &Record) {
+        if let Err(e) = self.write(record) {
+            self.error = Some(e);
+        }
+    }
+
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        if !self.started && self.format == Format::Csv {
+            writeln!(self.out, "{CSV_HEADER}")?;
+        }
+        self.started = true;
+        match self.format {
+            Format::Text => writeln!(self.out, "{record}"),
+            Format::Csv => writeln!(self.out, "{}", record.csv()),
+            Format::Json => {
+                serde_json::to_writer(&mut self.out, record)?;
+                writeln!(self.out)
+            }
+        }
+    }
+
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    // Once tracing is done: the first error, or whatever flushing the
+    // output runs into.
+    pub fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.out.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Chip8, Config};
+
+    struct Shared(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Unflushable;
+
+    impl Write for Unflushable {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::other("disk full"))
+        }
+    }
+
+    fn trace(format: Format, filter: Filter) -> String {
+        let out = std::rc::Rc::default();
+        // LD V1, #05; ADD V1, #01; JP #202
+        let mut chip8 =
+            Chip8::new(&[0x61, 0x05, 0x71, 0x01, 0x12, 0x02], Config::default()).unwrap();
+        chip8.set_tracer(Tracer::new(
+            Box::new(Shared(std::rc::Rc::clone(&out))),
+            format,
+            filter,
+        ));
+        for _ in 0..2 {
+            chip8.run_frame().unwrap();
+        }
+        let out = out.borrow().clone();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn records_every_instruction() {
+        let text = trace(Format::Text, Filter::default());
+        assert_eq!(text.lines().count(), 20);
+        assert!(text
+            .lines()
+            .next()
+            .unwrap()
+            .contains("200: 6105  LD V1, #05"));
+
+        let csv = trace(Format::Csv, Filter::default());
+        let mut lines = csv.lines();
+        assert!(lines.next().unwrap().starts_with("cycle,frame,pc"));
+        assert!(lines
+            .nth(1)
+            .unwrap()
+            .starts_with("1,0,514,28929,\"ADD V1, #01\",0,5,"));
+    }
+
+    #[test]
+    fn filters_by_address_and_frame() {
+        let filter = Filter {
+            addresses: Some(0x202..=0x202),
+            frames: Some(1..=1),
+        };
+        let json = trace(Format::Json, filter);
+        let records: Vec<Record> = json
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 5);
+        assert!(records.iter().all(|r| r.pc == 0x202 && r.frame == 1));
+        assert_eq!(records[0].mnemonic, "ADD V1, #01");
+    }
+
+    #[test]
+    fn finishing_flushes() {
+        let mut chip8 = Chip8::new(&[0x12, 0x00], Config::default()).unwrap();
+        chip8.set_tracer(Tracer::new(
+            Box::new(Unflushable),
+            Format::Text,
+            Filter::default(),
+        ));
+        chip8.step().unwrap();
+        let error = chip8.tracer_mut().unwrap().finish().unwrap_err();
+        assert_eq!(error.to_string(), "disk full");
+    }
+}
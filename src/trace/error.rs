@@ -0,0 +1,26 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    MissingField { line: usize, field: String },
+    BadValue { line: usize, field: String },
+    Json { line: usize, message: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "the trace is empty"),
+            ParseError::MissingField { line, field } => {
+                write!(f, "line {line} has no {field}")
+            }
+            ParseError::BadValue { line, field } => {
+                write!(f, "line {line} has a bad value for {field}")
+            }
+            ParseError::Json { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -0,0 +1,281 @@
+// Traces from other emulators, in the CSV or JSON lines layout `--trace`
+// writes. Only pc, v0-vf (or a `v` array in JSON) and i are required, opcode,
+// sp, delay_timer and sound_timer are compared when present. Numbers are
+// decimal or hex with a 0x prefix.
+
+use std::{collections::HashMap, fmt};
+
+use serde_json::Value;
+
+use super::{error::ParseError, Record};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub pc: u16,
+    pub opcode: Option<u16>,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub sp: Option<usize>,
+    pub delay_timer: Option<u8>,
+    pub sound_timer: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub field: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl Reference {
+    // Every field that differs from `record`.
+    pub fn diff(&self, record: &Record) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        let mut check = |field: &str, expected: Option<String>, actual: String| {
+            if let Some(expected) = expected.filter(|e| *e != actual) {
+                mismatches.push(Mismatch {
+                    field: field.to_owned(),
+                    expected,
+                    actual,
+                });
+            }
+        };
+        check(
+            "pc",
+            Some(format!("{:03X}", self.pc)),
+            format!("{:03X}", record.pc),
+        );
+        check(
+            "opcode",
+            self.opcode.map(|op| format!("{op:04X}")),
+            format!("{:04X}", record.opcode),
+        );
+        for (n, (expected, actual)) in self.v.iter().zip(record.v).enumerate() {
+            check(
+                &format!("V{n:X}"),
+                Some(format!("{expected:02X}")),
+                format!("{actual:02X}"),
+            );
+        }
+        check(
+            "I",
+            Some(format!("{:03X}", self.i)),
+            format!("{:03X}", record.i),
+        );
+        check(
+            "SP",
+            self.sp.map(|sp| sp.to_string()),
+            record.sp.to_string(),
+        );
+        check(
+            "DT",
+            self.delay_timer.map(|t| format!("{t:02X}")),
+            format!("{:02X}", record.delay_timer),
+        );
+        check(
+            "ST",
+            self.sound_timer.map(|t| format!("{t:02X}")),
+            format!("{:02X}", record.sound_timer),
+        );
+        mismatches
+    }
+}
+
+// Lined up with the text trace, fields the reference leaves out show as `-`.
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let v: Vec<_> = self.v.iter().map(|v| format!("{v:02X}")).collect();
+        let or_dash = |field: Option<String>| field.unwrap_or_else(|| "-".to_owned());
+        write!(
+            f,
+            "{:>18}  {:03X}: {:<4}  {:<16} V={} I={:03X} SP={} DT={} ST={}",
+            "",
+            self.pc,
+            or_dash(self.opcode.map(|op| format!("{op:04X}"))),
+            "",
+            v.join(" "),
+            self.i,
+            or_dash(self.sp.map(|sp| format!("{sp:X}"))),
+            or_dash(self.delay_timer.map(|t| format!("{t:02X}"))),
+            or_dash(self.sound_timer.map(|t| format!("{t:02X}"))),
+        )
+    }
+}
+
+pub fn parse(text: &str) -> Result<Vec<Reference>, ParseError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(n, line)| (n + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty());
+    let (first, header) = lines.next().ok_or(ParseError::Empty)?;
+    if header.starts_with('{') {
+        return std::iter::once((first, header))
+            .chain(lines)
+            .map(|(n, line)| from_json(n, line))
+            .collect();
+    }
+    let columns: Vec<String> = split_csv(header)
+        .into_iter()
+        .map(|c| c.to_lowercase())
+        .collect();
+    lines
+        .map(|(n, line)| {
+            let fields: HashMap<&str, String> = columns
+                .iter()
+                .map(String::as_str)
+                .zip(split_csv(line))
+                .collect();
+            from_fields(n, |field| fields.get(field).cloned())
+        })
+        .collect()
+}
+
+fn from_json(line: usize, text: &str) -> Result<Reference, ParseError> {
+    let value: Value = serde_json::from_str(text).map_err(|e| ParseError::Json {
+        line,
+        message: e.to_string(),
+    })?;
+    let v = value.get("v").and_then(Value::as_array);
+    from_fields(line, |field| {
+        let value = match field.strip_prefix('v').filter(|_| v.is_some()) {
+            Some(reg) => v?.get(usize::from_str_radix(reg, 16).ok()?)?,
+            None => value.get(field)?,
+        };
+        match value {
+            Value::String(s) => Some(s.clone()),
+            value => Some(value.to_string()),
+        }
+    })
+}
+
+fn from_fields(line: usize, get: impl Fn(&str) -> Option<String>) -> Result<Reference, ParseError> {
+    let optional = |field: &str| -> Result<Option<u64>, ParseError> {
+        get(field)
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                parse_number(&value).ok_or_else(|| ParseError::BadValue {
+                    line,
+                    field: field.to_owned(),
+                })
+            })
+            .transpose()
+    };
+    let required = |field: &str| {
+        optional(field)?.ok_or_else(|| ParseError::MissingField {
+            line,
+            field: field.to_owned(),
+        })
+    };
+    let mut v = [0; 16];
+    for (n, reg) in v.iter_mut().enumerate() {
+        let field = format!("v{n:x}");
+        *reg = fit(line, &field, required(&field)?)?;
+    }
+    Ok(Reference {
+        pc: fit(line, "pc", required("pc")?)?,
+        opcode: optional("opcode")?
+            .map(|op| fit(line, "opcode", op))
+            .transpose()?,
+        v,
+        i: fit(line, "i", required("i")?)?,
+        sp: optional("sp")?.map(|sp| fit(line, "sp", sp)).transpose()?,
+        delay_timer: optional("delay_timer")?
+            .map(|t| fit(line, "delay_timer", t))
+            .transpose()?,
+        sound_timer: optional("sound_timer")?
+            .map(|t| fit(line, "sound_timer", t))
+            .transpose()?,
+    })
+}
+
+// A value too big for the register it's for is a bad value, not one to
+// truncate and compare.
+fn fit<T: TryFrom<u64>>(line: usize, field: &str, value: u64) -> Result<T, ParseError> {
+    T::try_from(value).map_err(|_| ParseError::BadValue {
+        line,
+        field: field.to_owned(),
+    })
+}
+
+fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+// Commas inside double quotes don't split.
+fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields.iter().map(|f| f.trim().to_owned()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_csv_and_json_lines() {
+        let csv = "PC,opcode,mnemonic,V0,V1,V2,V3,V4,V5,V6,V7,V8,V9,VA,VB,VC,VD,VE,VF,I\n\
+                   0x200,0x6105,\"LD V1, #05\",0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0\n\
+                   0x202,28929,\"ADD V1, #01\",0,5,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0\n";
+        let csv = parse(csv).unwrap();
+        assert_eq!(csv.len(), 2);
+        assert_eq!(
+            (csv[1].pc, csv[1].opcode, csv[1].v[1]),
+            (0x202, Some(0x7101), 5)
+        );
+        assert_eq!(csv[1].sp, None);
+
+        let json = r#"{"pc":514,"v":[0,5,0,0,0,0,0,0,0,0,0,0,0,0,0,0],"i":"0x300","sp":1}"#;
+        let json = parse(json).unwrap();
+        assert_eq!((json[0].pc, json[0].i, json[0].sp), (0x202, 0x300, Some(1)));
+
+        assert_eq!(
+            parse("pc,i\n0x200,0\n"),
+            Err(ParseError::MissingField {
+                line: 2,
+                field: "v0".to_owned()
+            })
+        );
+        assert_eq!(
+            parse(r#"{"pc":514,"v":[0,0,0,511,0,0,0,0,0,0,0,0,0,0,0,0],"i":0}"#),
+            Err(ParseError::BadValue {
+                line: 1,
+                field: "v3".to_owned()
+            })
+        );
+        assert_eq!(
+            parse(r#"{"pc":"0x10200","v":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],"i":0}"#),
+            Err(ParseError::BadValue {
+                line: 1,
+                field: "pc".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn lines_up_with_the_text_trace() {
+        let reference = Reference {
+            pc: 0x202,
+            opcode: Some(0x7101),
+            v: [0; 16],
+            i: 0x300,
+            sp: None,
+            delay_timer: Some(0x3C),
+            sound_timer: None,
+        };
+        let line = reference.to_string();
+        assert_eq!(line.find("202: 7101"), Some(20));
+        assert!(line.ends_with("I=300 SP=- DT=3C ST=-"));
+    }
+}
@@ -0,0 +1,279 @@
+use std::io::{self, Stdout, Write};
+use std::time::Duration;
+
+use chipeite::{ColorMap, FrameBuffer, Key};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{style, Color, Colors, Print, ResetColor, SetColors, Stylize};
+use crossterm::{cursor, execute, queue, terminal};
+
+use crate::frontend::{Command, Frontend, Hotkeys, Input, Keymap, Palette};
+use crate::machine::Machine;
+use crate::settings::Settings;
+
+// Most terminals only report presses, so a key counts as held for this many
+// frames after its last press (or autorepeat).
+const HELD_FRAMES: u8 = 6;
+
+pub struct Tui {
+    stdout: Stdout,
+    title: String,
+    palette: Palette,
+    keymap: Keymap,
+    hotkeys: Hotkeys,
+    reports_release: bool,
+    held: [u8; 16],
+    // Hotkeys are held the same way, for fast-forward.
+    held_command: Option<(Command, u8)>,
+    last_frame: Option<Vec<String>>,
+    beeping: bool,
+    speed: String,
+    frame: u64,
+}
+
+impl Tui {
+    pub fn new(settings: &Settings) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(
+            stdout,
+            terminal::EnterAlternateScreen,
+            terminal::Clear(terminal::ClearType::All),
+            cursor::Hide
+        )?;
+        let reports_release = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if reports_release {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(Self {
+            stdout,
+            title: settings.title.clone(),
+            palette: settings.palette,
+            keymap: settings.keymap.clone(),
+            hotkeys: settings.hotkeys.clone(),
+            reports_release,
+            held: [0; 16],
+            held_command: None,
+            last_frame: None,
+            beeping: false,
+            speed: String::new(),
+            frame: 0,
+        })
+    }
+
+    fn release_expired<F: FnMut(Input, bool)>(&mut self, f: &mut F) {
+        for (i, frames) in self.held.iter_mut().enumerate() {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    f(Input::Key(Key::from(i as u8)), false);
+                }
+            }
+        }
+        if let Some((command, frames)) = &mut self.held_command {
+            *frames -= 1;
+            if *frames == 0 {
+                f(Input::Command(*command), false);
+                self.held_command = None;
+            }
+        }
+    }
+
+    fn status(&self) -> String {
+        // blinks at about 4Hz while the sound timer runs
+        let beep = if self.beeping && self.frame % 16 < 8 {
+            "[BEEP]"
+        } else {
+            "      "
+        };
+        format!("{beep}  {} [{}]  esc to quit", self.title, self.speed)
+    }
+}
+
+impl Frontend for Tui {
+    // Terminals can't tell the numeric keypad apart, so there's no second
+    // CHIP-8X keypad here.
+    fn poll_keys<F: FnMut(Input, bool)>(&mut self, mut on_input: F) -> anyhow::Result<bool> {
+        if !self.reports_release {
+            self.release_expired(&mut on_input);
+        }
+        while event::poll(Duration::ZERO)? {
+            let Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind,
+                ..
+            }) = event::read()?
+            else {
+                continue;
+            };
+            if code == KeyCode::Esc
+                || (code == KeyCode::Char('c') && modifiers.contains(KeyModifiers::CONTROL))
+            {
+                return Ok(false);
+            }
+            if let Some(command) = hotkey_name(code).and_then(|name| self.hotkeys.get(&name)) {
+                match kind {
+                    KeyEventKind::Release => on_input(Input::Command(command), false),
+                    KeyEventKind::Repeat if self.reports_release => {}
+                    // autorepeat, in terminals that can't tell
+                    _ if self.held_command.is_some_and(|(held, _)| held == command) => {
+                        self.held_command = Some((command, HELD_FRAMES));
+                    }
+                    _ => {
+                        on_input(Input::Command(command), true);
+                        if !self.reports_release {
+                            self.held_command = Some((command, HELD_FRAMES));
+                        }
+                    }
+                }
+                continue;
+            }
+            let key = key_from_code(code)
+                .or_else(|| action_from_code(code).and_then(|a| self.keymap.get(a)));
+            let Some(key) = key else {
+                continue;
+            };
+            if kind == KeyEventKind::Release {
+                on_input(Input::Key(key), false);
+            } else {
+                on_input(Input::Key(key), true);
+                if !self.reports_release {
+                    self.held[key as usize] = HELD_FRAMES;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    // Two framebuffer lines per terminal row, using half blocks.
+    fn draw(&mut self, machine: &dyn Machine) -> anyhow::Result<()> {
+        let (fb, colors) = (machine.framebuffer(), machine.colors());
+        self.frame += 1;
+        let lines: Vec<String> = (0..fb.height())
+            .step_by(2)
+            .map(|y| match colors {
+                Some(colors) => colored_line(fb, colors, y),
+                None => (0..fb.width())
+                    .map(|x| {
+                        let top = fb.get(x, y);
+                        let bottom = y + 1 < fb.height() && fb.get(x, y + 1);
+                        match (top, bottom) {
+                            (true, true) => '█',
+                            (true, false) => '▀',
+                            (false, true) => '▄',
+                            (false, false) => ' ',
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+        if self.last_frame.as_ref() != Some(&lines) || self.beeping {
+            let colors = Colors::new(rgb(self.palette.foreground), rgb(self.palette.background));
+            queue!(self.stdout, cursor::MoveTo(0, 0), SetColors(colors))?;
+            for line in &lines {
+                queue!(self.stdout, Print(line), cursor::MoveToNextLine(1))?;
+            }
+            queue!(self.stdout, ResetColor)?;
+            let status = self.status();
+            queue!(self.stdout, Print(status))?;
+            self.stdout.flush()?;
+            self.last_frame = Some(lines);
+        }
+        Ok(())
+    }
+
+    fn beep(&mut self, on: bool) {
+        if on && !self.beeping {
+            let _ = execute!(self.stdout, Print('\x07'));
+        }
+        if !on && self.beeping {
+            // make sure the indicator gets cleared on the next draw
+            self.last_frame = None;
+        }
+        self.beeping = on;
+    }
+
+    fn set_status(&mut self, status: &str) {
+        if status != self.speed {
+            self.speed = status.to_owned();
+            self.last_frame = None;
+        }
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        if self.reports_release {
+            let _ = execute!(self.stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn rgb([r, g, b]: [u8; 3]) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+// Every cell is an upper half block, coloured for the top and bottom pixel.
+fn colored_line(fb: &FrameBuffer, colors: &ColorMap, y: usize) -> String {
+    let pixel = |x, y| {
+        if y < fb.height() && fb.get(x, y) {
+            colors.foreground(x, y)
+        } else {
+            colors.background()
+        }
+    };
+    (0..fb.width())
+        .map(|x| {
+            style('▀')
+                .with(rgb(pixel(x, y)))
+                .on(rgb(pixel(x, y + 1)))
+                .to_string()
+        })
+        .collect()
+}
+
+// The key's name as SDL has it in lowercase, which hotkeys are bound by.
+fn hotkey_name(code: KeyCode) -> Option<String> {
+    Some(match code {
+        KeyCode::F(n) => format!("f{n}"),
+        KeyCode::Tab => "tab".to_owned(),
+        KeyCode::Enter => "return".to_owned(),
+        KeyCode::Backspace => "backspace".to_owned(),
+        KeyCode::Char(' ') => "space".to_owned(),
+        KeyCode::Char(c) => c.to_lowercase().to_string(),
+        _ => return None,
+    })
+}
+
+fn action_from_code(code: KeyCode) -> Option<&'static str> {
+    match code {
+        KeyCode::Up => Some("up"),
+        KeyCode::Down => Some("down"),
+        KeyCode::Left => Some("left"),
+        KeyCode::Right => Some("right"),
+        KeyCode::Char(' ') => Some("a"),
+        KeyCode::Enter => Some("b"),
+        KeyCode::Char('i') => Some("player2Up"),
+        KeyCode::Char('k') => Some("player2Down"),
+        KeyCode::Char('j') => Some("player2Left"),
+        KeyCode::Char('l') => Some("player2Right"),
+        KeyCode::Char('u') => Some("player2A"),
+        KeyCode::Char('o') => Some("player2B"),
+        _ => None,
+    }
+}
+
+fn key_from_code(code: KeyCode) -> Option<Key> {
+    match code {
+        KeyCode::Char(c) => c.to_digit(16).map(|digit| Key::from(digit as u8)),
+        _ => None,
+    }
+}
@@ -1,13 +1,66 @@
-use crate::memory::framebuffer::FrameBuffer;
-use sdl2::audio::{AudioCallback, AudioSpecDesired, AudioDevice};
+use anyhow::anyhow;
+use chipeite::{
+    memory::PROGRAM_START,
+    sprite::{Format, Sprite},
+    ColorMap, FrameBuffer, Key,
+};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 use sdl2::Sdl;
 
+use crate::frontend::{Command, Frontend, Hotkeys, Input, Keymap, Palette};
+use crate::machine::Machine;
+use crate::overlay::{self, font, Click, Line, Style};
+use crate::settings::Settings;
+
+// Overlay text is drawn with font pixels this big, whatever --scale says.
+const TEXT_SCALE: u32 = 2;
+const CELL_WIDTH: u32 = (font::WIDTH as u32 + 1) * TEXT_SCALE;
+const CELL_HEIGHT: u32 = (font::HEIGHT as u32 + 2) * TEXT_SCALE;
+const MARGIN: u32 = 8;
+const PANEL_BACKGROUND: [u8; 3] = [0x20, 0x20, 0x20];
+const PANEL_TEXT: [u8; 3] = [0xC0, 0xC0, 0xC0];
+const PANEL_HIGHLIGHT: [u8; 3] = [0xFF, 0xC0, 0x00];
+const PANEL_MARKED: [u8; 3] = [0x40, 0xA0, 0xFF];
+const PANEL_WRITTEN: [u8; 3] = [0xFF, 0x60, 0x60];
+// Sprite viewer pixels are this big.
+const SPRITE_SCALE: u32 = 3;
+// Octo's colours for the second XO-CHIP plane and both planes together.
+const SECOND_PLANE: [u8; 3] = [0xFF, 0x66, 0x00];
+const BOTH_PLANES: [u8; 3] = [0x66, 0x22, 0x00];
+
+// One of the panels right of the game.
+struct Panel {
+    lines: Vec<Line>,
+    columns: usize,
+    // A grid of these under the lines, in the sprite viewer.
+    sprites: Vec<(Sprite, bool)>,
+    format: Format,
+}
+
+impl Panel {
+    fn new(lines: Vec<Line>, columns: usize) -> Self {
+        Self {
+            lines,
+            columns,
+            sprites: Vec::new(),
+            format: Format::default(),
+        }
+    }
+
+    fn height(&self) -> u32 {
+        let (columns, _) = overlay::sprite_grid(self.format);
+        let rows = self.sprites.len().div_ceil(columns) as u32;
+        self.lines.len() as u32 * CELL_HEIGHT + rows * tile_height(self.format) + 2 * MARGIN
+    }
+}
+
 struct SquareWave {
     phase_inc: f32,
     phase: f32,
@@ -35,18 +88,45 @@ pub struct Video {
     sdl_context: Sdl,
     scale_factor: usize,
     audio: AudioDevice<SquareWave>,
+    palette: Palette,
+    keymap: Keymap,
+    hotkeys: Hotkeys,
+    title: String,
+    // Shown in the title after it.
+    title_status: String,
+    // The debug panel right of the game, toggled with F1.
+    overlay: bool,
+    // Where the memory view starts, while it's open. F2 toggles it.
+    memory_view: Option<u16>,
+    // The byte being typed into in the memory view, and its first digit.
+    editing: Option<(u16, Option<u8>)>,
+    // Where the sprite viewer starts and what it decodes, while it's open.
+    // F3 toggles it.
+    sprite_view: Option<(u16, Format)>,
+    // The last sprite export, or why it failed.
+    status: String,
+    ram_len: usize,
+    shown_sprites: Vec<Sprite>,
+    mouse_x: i32,
+    // Where the sprite viewer was last drawn, for the mouse wheel.
+    sprites_left: i32,
+    // Grows to fit the panels but never shrinks, so the window doesn't jump.
+    panel_height: u32,
+    // Where the panels' clickable spans were last drawn.
+    clicks: Vec<(Rect, Click)>,
 }
 
 impl Video {
-    pub fn new(scale_factor: usize) -> Self {
+    // The window fits `fb` at `scale_factor`.
+    pub fn new(scale_factor: usize, settings: &Settings, fb: &FrameBuffer) -> Self {
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
 
         let window = video_subsystem
             .window(
-                "chipeite",
-                64 * scale_factor as u32,
-                32 * scale_factor as u32,
+                &settings.title,
+                (fb.width() * scale_factor) as u32,
+                (fb.height() * scale_factor) as u32,
             )
             .position_centered()
             .build()
@@ -54,7 +134,7 @@ impl Video {
 
         let mut canvas = window.into_canvas().build().unwrap();
 
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.set_draw_color(color(settings.palette.background));
         canvas.clear();
         canvas.present();
 
@@ -83,76 +163,391 @@ impl Video {
             sdl_context,
             scale_factor,
             audio: device,
+            palette: settings.palette,
+            keymap: settings.keymap.clone(),
+            hotkeys: settings.hotkeys.clone(),
+            title: settings.title.clone(),
+            title_status: String::new(),
+            overlay: false,
+            memory_view: None,
+            editing: None,
+            sprite_view: None,
+            status: String::new(),
+            ram_len: 0,
+            shown_sprites: Vec::new(),
+            mouse_x: 0,
+            sprites_left: i32::MAX,
+            panel_height: 0,
+            clicks: Vec::new(),
         }
     }
 
-    pub fn draw(&mut self, fb: &FrameBuffer) -> Result<(), String> {
-        self.canvas.present();
-        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
-        let rect: Vec<Rect> = fb
-            .iter()
-            .filter_map(|pixel| {
-                if !pixel.state {
-                    None
-                } else {
-                    Some(Rect::new(
-                        (pixel.x * self.scale_factor) as i32,
-                        (pixel.y * self.scale_factor) as i32,
-                        self.scale_factor as u32,
-                        self.scale_factor as u32,
-                    ))
+    pub fn draw(&mut self, machine: &dyn Machine) -> Result<(), String> {
+        let (fb, colors) = (machine.framebuffer(), machine.colors());
+        let mut panels = Vec::new();
+        if let Some(chip8) = machine.chip8() {
+            self.ram_len = chip8.ram().len();
+            if self.overlay {
+                let lines = overlay::panel(chip8, machine.is_paused());
+                panels.push(Panel::new(lines, overlay::COLUMNS));
+            }
+            if let Some(start) = self.memory_view {
+                let ages = machine.write_ages();
+                let ages = ages.as_deref().map(Vec::as_slice);
+                let lines = overlay::memory(chip8, start, ages, self.editing);
+                panels.push(Panel::new(lines, overlay::MEMORY_COLUMNS));
+            }
+            if let Some((start, format)) = self.sprite_view {
+                let lines = overlay::sprite_controls(chip8, start, format, &self.status);
+                panels.push(Panel {
+                    sprites: overlay::sprites(chip8, start, format),
+                    format,
+                    ..Panel::new(lines, overlay::SPRITE_COLUMNS)
+                });
+            }
+        }
+        self.resize(fb, &panels)?;
+        let background = colors.map_or(self.palette.background, ColorMap::background);
+        self.canvas.set_draw_color(color(background));
+        self.canvas.clear();
+        self.canvas.set_draw_color(color(self.palette.foreground));
+        let rect = |x: usize, y: usize| {
+            Rect::new(
+                (x * self.scale_factor) as i32,
+                (y * self.scale_factor) as i32,
+                self.scale_factor as u32,
+                self.scale_factor as u32,
+            )
+        };
+        match colors {
+            Some(colors) => {
+                for pixel in fb.iter().filter(|pixel| pixel.state) {
+                    self.canvas
+                        .set_draw_color(color(colors.foreground(pixel.x, pixel.y)));
+                    self.canvas.fill_rect(rect(pixel.x, pixel.y))?;
                 }
-            })
+            }
+            None => {
+                let rects: Vec<Rect> = fb
+                    .iter()
+                    .filter(|pixel| pixel.state)
+                    .map(|pixel| rect(pixel.x, pixel.y))
+                    .collect();
+                self.canvas.fill_rects(&rects)?;
+            }
+        }
+        self.clicks.clear();
+        self.sprites_left = i32::MAX;
+        let mut x = (fb.width() * self.scale_factor) as i32;
+        for panel in &panels {
+            self.draw_panel(x, panel)?;
+            x += panel_width(panel.columns) as i32;
+        }
+        self.shown_sprites = panels
+            .into_iter()
+            .flat_map(|panel| panel.sprites)
+            .map(|(sprite, _)| sprite)
             .collect();
-        self.canvas.fill_rects(&rect)?;
         self.canvas.present();
         Ok(())
     }
 
-    pub fn wait_for_key(&self) -> Option<Keycode> {
-        let mut event_pump = self.sdl_context.event_pump().unwrap();
-        for event in event_pump.wait_iter() {
-            match event {
-                Event::Quit { .. } => std::process::exit(0),
-                Event::KeyDown {
-                    keycode: Some(keycode),
-                    ..
-                } => {
-                    if keycode as i32 >= Keycode::Num0 as i32
-                        && keycode as i32 <= Keycode::Num9 as i32
-                    {
-                        return Some(keycode);
-                    }
-                    if keycode as i32 >= Keycode::A as i32 && keycode as i32 <= Keycode::F as i32 {
-                        return Some(keycode);
-                    }
-                    continue;
+    // Fits the window to the game and the panels that are showing.
+    fn resize(&mut self, fb: &FrameBuffer, panels: &[Panel]) -> Result<(), String> {
+        let mut size = (
+            (fb.width() * self.scale_factor) as u32,
+            (fb.height() * self.scale_factor) as u32,
+        );
+        for panel in panels {
+            self.panel_height = self.panel_height.max(panel.height());
+            size = (
+                size.0 + panel_width(panel.columns),
+                size.1.max(self.panel_height),
+            );
+        }
+        if self.canvas.window().size() != size {
+            self.canvas
+                .window_mut()
+                .set_size(size.0, size.1)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn draw_panel(&mut self, left: i32, panel: &Panel) -> Result<(), String> {
+        let (_, height) = self.canvas.window().size();
+        self.canvas.set_draw_color(color(PANEL_BACKGROUND));
+        self.canvas
+            .fill_rect(Rect::new(left, 0, panel_width(panel.columns), height))?;
+        for (row, line) in panel.lines.iter().enumerate() {
+            let y = (MARGIN + row as u32 * CELL_HEIGHT) as i32;
+            let mut x = left + MARGIN as i32;
+            for span in line {
+                let width = span.text.chars().count() as u32 * CELL_WIDTH;
+                let cell = Rect::new(x, y, width, CELL_HEIGHT);
+                let (ink, paper) = match span.style {
+                    Style::Plain => (PANEL_TEXT, None),
+                    Style::Written => (PANEL_WRITTEN, None),
+                    Style::Highlight => (PANEL_BACKGROUND, Some(PANEL_HIGHLIGHT)),
+                    Style::Marked => (PANEL_BACKGROUND, Some(PANEL_MARKED)),
+                };
+                if let Some(paper) = paper {
+                    self.canvas.set_draw_color(color(paper));
+                    self.canvas.fill_rect(cell)?;
                 }
-                _ => continue,
+                if let Some(click) = span.click {
+                    self.clicks.push((cell, click));
+                }
+                self.canvas.set_draw_color(color(ink));
+                self.canvas
+                    .fill_rects(&text_rects(x, y + TEXT_SCALE as i32, &span.text))?;
+                x += width as i32;
             }
         }
-        None
+        if !panel.sprites.is_empty() {
+            self.sprites_left = left;
+            let top = MARGIN + panel.lines.len() as u32 * CELL_HEIGHT;
+            self.draw_sprites(left + MARGIN as i32, top as i32, panel)?;
+        }
+        Ok(())
+    }
+
+    // Each sprite under its address, which is highlighted if the last Dxyn
+    // drew from it.
+    fn draw_sprites(&mut self, left: i32, top: i32, panel: &Panel) -> Result<(), String> {
+        let (columns, _) = overlay::sprite_grid(panel.format);
+        let tile_width = (overlay::SPRITE_COLUMNS as u32 * CELL_WIDTH / columns as u32) as i32;
+        let tile_height = tile_height(panel.format) as i32;
+        let colors = self.sprite_colors();
+        for (n, (sprite, drawn)) in panel.sprites.iter().enumerate() {
+            let x = left + (n % columns) as i32 * tile_width;
+            let y = top + (n / columns) as i32 * tile_height;
+            let label = format!("{:03X}", sprite.addr);
+            let cell = Rect::new(x, y, label.len() as u32 * CELL_WIDTH, CELL_HEIGHT);
+            let ink = if *drawn {
+                self.canvas.set_draw_color(color(PANEL_HIGHLIGHT));
+                self.canvas.fill_rect(cell)?;
+                PANEL_BACKGROUND
+            } else {
+                PANEL_TEXT
+            };
+            self.canvas.set_draw_color(color(ink));
+            self.canvas
+                .fill_rects(&text_rects(x, y + TEXT_SCALE as i32, &label))?;
+
+            let (width, height) = (sprite.format.width(), sprite.format.height());
+            let y = y + CELL_HEIGHT as i32;
+            let area = Rect::new(
+                x,
+                y,
+                width as u32 * SPRITE_SCALE,
+                height as u32 * SPRITE_SCALE,
+            );
+            self.clicks.push((area, Click::Export(sprite.addr)));
+            for py in 0..height {
+                for px in 0..width {
+                    self.canvas
+                        .set_draw_color(color(colors[sprite.pixel(px, py) as usize]));
+                    self.canvas.fill_rect(Rect::new(
+                        x + (px as u32 * SPRITE_SCALE) as i32,
+                        y + (py as u32 * SPRITE_SCALE) as i32,
+                        SPRITE_SCALE,
+                        SPRITE_SCALE,
+                    ))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Sprites are drawn and exported in the game's colours.
+    fn sprite_colors(&self) -> [[u8; 3]; 4] {
+        [
+            self.palette.background,
+            self.palette.foreground,
+            SECOND_PLANE,
+            BOTH_PLANES,
+        ]
+    }
+
+    // Saves the sprite at `addr` next to where chipeite was started, e.g.
+    // `sprite-2A0-8x5.png`.
+    fn export(&mut self, addr: u16) {
+        let Some((_, format)) = self.sprite_view else {
+            return;
+        };
+        let Some(sprite) = self.shown_sprites.iter().find(|sprite| sprite.addr == addr) else {
+            return;
+        };
+        let path = format!("sprite-{addr:03X}-{format}.png");
+        let saved = sprite
+            .png(&self.sprite_colors())
+            .map_err(anyhow::Error::from)
+            .and_then(|png| Ok(std::fs::write(&path, png)?));
+        self.status = match saved {
+            Ok(()) => format!("saved {path}"),
+            Err(e) => format!("{e}"),
+        };
     }
 
-    pub fn get_keys<F>(&self, f: F)
+    // Feeds key changes to `f`, false once the window was closed.
+    pub fn get_keys<F>(&mut self, mut f: F) -> bool
     where
-        F: FnMut((Keycode, bool)),
+        F: FnMut((Input, bool)),
     {
         let mut event_pump = self.sdl_context.event_pump().unwrap();
-        event_pump
-            .poll_iter()
-            .filter_map(|event| match event {
+        for event in event_pump.poll_iter() {
+            let (keycode, pressed) = match event {
+                Event::Quit { .. } => return false,
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    let click = self
+                        .clicks
+                        .iter()
+                        .find(|(rect, _)| rect.contains_point((x, y)))
+                        .map(|&(_, click)| click);
+                    self.editing = None;
+                    match click {
+                        Some(Click::Command(command)) => f((Input::Command(command), true)),
+                        Some(Click::Edit(addr)) => self.editing = Some((addr, None)),
+                        Some(Click::Show(addr)) => {
+                            self.memory_view = Some(overlay::show(addr, self.ram_len));
+                        }
+                        Some(Click::SpriteFormat(format)) => {
+                            if let Some((_, current)) = &mut self.sprite_view {
+                                *current = format;
+                            }
+                        }
+                        Some(Click::MoveSprites(bytes)) => self.move_sprites(bytes),
+                        Some(Click::ShowSprites(addr)) => {
+                            if let Some((start, _)) = &mut self.sprite_view {
+                                *start = addr;
+                            }
+                        }
+                        Some(Click::Export(addr)) => self.export(addr),
+                        None => {}
+                    }
+                    continue;
+                }
+                Event::MouseMotion { x, .. } => {
+                    self.mouse_x = x;
+                    continue;
+                }
+                Event::MouseWheel { y, .. } => {
+                    match (self.sprite_view, self.memory_view) {
+                        (Some((_, format)), _) if self.mouse_x >= self.sprites_left => {
+                            let (columns, _) = overlay::sprite_grid(format);
+                            self.move_sprites(-(y as i64) * (columns * format.bytes()) as i64);
+                        }
+                        (_, Some(start)) => {
+                            let rows = -2 * y as i64;
+                            self.memory_view = Some(overlay::scroll(start, rows, self.ram_len));
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    repeat: false,
+                    ..
+                } => {
+                    self.overlay = !self.overlay;
+                    continue;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    repeat: false,
+                    ..
+                } => {
+                    self.editing = None;
+                    self.memory_view = match self.memory_view {
+                        Some(_) => None,
+                        None => Some(overlay::show(PROGRAM_START, self.ram_len)),
+                    };
+                    continue;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    repeat: false,
+                    ..
+                } => {
+                    self.sprite_view = match self.sprite_view {
+                        Some(_) => None,
+                        None => Some((PROGRAM_START, Format::default())),
+                    };
+                    continue;
+                }
+                // While a byte is being edited, typing goes to it rather than
+                // the game.
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if self.editing.is_some() => {
+                    self.edit(keycode, &mut f);
+                    continue;
+                }
                 Event::KeyUp {
                     keycode: Some(keycode),
                     ..
-                } => Some((keycode, false)),
+                } => (keycode, false),
                 Event::KeyDown {
                     keycode: Some(keycode),
+                    repeat,
                     ..
-                } => Some((keycode, true)),
-                _ => None,
-            })
-            .for_each(f)
+                } => {
+                    if repeat && self.hotkeys.get(&hotkey_name(keycode)).is_some() {
+                        continue;
+                    }
+                    (keycode, true)
+                }
+                _ => continue,
+            };
+            if let Some(command) = self.hotkeys.get(&hotkey_name(keycode)) {
+                f((Input::Command(command), pressed));
+                continue;
+            }
+            let input = key_from_keycode(keycode)
+                .or_else(|| action_from_keycode(keycode).and_then(|a| self.keymap.get(a)))
+                .map(Input::Key)
+                .or_else(|| second_key_from_keycode(keycode).map(Input::SecondKey));
+            if let Some(input) = input {
+                f((input, pressed));
+            }
+        }
+        true
+    }
+
+    fn move_sprites(&mut self, bytes: i64) {
+        if let Some((start, _)) = &mut self.sprite_view {
+            *start = overlay::move_sprites(*start, bytes, self.ram_len);
+        }
+    }
+
+    // Two hex digits make a byte, which is written and the next one edited.
+    fn edit<F: FnMut((Input, bool))>(&mut self, keycode: Keycode, f: &mut F) {
+        let Some((addr, first)) = self.editing else {
+            return;
+        };
+        if keycode == Keycode::Escape {
+            self.editing = None;
+            return;
+        }
+        let Some(digit) = key_from_keycode(keycode).map(|key| key as u8) else {
+            return;
+        };
+        self.editing = match first {
+            None => Some((addr, Some(digit))),
+            Some(high) => {
+                let value = high << 4 | digit;
+                f((Input::Command(Command::Poke { addr, value }), true));
+                Some((addr + 1, None)).filter(|&(next, _)| (next as usize) < self.ram_len)
+            }
+        };
     }
 
     pub fn start_beep(&self) {
@@ -163,3 +558,131 @@ impl Video {
         self.audio.pause();
     }
 }
+
+impl Frontend for Video {
+    fn poll_keys<F: FnMut(Input, bool)>(&mut self, mut f: F) -> anyhow::Result<bool> {
+        Ok(self.get_keys(|(input, pressed)| f(input, pressed)))
+    }
+
+    fn draw(&mut self, machine: &dyn Machine) -> anyhow::Result<()> {
+        Video::draw(self, machine).map_err(|e| anyhow!(e))
+    }
+
+    fn beep(&mut self, on: bool) {
+        if on {
+            self.start_beep();
+        } else {
+            self.stop_beep();
+        }
+    }
+
+    fn set_status(&mut self, status: &str) {
+        if status != self.title_status {
+            self.title_status = status.to_owned();
+            let title = format!("{} [{status}]", self.title);
+            let _ = self.canvas.window_mut().set_title(&title);
+        }
+    }
+}
+
+fn color([r, g, b]: [u8; 3]) -> Color {
+    Color::RGB(r, g, b)
+}
+
+fn panel_width(columns: usize) -> u32 {
+    columns as u32 * CELL_WIDTH + 2 * MARGIN
+}
+
+// A sprite in the sprite viewer with its address above and a gap below.
+fn tile_height(format: Format) -> u32 {
+    CELL_HEIGHT + format.height() as u32 * SPRITE_SCALE + MARGIN
+}
+
+// The font pixels of `text`, its top left at `x`, `y`.
+fn text_rects(x: i32, y: i32, text: &str) -> Vec<Rect> {
+    let mut rects = Vec::new();
+    for (n, c) in text.chars().enumerate() {
+        let left = x + (n as u32 * CELL_WIDTH) as i32;
+        for (row, bits) in font::glyph(c).into_iter().enumerate() {
+            for column in 0..font::WIDTH {
+                if bits >> (font::WIDTH - 1 - column) & 1 == 1 {
+                    rects.push(Rect::new(
+                        left + (column as u32 * TEXT_SCALE) as i32,
+                        y + (row as u32 * TEXT_SCALE) as i32,
+                        TEXT_SCALE,
+                        TEXT_SCALE,
+                    ));
+                }
+            }
+        }
+    }
+    rects
+}
+
+// SDL's name for the key, e.g. "f5" or "tab", as hotkeys are bound.
+fn hotkey_name(keycode: Keycode) -> String {
+    keycode.name().to_lowercase()
+}
+
+fn action_from_keycode(keycode: Keycode) -> Option<&'static str> {
+    match keycode {
+        Keycode::Up => Some("up"),
+        Keycode::Down => Some("down"),
+        Keycode::Left => Some("left"),
+        Keycode::Right => Some("right"),
+        Keycode::Space => Some("a"),
+        Keycode::Return => Some("b"),
+        Keycode::I => Some("player2Up"),
+        Keycode::K => Some("player2Down"),
+        Keycode::J => Some("player2Left"),
+        Keycode::L => Some("player2Right"),
+        Keycode::U => Some("player2A"),
+        Keycode::O => Some("player2B"),
+        _ => None,
+    }
+}
+
+fn key_from_keycode(keycode: Keycode) -> Option<Key> {
+    match keycode {
+        Keycode::Num0 => Some(Key::Zero),
+        Keycode::Num1 => Some(Key::One),
+        Keycode::Num2 => Some(Key::Two),
+        Keycode::Num3 => Some(Key::Three),
+        Keycode::Num4 => Some(Key::Four),
+        Keycode::Num5 => Some(Key::Five),
+        Keycode::Num6 => Some(Key::Six),
+        Keycode::Num7 => Some(Key::Seven),
+        Keycode::Num8 => Some(Key::Eight),
+        Keycode::Num9 => Some(Key::Nine),
+        Keycode::A => Some(Key::A),
+        Keycode::B => Some(Key::B),
+        Keycode::C => Some(Key::C),
+        Keycode::D => Some(Key::D),
+        Keycode::E => Some(Key::E),
+        Keycode::F => Some(Key::F),
+        _ => None,
+    }
+}
+
+// The numeric keypad stands in for CHIP-8X's second keypad.
+fn second_key_from_keycode(keycode: Keycode) -> Option<Key> {
+    match keycode {
+        Keycode::Kp0 => Some(Key::Zero),
+        Keycode::Kp1 => Some(Key::One),
+        Keycode::Kp2 => Some(Key::Two),
+        Keycode::Kp3 => Some(Key::Three),
+        Keycode::Kp4 => Some(Key::Four),
+        Keycode::Kp5 => Some(Key::Five),
+        Keycode::Kp6 => Some(Key::Six),
+        Keycode::Kp7 => Some(Key::Seven),
+        Keycode::Kp8 => Some(Key::Eight),
+        Keycode::Kp9 => Some(Key::Nine),
+        Keycode::KpDivide => Some(Key::A),
+        Keycode::KpMultiply => Some(Key::B),
+        Keycode::KpMinus => Some(Key::C),
+        Keycode::KpPlus => Some(Key::D),
+        Keycode::KpEnter => Some(Key::E),
+        Keycode::KpPeriod => Some(Key::F),
+        _ => None,
+    }
+}
@@ -0,0 +1,286 @@
+pub mod cdp1802;
+pub mod cdp1861;
+
+use crate::memory::{
+    error::LoadError,
+    framebuffer::FrameBuffer,
+    key_state::{Key, KeyState},
+    FOUR_K, PROGRAM_START,
+};
+
+use self::{
+    cdp1802::{Bus, Cdp1802},
+    cdp1861::Cdp1861,
+};
+
+const ROM_START: u16 = 0x8000;
+const ROM_SIZE: usize = 0x200;
+
+// Stands in for the VIP monitor ROM when none is given. It only does what the
+// CHIP-8 interpreter needs from it: the hand-off to 0000 with the top page of
+// ram in R1.1, and the interrupt routine at 8146 that feeds the 1861 and
+// counts down the timers in R8.1 and R8.0 (tone, on Q).
+#[rustfmt::skip]
+const BUILTIN_MONITOR: &[(u16, &[u8])] = &[
+    (0x000, &[
+        0xC0, 0x80, 0x03, // LBR 8003, leaves the reset overlay
+        0xF8, 0x0F,       // LDI 0F
+        0xB1,             // PHI 1
+        0xC0, 0x00, 0x00, // LBR 0000
+    ]),
+    (0x144, &[
+        0x72,             // 8144 LDXA
+        0x70,             //      RET
+        0x22,             // 8146 DEC 2
+        0x78,             //      SAV
+        0x22,             //      DEC 2
+        0x52,             //      STR 2
+        0xC4, 0xC4, 0xC4, //      NOP, NOP, NOP
+        0xE2,             //      SEX 2, until the first DMA lines up
+        0xF8, 0x00,       //      LDI 00
+        0xA0,             //      PLO 0
+        0x9B,             //      GHI B
+        0xB0,             //      PHI 0
+        // Each pass shows one row on four lines, three instructions before
+        // every DMA. DEC 0 undoes the carry into R0.1 after the last row.
+        0x80,             // 8153 GLO 0
+        0xE2, 0xE2,       //      SEX 2, SEX 2
+        0x20, 0xA0,       //      DEC 0, PLO 0
+        0xE2,             //      SEX 2
+        0x20, 0xA0,       //      DEC 0, PLO 0
+        0xE2,             //      SEX 2
+        0x20, 0xA0,       //      DEC 0, PLO 0
+        0x3C, 0x53,       //      BN1 8153
+        0x98,             //      GHI 8
+        0x32, 0x66,       //      BZ 8166
+        0xFF, 0x01,       //      SMI 01
+        0xB8,             //      PHI 8
+        0x88,             // 8166 GLO 8
+        0x32, 0x6E,       //      BZ 816E
+        0xFF, 0x01,       //      SMI 01
+        0xA8,             //      PLO 8
+        0x7B,             //      SEQ
+        0x38,             //      SKP
+        0x7A,             // 816E REQ
+        0x30, 0x44,       //      BR 8144
+    ]),
+];
+
+struct VipBus {
+    ram: [u8; FOUR_K],
+    rom: [u8; ROM_SIZE],
+    // After a reset the ROM also shows up at 0000, until the first access
+    // with A15 set.
+    overlay: bool,
+    video: Cdp1861,
+    keys: KeyState,
+    key_latch: u8,
+}
+
+impl Bus for VipBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        if addr >= ROM_START {
+            self.overlay = false;
+        }
+        if addr >= ROM_START || self.overlay {
+            self.rom[addr as usize % ROM_SIZE]
+        } else {
+            self.ram[addr as usize % FOUR_K]
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if addr < ROM_START {
+            self.ram[addr as usize % FOUR_K] = value;
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => self.video.disable(),
+            2 => self.key_latch = value & 0xF,
+            _ => {}
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.video.enable();
+        }
+        0
+    }
+
+    fn flag(&self, ef: u8) -> bool {
+        match ef {
+            1 => self.video.ef1(),
+            3 => self.keys.is_set(Key::from(self.key_latch)),
+            _ => false,
+        }
+    }
+}
+
+// A 4K COSMAC VIP running the original CHIP-8 interpreter from 0000 and the
+// program from 0200.
+pub struct Vip {
+    cpu: Cdp1802,
+    bus: VipBus,
+    framebuffer: FrameBuffer,
+}
+
+impl Vip {
+    // `monitor` is the VIP's 512 byte ROM, a built-in stand-in when None.
+    pub fn new(
+        interpreter: &[u8],
+        monitor: Option<&[u8]>,
+        program: &[u8],
+    ) -> Result<Self, LoadError> {
+        let mut bus = VipBus {
+            ram: [0; FOUR_K],
+            rom: [0; ROM_SIZE],
+            overlay: true,
+            video: Cdp1861::new(),
+            keys: KeyState::default(),
+            key_latch: 0,
+        };
+        copy(&mut bus.ram[..PROGRAM_START as usize], 0, interpreter)?;
+        copy(&mut bus.ram, PROGRAM_START as usize, program)?;
+        match monitor {
+            Some(monitor) => copy(&mut bus.rom, 0, monitor)?,
+            None => {
+                for &(offset, code) in BUILTIN_MONITOR {
+                    copy(&mut bus.rom, offset as usize, code)?;
+                }
+            }
+        }
+        Ok(Self {
+            cpu: Cdp1802::new(),
+            bus,
+            framebuffer: FrameBuffer::default(),
+        })
+    }
+
+    pub fn cpu(&self) -> &Cdp1802 {
+        &self.cpu
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        &self.bus.ram
+    }
+
+    // Runs until the 1861 finished a frame, 3668 machine cycles.
+    pub fn run_frame(&mut self) {
+        loop {
+            let cycles = if self.bus.video.dma_requested() {
+                let value = self.cpu.dma_out(&mut self.bus);
+                self.bus.video.dma(value);
+                1
+            } else if self.bus.video.interrupt() && self.cpu.interrupt_enabled() {
+                self.cpu.interrupt();
+                1
+            } else {
+                self.cpu.step(&mut self.bus)
+            };
+            if self.bus.video.tick(cycles) {
+                self.framebuffer = FrameBuffer::from_rows(&self.bus.video.rows());
+                return;
+            }
+        }
+    }
+
+    pub fn press_key(&mut self, key: Key) {
+        self.bus.keys.set(key);
+    }
+
+    pub fn release_key(&mut self, key: Key) {
+        self.bus.keys.unset(key);
+    }
+
+    pub fn framebuffer(&self) -> &FrameBuffer {
+        &self.framebuffer
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        self.cpu.q()
+    }
+}
+
+fn copy(dest: &mut [u8], offset: usize, bytes: &[u8]) -> Result<(), LoadError> {
+    let max = dest.len() - offset;
+    dest.get_mut(offset..offset + bytes.len())
+        .ok_or(LoadError::TooLarge {
+            len: bytes.len(),
+            max,
+        })?
+        .copy_from_slice(bytes);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Sets things up the way the real interpreter does, fills the timers and
+    // turns the display on. R0 belongs to the DMA, so it runs on R3.
+    #[rustfmt::skip]
+    const INTERPRETER: &[u8] = &[
+        0x90, 0xB3,       // GHI 0, PHI 3
+        0xF8, 0x06, 0xA3, // LDI 06, PLO 3
+        0xD3,             // SEP 3
+        0x91, 0xBB,       // GHI 1, PHI B
+        0xFF, 0x01, 0xB2, // SMI 01, PHI 2
+        0xF8, 0xCF, 0xA2, // LDI CF, PLO 2
+        0xF8, 0x81, 0xB1, // LDI 81, PHI 1
+        0xF8, 0x46, 0xA1, // LDI 46, PLO 1
+        0xF8, 0x05, 0xB8, // LDI 05, PHI 8
+        0xF8, 0x03, 0xA8, // LDI 03, PLO 8
+        0xE2, 0x69,       // SEX 2, INP 1
+        0x30, 0x1C,       // BR 001C
+    ];
+
+    fn vip() -> Vip {
+        let mut program = vec![0; 0xE00];
+        program[0xD00] = 0xF0;
+        program[0xDFF] = 0x01;
+        Vip::new(INTERPRETER, None, &program).unwrap()
+    }
+
+    #[test]
+    fn display_memory_is_shown_through_dma() {
+        let mut vip = vip();
+        vip.run_frame();
+        vip.run_frame();
+        let lit: Vec<_> = vip
+            .framebuffer()
+            .iter()
+            .filter(|p| p.state)
+            .map(|p| (p.x, p.y))
+            .collect();
+        assert_eq!(lit, [(0, 0), (1, 0), (2, 0), (3, 0), (63, 31)]);
+        assert_eq!(vip.cpu().r(2), 0x0ECF);
+    }
+
+    #[test]
+    fn timers_count_down_once_a_frame() {
+        let mut vip = vip();
+        vip.run_frame();
+        vip.run_frame();
+        assert_eq!(vip.cpu().r(8), 0x0301);
+        assert!(vip.is_beeping());
+        for _ in 0..5 {
+            vip.run_frame();
+        }
+        assert_eq!(vip.cpu().r(8), 0);
+        assert!(!vip.is_beeping());
+    }
+
+    #[test]
+    fn oversize_interpreters_are_rejected() {
+        assert_eq!(
+            Vip::new(&[0; 0x201], None, &[]).err(),
+            Some(LoadError::TooLarge {
+                len: 0x201,
+                max: 0x200
+            })
+        );
+    }
+}
@@ -0,0 +1,342 @@
+// RCA CDP1802, the CPU of the COSMAC VIP.
+
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, value: u8);
+    // OUT 1-7, `value` is what was on the data bus.
+    fn output(&mut self, port: u8, value: u8);
+    // INP 1-7
+    fn input(&mut self, port: u8) -> u8;
+    // External flag inputs EF1-4.
+    fn flag(&self, ef: u8) -> bool;
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Cdp1802 {
+    r: [u16; 16],
+    d: u8,
+    df: bool,
+    p: u8,
+    x: u8,
+    t: u8,
+    q: bool,
+    ie: bool,
+    idle: bool,
+}
+
+impl Cdp1802 {
+    pub fn new() -> Self {
+        Self {
+            ie: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn r(&self, n: u8) -> u16 {
+        self.r[n as usize]
+    }
+
+    pub fn d(&self) -> u8 {
+        self.d
+    }
+
+    pub fn df(&self) -> bool {
+        self.df
+    }
+
+    pub fn q(&self) -> bool {
+        self.q
+    }
+
+    pub fn interrupt_enabled(&self) -> bool {
+        self.ie
+    }
+
+    // Takes one machine cycle.
+    pub fn interrupt(&mut self) {
+        self.t = self.x << 4 | self.p;
+        self.p = 1;
+        self.x = 2;
+        self.ie = false;
+        self.idle = false;
+    }
+
+    // DMA out through R0, takes one machine cycle.
+    pub fn dma_out(&mut self, bus: &mut impl Bus) -> u8 {
+        self.idle = false;
+        let value = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        value
+    }
+
+    // Executes one instruction and returns the machine cycles it took.
+    pub fn step(&mut self, bus: &mut impl Bus) -> u32 {
+        if self.idle {
+            return 1;
+        }
+        let opcode = self.immediate(bus);
+        let (i, n) = (opcode >> 4, opcode & 0xF);
+        let rn = n as usize;
+        match i {
+            0x0 if n == 0 => self.idle = true,
+            0x0 => self.d = bus.read(self.r[rn]),
+            0x1 => self.r[rn] = self.r[rn].wrapping_add(1),
+            0x2 => self.r[rn] = self.r[rn].wrapping_sub(1),
+            0x3 => self.short_branch(n, bus),
+            0x4 => {
+                self.d = bus.read(self.r[rn]);
+                self.r[rn] = self.r[rn].wrapping_add(1);
+            }
+            0x5 => bus.write(self.r[rn], self.d),
+            0x6 => self.input_output(n, bus),
+            0x7 => self.seventh(n, bus),
+            0x8 => self.d = self.r[rn] as u8,
+            0x9 => self.d = (self.r[rn] >> 8) as u8,
+            0xA => self.r[rn] = self.r[rn] & 0xFF00 | self.d as u16,
+            0xB => self.r[rn] = self.r[rn] & 0x00FF | (self.d as u16) << 8,
+            0xC => {
+                self.long_branch(n, bus);
+                return 3;
+            }
+            0xD => self.p = n,
+            0xE => self.x = n,
+            _ => self.arithmetic(n, bus),
+        }
+        2
+    }
+
+    fn immediate(&mut self, bus: &mut impl Bus) -> u8 {
+        let p = self.p as usize;
+        let value = bus.read(self.r[p]);
+        self.r[p] = self.r[p].wrapping_add(1);
+        value
+    }
+
+    fn condition(&self, n: u8, bus: &impl Bus) -> bool {
+        let taken = match n & 0x7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            ef => bus.flag(ef - 3),
+        };
+        taken != (n & 0x8 != 0)
+    }
+
+    fn short_branch(&mut self, n: u8, bus: &mut impl Bus) {
+        let p = self.p as usize;
+        if self.condition(n, bus) {
+            let target = bus.read(self.r[p]);
+            self.r[p] = self.r[p] & 0xFF00 | target as u16;
+        } else {
+            self.r[p] = self.r[p].wrapping_add(1);
+        }
+    }
+
+    fn long_branch(&mut self, n: u8, bus: &mut impl Bus) {
+        let p = self.p as usize;
+        // C4 is NOP, C8 the unconditional long skip.
+        let (skip, taken) = match n {
+            0x4 => return,
+            0x8 => (true, true),
+            0xC => (true, self.ie),
+            _ if n & 0x4 != 0 => (true, !self.condition(n & 0xB, bus)),
+            _ => (false, self.condition(n, bus)),
+        };
+        match (skip, taken) {
+            (true, true) => self.r[p] = self.r[p].wrapping_add(2),
+            (true, false) => {}
+            (false, true) => {
+                let hi = bus.read(self.r[p]);
+                let lo = bus.read(self.r[p].wrapping_add(1));
+                self.r[p] = u16::from_be_bytes([hi, lo]);
+            }
+            (false, false) => self.r[p] = self.r[p].wrapping_add(2),
+        }
+    }
+
+    fn input_output(&mut self, n: u8, bus: &mut impl Bus) {
+        let x = self.x as usize;
+        match n {
+            0x0 => self.r[x] = self.r[x].wrapping_add(1),
+            0x1..=0x7 => {
+                let value = bus.read(self.r[x]);
+                bus.output(n, value);
+                self.r[x] = self.r[x].wrapping_add(1);
+            }
+            // 68 is undefined on the 1802.
+            0x8 => {}
+            _ => {
+                let value = bus.input(n - 8);
+                bus.write(self.r[x], value);
+                self.d = value;
+            }
+        }
+    }
+
+    fn seventh(&mut self, n: u8, bus: &mut impl Bus) {
+        let x = self.x as usize;
+        match n {
+            0x0 | 0x1 => {
+                let value = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+                self.x = value >> 4;
+                self.p = value & 0xF;
+                self.ie = n == 0;
+            }
+            0x2 => {
+                self.d = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+            }
+            0x3 => {
+                bus.write(self.r[x], self.d);
+                self.r[x] = self.r[x].wrapping_sub(1);
+            }
+            0x6 => {
+                let carry = self.d & 1 != 0;
+                self.d = self.d >> 1 | (self.df as u8) << 7;
+                self.df = carry;
+            }
+            0x8 => bus.write(self.r[x], self.t),
+            0x9 => {
+                self.t = self.x << 4 | self.p;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0xA => self.q = false,
+            0xB => self.q = true,
+            0xE => {
+                let carry = self.d & 0x80 != 0;
+                self.d = self.d << 1 | self.df as u8;
+                self.df = carry;
+            }
+            // 74/75/77 with borrow or carry from M(R(X)), 7C/7D/7F immediate.
+            _ => {
+                let operand = if n & 0x8 != 0 {
+                    self.immediate(bus)
+                } else {
+                    bus.read(self.r[x])
+                };
+                self.alu(n & 0x3, operand, self.df);
+            }
+        }
+    }
+
+    fn arithmetic(&mut self, n: u8, bus: &mut impl Bus) {
+        // SHR and SHL take no operand.
+        if n & 0x7 == 0x6 {
+            if n & 0x8 == 0 {
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+            } else {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            }
+            return;
+        }
+        let operand = if n & 0x8 != 0 {
+            self.immediate(bus)
+        } else {
+            bus.read(self.r[self.x as usize])
+        };
+        match n & 0x7 {
+            0x0 => self.d = operand,
+            0x1 => self.d |= operand,
+            0x2 => self.d &= operand,
+            0x3 => self.d ^= operand,
+            op => self.alu(op & 0x3, operand, op != 0x4),
+        }
+    }
+
+    // 0 adds, 1 is M - D and 3 is D - M. DF is the carry in, for
+    // subtractions it's set when nothing was borrowed.
+    fn alu(&mut self, op: u8, operand: u8, df: bool) {
+        let (a, b) = match op {
+            0 => {
+                let sum = self.d as u16 + operand as u16 + df as u16;
+                self.d = sum as u8;
+                self.df = sum > 0xFF;
+                return;
+            }
+            1 => (operand, self.d),
+            _ => (self.d, operand),
+        };
+        let difference = a as i16 - b as i16 - !df as i16;
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Ram([u8; 0x100]);
+
+    impl Bus for Ram {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.0[addr as usize]
+        }
+        fn write(&mut self, addr: u16, value: u8) {
+            self.0[addr as usize] = value;
+        }
+        fn output(&mut self, _: u8, _: u8) {}
+        fn input(&mut self, _: u8) -> u8 {
+            0
+        }
+        fn flag(&self, ef: u8) -> bool {
+            ef == 2
+        }
+    }
+
+    fn run(code: &[u8], steps: usize) -> (Cdp1802, Ram) {
+        let mut ram = Ram([0; 0x100]);
+        ram.0[..code.len()].copy_from_slice(code);
+        let mut cpu = Cdp1802::new();
+        for _ in 0..steps {
+            cpu.step(&mut ram);
+        }
+        (cpu, ram)
+    }
+
+    #[test]
+    fn subtractions_set_df_when_nothing_was_borrowed() {
+        // LDI 05, SMI 06
+        let (cpu, _) = run(&[0xF8, 0x05, 0xFF, 0x06], 2);
+        assert_eq!((cpu.d(), cpu.df()), (0xFF, false));
+        // LDI 05, SDI 06
+        let (cpu, _) = run(&[0xF8, 0x05, 0xFD, 0x06], 2);
+        assert_eq!((cpu.d(), cpu.df()), (0x01, true));
+        // LDI FF, ADI 01, ADCI 00
+        let (cpu, _) = run(&[0xF8, 0xFF, 0xFC, 0x01, 0x7C, 0x00], 3);
+        assert_eq!((cpu.d(), cpu.df()), (0x01, false));
+    }
+
+    #[test]
+    fn branches_and_skips() {
+        // B2 06 is taken on EF2, SKP jumps over 7B, LBNZ 000A is not.
+        let code = [
+            0x35, 0x06, 0x00, 0x00, 0x00, 0x00, 0x38, 0x7B, 0xCA, 0x00, 0x0A,
+        ];
+        let (cpu, _) = run(&code, 3);
+        assert_eq!(cpu.r(0), 0x0B);
+        assert!(!cpu.q());
+        // LSZ skips the SEQ
+        let (cpu, _) = run(&[0xCE, 0x7B, 0x00, 0x7B], 2);
+        assert_eq!(cpu.r(0), 0x04);
+        assert!(cpu.q());
+    }
+
+    #[test]
+    fn mark_and_ret_save_and_restore_x_and_p() {
+        // R2 = 0080, R3 = 0010, SEX 5, SEP 3; at 0010: MARK, SEX 2, INC 2, RET
+        let mut code = vec![0xF8, 0x80, 0xA2, 0xF8, 0x10, 0xA3, 0xE5, 0xD3];
+        code.resize(0x10, 0);
+        code.extend([0x79, 0xE2, 0x12, 0x70]);
+        let (cpu, ram) = run(&code, 10);
+        assert_eq!(ram.0[0x80], 0x53);
+        assert_eq!(cpu.r(2), 0x81);
+        assert_eq!(cpu.r(3), 0x14);
+    }
+}
@@ -0,0 +1,108 @@
+// RCA CDP1861 "Pixie" video chip. It counts machine cycles to know where the
+// beam is and pulls each displayed line out of ram through the 1802's DMA.
+
+pub const CYCLES_PER_LINE: u32 = 14;
+pub const LINES_PER_FRAME: u32 = 262;
+pub const CYCLES_PER_FRAME: u32 = CYCLES_PER_LINE * LINES_PER_FRAME;
+
+const FIRST_LINE: u32 = 64;
+const DISPLAY_LINES: u32 = 128;
+const INTERRUPT_LINE: u32 = FIRST_LINE - 2;
+// Cycles into a display line before the eight DMA cycles start.
+const DMA_START: u32 = 6;
+const BYTES_PER_LINE: u8 = 8;
+
+#[derive(Debug, Clone)]
+pub struct Cdp1861 {
+    enabled: bool,
+    cycle: u32,
+    next_dma_line: u32,
+    dma_line: u32,
+    dma_remaining: u8,
+    lines: [u64; DISPLAY_LINES as usize],
+}
+
+impl Default for Cdp1861 {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cycle: 0,
+            next_dma_line: FIRST_LINE,
+            dma_line: 0,
+            dma_remaining: 0,
+            lines: [0; DISPLAY_LINES as usize],
+        }
+    }
+}
+
+impl Cdp1861 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // INP 1
+    pub fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    // OUT 1
+    pub fn disable(&mut self) {
+        self.enabled = false;
+        self.lines.fill(0);
+    }
+
+    fn line(&self) -> u32 {
+        self.cycle / CYCLES_PER_LINE
+    }
+
+    pub fn interrupt(&self) -> bool {
+        self.enabled && (INTERRUPT_LINE..FIRST_LINE).contains(&self.line())
+    }
+
+    // EF1, up for the four lines before the display starts and ends.
+    pub fn ef1(&self) -> bool {
+        let last = FIRST_LINE + DISPLAY_LINES;
+        let line = self.line();
+        self.enabled
+            && ((FIRST_LINE - 4..FIRST_LINE).contains(&line) || (last - 4..last).contains(&line))
+    }
+
+    pub fn dma_requested(&mut self) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let line = self.line();
+        if self.dma_remaining == 0
+            && line == self.next_dma_line
+            && line < FIRST_LINE + DISPLAY_LINES
+            && self.cycle % CYCLES_PER_LINE >= DMA_START
+        {
+            self.dma_line = line - FIRST_LINE;
+            self.dma_remaining = BYTES_PER_LINE;
+            self.next_dma_line += 1;
+        }
+        self.dma_remaining > 0
+    }
+
+    pub fn dma(&mut self, value: u8) {
+        let line = &mut self.lines[self.dma_line as usize];
+        *line = *line << 8 | value as u64;
+        self.dma_remaining -= 1;
+    }
+
+    // True when a frame was completed.
+    pub fn tick(&mut self, cycles: u32) -> bool {
+        self.cycle += cycles;
+        if self.cycle < CYCLES_PER_FRAME {
+            return false;
+        }
+        self.cycle -= CYCLES_PER_FRAME;
+        self.next_dma_line = FIRST_LINE;
+        true
+    }
+
+    // The VIP shows every CHIP-8 row on four lines, the first one is taken.
+    pub fn rows(&self) -> Vec<u64> {
+        self.lines.iter().step_by(4).copied().collect()
+    }
+}
@@ -0,0 +1,224 @@
+// Runs ROMs headlessly and compares the final framebuffer against the ASCII
+// baselines in `tests/golden`. Set `CHIPEITE_BLESS=1` to (re)write them.
+//
+// The test suite ROMs are not redistributed here, so their tests are ignored.
+// Drop them in `tests/roms` (see the README there) and run
+// `cargo test --test conformance -- --ignored`. Their baselines say what a
+// correct interpreter shows, so they come from a reference emulator and are
+// never blessed from chipeite's own output.
+
+use std::{env, fs, path::PathBuf};
+
+use chipeite::{Chip8, Config, Key};
+
+const SEED: u64 = 0xC8;
+
+const SUITE_DIR: &str = "tests/roms/";
+
+struct Press {
+    frame: usize,
+    key: Key,
+    frames_held: usize,
+}
+
+struct Case {
+    rom: &'static str,
+    frames: usize,
+    presses: &'static [Press],
+}
+
+fn manifest_path(relative: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(relative)
+}
+
+fn run(rom: &[u8], case: &Case) -> String {
+    let mut chip8 = Chip8::new(
+        rom,
+        Config {
+            rng_seed: Some(SEED),
+            ..Config::default()
+        },
+    )
+    .unwrap();
+    for frame in 0..case.frames {
+        for press in case.presses {
+            if press.frame == frame {
+                chip8.press_key(press.key);
+            }
+            if press.frame + press.frames_held == frame {
+                chip8.release_key(press.key);
+            }
+        }
+        chip8.run_frame().unwrap();
+    }
+    chip8.framebuffer().to_string()
+}
+
+fn check(name: &str, case: Case) {
+    let rom_path = manifest_path(case.rom);
+    let rom = fs::read(&rom_path)
+        .unwrap_or_else(|_| panic!("{} not found, see tests/roms/README.md", rom_path.display()));
+    let actual = run(&rom, &case);
+    let golden_path = manifest_path(&format!("tests/golden/{name}.txt"));
+    let suite = case.rom.starts_with(SUITE_DIR);
+    if env::var_os("CHIPEITE_BLESS").is_some() && !suite {
+        fs::write(&golden_path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&golden_path).unwrap_or_else(|_| {
+        let how = if suite {
+            "it has to come from a reference emulator, see tests/roms/README.md"
+        } else {
+            "run with CHIPEITE_BLESS=1 to create it"
+        };
+        panic!("no baseline at {}, {how}", golden_path.display())
+    });
+    assert!(
+        expected == actual,
+        "{name} framebuffer differs from {}\nexpected:\n{expected}\nactual:\n{actual}",
+        golden_path.display()
+    );
+}
+
+#[test]
+fn pong() {
+    check(
+        "pong",
+        Case {
+            rom: "roms/pong.ch8",
+            frames: 300,
+            presses: &[Press {
+                frame: 60,
+                key: Key::One,
+                frames_held: 30,
+            }],
+        },
+    );
+}
+
+#[test]
+fn pong_1p() {
+    check(
+        "pong_1p",
+        Case {
+            rom: "roms/pong_1p.ch8",
+            frames: 300,
+            presses: &[Press {
+                frame: 60,
+                key: Key::Four,
+                frames_held: 30,
+            }],
+        },
+    );
+}
+
+#[test]
+fn russian_roulete() {
+    check(
+        "russian-roulete",
+        Case {
+            rom: "roms/russian-roulete.ch8",
+            frames: 300,
+            presses: &[Press {
+                frame: 60,
+                key: Key::F,
+                frames_held: 10,
+            }],
+        },
+    );
+}
+
+#[test]
+#[ignore = "needs tests/roms/1-chip8-logo.ch8"]
+fn chip8_logo() {
+    check(
+        "chip8-logo",
+        Case {
+            rom: "tests/roms/1-chip8-logo.ch8",
+            frames: 60,
+            presses: &[],
+        },
+    );
+}
+
+#[test]
+#[ignore = "needs tests/roms/2-ibm-logo.ch8"]
+fn ibm_logo() {
+    check(
+        "ibm-logo",
+        Case {
+            rom: "tests/roms/2-ibm-logo.ch8",
+            frames: 60,
+            presses: &[],
+        },
+    );
+}
+
+#[test]
+#[ignore = "needs tests/roms/3-corax+.ch8"]
+fn opcodes() {
+    check(
+        "corax+",
+        Case {
+            rom: "tests/roms/3-corax+.ch8",
+            frames: 120,
+            presses: &[],
+        },
+    );
+}
+
+#[test]
+#[ignore = "needs tests/roms/4-flags.ch8"]
+fn flags() {
+    check(
+        "flags",
+        Case {
+            rom: "tests/roms/4-flags.ch8",
+            frames: 120,
+            presses: &[],
+        },
+    );
+}
+
+#[test]
+#[ignore = "needs tests/roms/5-quirks.ch8"]
+fn quirks() {
+    check(
+        "quirks",
+        Case {
+            rom: "tests/roms/5-quirks.ch8",
+            frames: 600,
+            // picks "CHIP-8" in the platform menu
+            presses: &[Press {
+                frame: 30,
+                key: Key::One,
+                frames_held: 5,
+            }],
+        },
+    );
+}
+
+#[test]
+#[ignore = "needs tests/roms/6-keypad.ch8"]
+fn keypad() {
+    check(
+        "keypad",
+        Case {
+            rom: "tests/roms/6-keypad.ch8",
+            frames: 240,
+            // picks the Fx0A test and then answers it
+            presses: &[
+                Press {
+                    frame: 30,
+                    key: Key::Three,
+                    frames_held: 5,
+                },
+                Press {
+                    frame: 90,
+                    key: Key::A,
+                    frames_held: 5,
+                },
+            ],
+        },
+    );
+}